@@ -2,13 +2,36 @@ use std::path::PathBuf;
 
 use clap::Parser;
 
+use vrain_rs::RasterFormat;
+
 #[derive(Debug, Parser)]
 #[command(author = "vRain Project", version)]
 #[command(about = "Experimental Rust port of the vRain typesetting tool")]
 pub struct Cli {
     /// Book identifier (maps to books/<book>/)
-    #[arg(short = 'b', long = "book", value_name = "BOOK_ID")]
-    pub book_id: String,
+    #[arg(
+        short = 'b',
+        long = "book",
+        value_name = "BOOK_ID",
+        required_unless_present = "input"
+    )]
+    pub book_id: Option<String>,
+
+    /// 单章文本来源（`-` 为标准输入），代替 books/<book>/text；按 --from 编号
+    #[arg(long = "input", value_name = "PATH")]
+    pub input: Option<PathBuf>,
+
+    /// 覆盖 book.cfg 中的一项，如 --set row_num=20（可重复）
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub book_overrides: Vec<String>,
+
+    /// 覆盖画布配置中的一项，如 --canvas-set canvas_width=2480（可重复）
+    #[arg(long = "canvas-set", value_name = "KEY=VALUE")]
+    pub canvas_overrides: Vec<String>,
+
+    /// PDF 输出路径（默认：books/<book>/《书名》文本X至Y.pdf）
+    #[arg(short = 'o', long = "output", value_name = "PDF_PATH")]
+    pub output: Option<PathBuf>,
 
     /// 仅生成背景图并退出
     #[arg(long = "generate-bg")]
@@ -46,14 +69,42 @@ pub struct Cli {
     #[arg(long = "db-dir", value_name = "PATH", default_value = "db")]
     pub db_root: PathBuf,
 
-    /// Compress PDF via Ghostscript after generation (macOS only, matches -c)
+    /// Recompress embedded images at a lower JPEG quality (matches -c)
     #[arg(short = 'c', long = "compress")]
     pub compress: bool,
 
+    /// 同时将每页导出为图片（png 或 jpeg）
+    #[arg(long = "raster", value_name = "FORMAT")]
+    pub raster: Option<RasterFormat>,
+
+    /// 页面图片分辨率（DPI）
+    #[arg(long = "dpi", value_name = "DPI", default_value_t = 150.0)]
+    pub dpi: f32,
+
+    /// 页面图片输出目录（默认：与 PDF 同名的 _pages 目录）
+    #[arg(long = "raster-dir", value_name = "PATH")]
+    pub raster_dir: Option<PathBuf>,
+
+    /// 同时将每页导出为独立 SVG（内嵌子集字体）
+    #[arg(long = "svg")]
+    pub svg: bool,
+
+    /// SVG 输出目录（默认：与 PDF 同名的 _svg 目录）
+    #[arg(long = "svg-dir", value_name = "PATH")]
+    pub svg_dir: Option<PathBuf>,
+
     /// Verbose glyph logging (matches Perl -v)
     #[arg(short = 'v', long = "verbose")]
     pub verbose: bool,
 
+    /// 缺字报告（TSV）输出路径；未指定时缺字清单打印到终端
+    #[arg(long = "missing-report", value_name = "PATH")]
+    pub missing_report: Option<PathBuf>,
+
+    /// 有字以 □ 代替时报错退出，不生成 PDF
+    #[arg(long = "fail-on-missing")]
+    pub fail_on_missing: bool,
+
     /// Export the computed DocumentPlan as JSON for debugging
     #[arg(long = "debug-plan", value_name = "JSON_PATH")]
     pub debug_plan: Option<PathBuf>,
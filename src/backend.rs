@@ -0,0 +1,831 @@
+use crate::color::RgbColor;
+use crate::config::{BookConfig, CanvasConfig, LayoutMode};
+use crate::fonts::FontManager;
+use crate::frame::{build_frame, build_multirows_lines, center_strip};
+use crate::multirows::MultiRowsMode;
+use crate::numerals::NumeralMap;
+use crate::plan::{CoverPlan, DocumentPlan, GlyphSpec, LineSpec, PolygonSpec};
+use anyhow::{Context, Result};
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use resvg::{tiny_skia, usvg};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub struct RenderContext<'a> {
+    pub book: &'a BookConfig,
+    pub canvas: &'a CanvasConfig,
+    pub fonts: &'a FontManager,
+    pub numerals: &'a NumeralMap,
+    pub background: Option<&'a SourceImage>,
+    pub background_color: Option<RgbColor>,
+    pub cover_image: Option<&'a SourceImage>,
+    pub logo_image: Option<&'a SourceImage>,
+    /// 背景图未包含版框时，按 canvas 配置以矢量绘制版框与书房名
+    pub draw_frame: bool,
+}
+
+/// 解码后的图片；来源为 JPEG 文件时保留原始数据，供后端原样嵌入而不必重新编码
+#[derive(Debug, Clone)]
+pub struct SourceImage {
+    pub image: DynamicImage,
+    pub jpeg: Option<Vec<u8>>,
+}
+
+/// SVG 图片栅格化后较长边的像素数
+const SVG_RASTER_SIZE: f32 = 512.0;
+
+impl SourceImage {
+    /// 读取 PNG/JPEG 等位图；扩展名为 svg 时按 [`SVG_RASTER_SIZE`] 栅格化
+    pub fn open(path: &Path) -> Result<Self> {
+        let data =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"))
+        {
+            return rasterize_svg(&data)
+                .map(Self::from)
+                .with_context(|| format!("failed to render {}", path.display()));
+        }
+        let format = image::guess_format(&data)
+            .with_context(|| format!("unknown image format: {}", path.display()))?;
+        let image = image::load_from_memory_with_format(&data, format)
+            .with_context(|| format!("failed to decode {}", path.display()))?;
+        Ok(Self {
+            image,
+            jpeg: (format == ImageFormat::Jpeg).then_some(data),
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.image.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.image.height()
+    }
+}
+
+fn rasterize_svg(data: &[u8]) -> Result<DynamicImage> {
+    let tree = usvg::Tree::from_data(data, &usvg::Options::default())?;
+    let size = tree.size();
+    let scale = SVG_RASTER_SIZE / size.width().max(size.height());
+    let (width, height) = (
+        (size.width() * scale).ceil().max(1.0) as u32,
+        (size.height() * scale).ceil().max(1.0) as u32,
+    );
+    let mut pixmap =
+        tiny_skia::Pixmap::new(width, height).context("svg image too large to render")?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    // tiny-skia 的像素为预乘透明度，还原后交给 image
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    let image = RgbaImage::from_raw(width, height, pixels).context("svg pixel buffer")?;
+    Ok(DynamicImage::ImageRgba8(image))
+}
+
+/// 以图片为墨迹着色：越暗越不透明，透明处仍透明，颜色统一为 color
+fn tint_image(source: &SourceImage, color: RgbColor) -> SourceImage {
+    let mut image = source.image.to_rgba8();
+    let ink = color.to_u8();
+    for pixel in image.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        let luma = (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32) / 255.0;
+        let alpha = ((1.0 - luma) * a as f32).round() as u8;
+        *pixel = Rgba([ink[0], ink[1], ink[2], alpha]);
+    }
+    SourceImage::from(DynamicImage::ImageRgba8(image))
+}
+
+impl From<DynamicImage> for SourceImage {
+    fn from(image: DynamicImage) -> Self {
+        Self { image, jpeg: None }
+    }
+}
+
+/// 图片来源，后端据此缓存已注册或已编码的图片
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ImageKey {
+    Background,
+    Cover,
+    Logo,
+    Stamp(PathBuf),
+    /// 外字图片，按所着颜色（RGB 各 8 位）区分
+    Glyph(PathBuf, [u8; 3]),
+}
+
+/// 字形所用字体：书籍字体槽位（1 起）或书房名字体
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FontFace {
+    Slot(usize),
+    Logo,
+}
+
+/// 与输出格式无关的绘制接口。
+///
+/// 坐标均为画布单位（72dpi 下的点，左下原点），与 GlyphSpec/LineSpec 一致；
+/// 页面内容的落点与绘制顺序由 [`render_plan`] 统一决定。
+pub trait Renderer {
+    /// 开始新的一页，number 为 0 表示封面
+    fn begin_page(&mut self, number: usize) -> Result<()>;
+    fn draw_image(
+        &mut self,
+        key: &ImageKey,
+        image: &SourceImage,
+        target: ImagePlacement,
+    ) -> Result<()>;
+    fn draw_polygon(&mut self, polygon: &PolygonSpec);
+    fn draw_line(&mut self, line: &LineSpec);
+    fn draw_glyph(&mut self, face: FontFace, glyph: &GlyphSpec) -> Result<()>;
+    fn end_page(&mut self) -> Result<()>;
+}
+
+/// 按 DocumentPlan 依次驱动后端绘制封面与各页。
+///
+/// `document_path` 为输出文档路径，用于匹配同目录下 yins.cfg 中的印章；
+/// 为 None（如输出到内存）时不叠加印章。
+pub fn render_plan<R: Renderer>(
+    plan: &DocumentPlan,
+    ctx: &RenderContext,
+    document_path: Option<&Path>,
+    renderer: &mut R,
+) -> Result<()> {
+    let stamps = match document_path {
+        Some(path) => load_stamps(path)?,
+        None => HashMap::new(),
+    };
+
+    renderer.begin_page(0)?;
+    match (&plan.cover, ctx.cover_image.as_ref()) {
+        (CoverPlan::Image, Some(image)) => {
+            renderer.draw_image(&ImageKey::Cover, image, full_page(image))?;
+        }
+        (cover, _) => {
+            if matches!(cover, CoverPlan::Image) {
+                if let Some(path) = &plan.cover_path {
+                    eprintln!(
+                        "Cover image requested ({}) but no image loaded; fallback to generated cover",
+                        path.display()
+                    );
+                } else {
+                    eprintln!(
+                        "Cover image requested but no image loaded; fallback to generated cover"
+                    );
+                }
+            }
+            draw_background(renderer, ctx)?;
+            for glyph in cover_glyphs(ctx) {
+                renderer.draw_glyph(FontFace::Slot(glyph.font_idx), &glyph)?;
+            }
+        }
+    }
+    renderer.end_page()?;
+
+    // 横排不用刻本版框与版心，页眉、页脚取代版心标题与页码
+    let vertical = ctx.book.layout_mode != LayoutMode::Horizontal;
+    let multirows = MultiRowsMode::from_flags(
+        ctx.canvas.multirows_enabled,
+        ctx.canvas.multirows_count,
+        ctx.book.multirows_horizontal_layout,
+    );
+    let draw_frame = ctx.draw_frame && vertical;
+    let frame = if draw_frame {
+        build_frame(ctx.canvas, multirows)
+    } else {
+        None
+    };
+    // 分栏线与版框一样只在矢量绘制时添加，预制的 mr_4、mr_5 等背景图已含分栏线；
+    // 不随可选的 FrameConfig 缺省而省略
+    let multirows_lines = if draw_frame {
+        build_multirows_lines(ctx.canvas, multirows)
+    } else {
+        Vec::new()
+    };
+    // 预制背景图已含书房名，与版框一样只在矢量绘制时添加
+    let logo = draw_frame.then(|| logo_placement(ctx));
+    // 印章按路径只读取一次，后端再按 ImageKey 只注册一次
+    let mut file_images: HashMap<PathBuf, SourceImage> = HashMap::new();
+    let mut glyph_images: HashMap<ImageKey, SourceImage> = HashMap::new();
+
+    for page in &plan.pages {
+        renderer.begin_page(page.number)?;
+        draw_background(renderer, ctx)?;
+        if let Some(frame) = &frame {
+            for line in &frame.lines {
+                renderer.draw_line(line);
+            }
+            for polygon in &frame.fills {
+                renderer.draw_polygon(polygon);
+            }
+        }
+        for line in &multirows_lines {
+            renderer.draw_line(line);
+        }
+        if let Some(logo) = &logo {
+            draw_logo(renderer, ctx, logo)?;
+        }
+        for stamp in stamps.get(&page.number).into_iter().flatten() {
+            if !stamp.path.exists() {
+                eprintln!("印章文件不存在，跳过: {}", stamp.path.display());
+                continue;
+            }
+            if !file_images.contains_key(&stamp.path) {
+                let image = SourceImage::open(&stamp.path)
+                    .with_context(|| format!("读取印章图片失败: {}", stamp.path.display()))?;
+                file_images.insert(stamp.path.clone(), image);
+            }
+            let image = &file_images[&stamp.path];
+            let target = stamp_placement(stamp, ctx, image.width(), image.height());
+            renderer.draw_image(&ImageKey::Stamp(stamp.path.clone()), image, target)?;
+        }
+        for glyph in page_title_glyphs(ctx, &page.title)
+            .iter()
+            .chain(&page_number_glyphs(ctx, page.number))
+        {
+            renderer.draw_glyph(FontFace::Slot(glyph.font_idx), glyph)?;
+        }
+        for line in &page.lines {
+            renderer.draw_line(line);
+        }
+        for glyph in &page.glyphs {
+            draw_body_glyph(renderer, ctx, glyph, &mut file_images, &mut glyph_images)?;
+        }
+        renderer.end_page()?;
+    }
+    Ok(())
+}
+
+/// 正文字：外字图片（按字色着色）、拼合或整形所得的各部件，或单个字形
+fn draw_body_glyph<R: Renderer>(
+    renderer: &mut R,
+    ctx: &RenderContext,
+    glyph: &GlyphSpec,
+    file_images: &mut HashMap<PathBuf, SourceImage>,
+    glyph_images: &mut HashMap<ImageKey, SourceImage>,
+) -> Result<()> {
+    if let Some(path) = &glyph.image {
+        let key = ImageKey::Glyph(path.clone(), glyph.color.to_u8());
+        if !glyph_images.contains_key(&key) {
+            if !file_images.contains_key(path) {
+                let image = SourceImage::open(path)
+                    .with_context(|| format!("读取外字图片失败: {}", path.display()))?;
+                file_images.insert(path.clone(), image);
+            }
+            glyph_images.insert(key.clone(), tint_image(&file_images[path], glyph.color));
+        }
+        let image = &glyph_images[&key];
+        let target = glyph_image_placement(ctx, glyph, image.width(), image.height());
+        return renderer.draw_image(&key, image, target);
+    }
+    if glyph.parts.is_empty() {
+        return renderer.draw_glyph(FontFace::Slot(glyph.font_idx), glyph);
+    }
+    for part in glyph.part_glyphs() {
+        renderer.draw_glyph(FontFace::Slot(part.font_idx), &part)?;
+    }
+    Ok(())
+}
+
+/// 外字图片按原比例缩入字身（宽为字号，底边在基线下 descent 处）并居中
+pub fn glyph_image_placement(
+    ctx: &RenderContext,
+    glyph: &GlyphSpec,
+    source_w: u32,
+    source_h: u32,
+) -> ImagePlacement {
+    let size = glyph.font_size;
+    let descent = ctx
+        .fonts
+        .font(glyph.font_idx)
+        .and_then(|font| font.font.horizontal_line_metrics(size))
+        .map_or(0.0, |metrics| metrics.descent);
+    let (box_w, box_h) = (size * glyph.scale_x, size);
+    let scale = if source_w > 0 && source_h > 0 {
+        (box_w / source_w as f32).min(box_h / source_h as f32)
+    } else {
+        0.0
+    };
+    let (width, height) = (source_w as f32 * scale, source_h as f32 * scale);
+    ImagePlacement {
+        x: glyph.x + (box_w - width) / 2.0,
+        y: glyph.y + descent + (box_h - height) / 2.0,
+        width,
+        height,
+    }
+}
+
+fn draw_background<R: Renderer>(renderer: &mut R, ctx: &RenderContext) -> Result<()> {
+    if let Some(color) = ctx.background_color {
+        let (w, h) = (ctx.canvas.canvas_width, ctx.canvas.canvas_height);
+        renderer.draw_polygon(&PolygonSpec {
+            points: vec![(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)],
+            color,
+        });
+    }
+    // 共用纹理的尺寸未必与画布一致，按画布尺寸铺满，不重采样以保留原 JPEG 数据
+    if let Some(image) = &ctx.background {
+        let target = ImagePlacement {
+            x: 0.0,
+            y: 0.0,
+            width: ctx.canvas.canvas_width,
+            height: ctx.canvas.canvas_height,
+        };
+        renderer.draw_image(&ImageKey::Background, image, target)?;
+    }
+    Ok(())
+}
+
+fn draw_logo<R: Renderer>(
+    renderer: &mut R,
+    ctx: &RenderContext,
+    logo: &LogoPlacement,
+) -> Result<()> {
+    let style = &ctx.canvas.logo;
+    for &(ch, x, y) in &logo.text {
+        let glyph = GlyphSpec::new(ch, 0, style.font_size, x, y, style.color);
+        renderer.draw_glyph(FontFace::Logo, &glyph)?;
+    }
+    if let (Some(image), Some(target)) = (ctx.logo_image.as_ref(), logo.image) {
+        renderer.draw_image(&ImageKey::Logo, image, target)?;
+    }
+    Ok(())
+}
+
+/// 整页图片（背景、封面）与 PDF 一致：以 72dpi 自左下角铺放
+fn full_page(image: &SourceImage) -> ImagePlacement {
+    ImagePlacement {
+        x: 0.0,
+        y: 0.0,
+        width: image.width() as f32,
+        height: image.height() as f32,
+    }
+}
+
+/// 封面书名与作者，竖排于页面左侧
+pub fn cover_glyphs(ctx: &RenderContext) -> Vec<GlyphSpec> {
+    let Some(font_idx) = ctx.fonts.text_stack.first().copied() else {
+        return Vec::new();
+    };
+    let cover = &ctx.book.cover;
+    let column = |text: &str, x: f32, top: f32, size: f32| {
+        text.chars()
+            .enumerate()
+            .map(|(idx, ch)| {
+                let y = ctx.canvas.canvas_height - top - idx as f32 * size * 1.2;
+                GlyphSpec::new(ch, font_idx, size, x, y, cover.color)
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut glyphs = column(
+        &ctx.book.title,
+        cover.title_font_size,
+        cover.title_y,
+        cover.title_font_size,
+    );
+    glyphs.extend(column(
+        &ctx.book.author,
+        cover.author_font_size / 2.0,
+        cover.author_y,
+        cover.author_font_size,
+    ));
+    glyphs
+}
+
+/// 版心标题，按 if_tpcenter 居中或居左；横排时为页眉
+pub fn page_title_glyphs(ctx: &RenderContext, title: &str) -> Vec<GlyphSpec> {
+    let Some(font_idx) = ctx.fonts.text_stack.first().copied() else {
+        return Vec::new();
+    };
+    let style = &ctx.book.title_style;
+    if ctx.book.layout_mode == LayoutMode::Horizontal {
+        let y = ctx.canvas.canvas_height - (ctx.canvas.margins_top + style.font_size) / 2.0;
+        let run = HorizontalRun {
+            font_idx,
+            font_size: style.font_size,
+            spacing: style.y_dis,
+            y,
+            color: style.color,
+        };
+        return run.glyphs(ctx, title, style.center);
+    }
+    let x = if style.center {
+        ctx.canvas.canvas_width / 2.0 - style.font_size / 2.0
+    } else {
+        0.0
+    };
+    title
+        .chars()
+        .enumerate()
+        .map(|(idx, ch)| {
+            let y = style.y - style.font_size * idx as f32 * style.y_dis;
+            GlyphSpec::new(ch, font_idx, style.font_size, x, y, style.color)
+        })
+        .collect()
+}
+
+/// 版心页码，以中文数字竖排；横排时居中于页脚
+pub fn page_number_glyphs(ctx: &RenderContext, number: usize) -> Vec<GlyphSpec> {
+    let Some(font_idx) = ctx.fonts.text_stack.first().copied() else {
+        return Vec::new();
+    };
+    let pager = &ctx.book.pager_style;
+    if ctx.book.layout_mode == LayoutMode::Horizontal {
+        let run = HorizontalRun {
+            font_idx,
+            font_size: pager.font_size,
+            spacing: ctx.book.title_style.y_dis,
+            y: (ctx.canvas.margins_bottom - pager.font_size) / 2.0,
+            color: pager.color,
+        };
+        return run.glyphs(ctx, &ctx.numerals.render(number), true);
+    }
+    let x = ctx.canvas.canvas_width / 2.0 - pager.font_size / 2.0;
+    ctx.numerals
+        .render(number)
+        .chars()
+        .enumerate()
+        .map(|(idx, ch)| {
+            let y = pager.y - pager.font_size * idx as f32 * ctx.book.title_style.y_dis;
+            GlyphSpec::new(ch, font_idx, pager.font_size, x, y, pager.color)
+        })
+        .collect()
+}
+
+/// 图片在画布上的落点（左下角与宽高，单位同 GlyphSpec）
+#[derive(Debug, Clone, Copy)]
+pub struct ImagePlacement {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// 横排页眉、页脚的一行字，居中或自左边距起排
+struct HorizontalRun {
+    font_idx: usize,
+    font_size: f32,
+    spacing: f32,
+    y: f32,
+    color: RgbColor,
+}
+
+impl HorizontalRun {
+    fn glyphs(&self, ctx: &RenderContext, text: &str, center: bool) -> Vec<GlyphSpec> {
+        let step = self.font_size * self.spacing;
+        let count = text.chars().count();
+        let width = step * count.saturating_sub(1) as f32 + self.font_size;
+        let left = if center {
+            (ctx.canvas.canvas_width - width) / 2.0
+        } else {
+            ctx.canvas.margins_left
+        };
+        text.chars()
+            .enumerate()
+            .map(|(idx, ch)| {
+                let x = left + step * idx as f32;
+                GlyphSpec::new(ch, self.font_idx, self.font_size, x, self.y, self.color)
+            })
+            .collect()
+    }
+}
+
+pub struct LogoPlacement {
+    /// 书房名逐字的基线位置
+    pub text: Vec<(char, f32, f32)>,
+    pub image: Option<ImagePlacement>,
+}
+
+/// 书房名：文字自 logo_y 起在版心内竖排，图片接在文字之下并缩放至版框底边以内
+pub fn logo_placement(ctx: &RenderContext) -> LogoPlacement {
+    let logo = &ctx.canvas.logo;
+    let (strip_l, strip_r) = center_strip(ctx.canvas);
+    let cx = (strip_l + strip_r) / 2.0;
+    let mut top = ctx.canvas.canvas_height - logo.y;
+
+    let mut text = Vec::new();
+    if let (Some(logo_text), Some(_)) = (&logo.text, &ctx.fonts.logo) {
+        for ch in logo_text.chars() {
+            top -= logo.font_size;
+            text.push((ch, cx - logo.font_size / 2.0, top));
+        }
+    }
+
+    let image = ctx.logo_image.as_ref().and_then(|image| {
+        let avail_h = top - ctx.canvas.margins_bottom;
+        let avail_w = strip_r - strip_l;
+        let (w, h) = (image.width() as f32, image.height() as f32);
+        if avail_h <= 0.0 || avail_w <= 0.0 || w <= 0.0 || h <= 0.0 {
+            return None;
+        }
+        let scale = (avail_h / h).min(avail_w / w);
+        Some(ImagePlacement {
+            x: cx - w * scale / 2.0,
+            y: top - h * scale,
+            width: w * scale,
+            height: h * scale,
+        })
+    });
+    LogoPlacement { text, image }
+}
+
+/// 书名线的波浪折线采样点；竖排为侧线，横排为下划线
+pub fn wavy_points(line: &LineSpec) -> Vec<(f32, f32)> {
+    let (dx, dy) = (line.x2 - line.x1, line.y2 - line.y1);
+    let length = dx.hypot(dy);
+    let segments = (length.max(20.0) / 12.0).ceil() as usize;
+    let amplitude = length.max(1.0) * 0.05;
+    let wavelength = length.max(1.0) / (segments as f32);
+    // 沿线方向与其法向；零长度线按竖线处理
+    let (along, normal) = if length > 0.0 {
+        ((dx / length, dy / length), (dy / length, -dx / length))
+    } else {
+        ((0.0, 1.0), (1.0, 0.0))
+    };
+    let mut points = Vec::with_capacity(segments + 1);
+    for i in 0..=segments {
+        let s = length * i as f32 / segments as f32;
+        let wave = amplitude * (2.0 * std::f32::consts::PI * s / wavelength).sin();
+        points.push((
+            line.x1 + along.0 * s + normal.0 * wave,
+            line.y1 + along.1 * s + normal.1 * wave,
+        ));
+    }
+    points
+}
+
+#[derive(Debug, Clone)]
+pub struct StampSpec {
+    pub page: usize,
+    pub col_begin: usize,
+    pub row_begin: usize,
+    pub cols: usize,
+    pub path: PathBuf,
+}
+
+pub fn load_stamps(output_path: &Path) -> Result<HashMap<usize, Vec<StampSpec>>> {
+    let stamps_cfg = output_path
+        .parent()
+        .map(|p| p.join("yins.cfg"))
+        .unwrap_or_else(|| PathBuf::from("yins.cfg"));
+    if !stamps_cfg.exists() {
+        return Ok(HashMap::new());
+    }
+    let content = std::fs::read_to_string(&stamps_cfg)
+        .with_context(|| format!("读取印章配置失败: {}", stamps_cfg.display()))?;
+    let stem = output_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .to_string();
+    let mut map: HashMap<usize, Vec<StampSpec>> = HashMap::new();
+    for (lineno, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let mut parts = trimmed.split('|');
+        let pdf_name = parts.next().unwrap_or("").trim();
+        let pos = parts.next().unwrap_or("").trim();
+        let file = parts.next().unwrap_or("").trim();
+        if pdf_name != stem && pdf_name != "*" {
+            continue;
+        }
+        let parsed = pos
+            .split(',')
+            .filter_map(|x| x.trim().parse::<usize>().ok())
+            .collect::<Vec<_>>();
+        if parsed.len() != 4 || file.is_empty() {
+            eprintln!(
+                "忽略 yins.cfg 第 {} 行：格式错误 (需要 pdf|page,col,row,cols|file)",
+                lineno + 1
+            );
+            continue;
+        }
+        let spec = StampSpec {
+            page: parsed[0],
+            col_begin: parsed[1],
+            row_begin: parsed[2],
+            cols: parsed[3],
+            path: stamps_cfg
+                .parent()
+                .unwrap_or_else(|| Path::new("."))
+                .join("yins")
+                .join(file),
+        };
+        map.entry(spec.page).or_default().push(spec);
+    }
+    Ok(map)
+}
+
+/// 印章落点：自 col_begin 列左缘、row_begin 行起，宽 cols 列并保持图片比例
+pub fn stamp_placement(
+    stamp: &StampSpec,
+    ctx: &RenderContext,
+    source_w: u32,
+    source_h: u32,
+) -> ImagePlacement {
+    let cw = (ctx.canvas.canvas_width
+        - ctx.canvas.margins_left
+        - ctx.canvas.margins_right
+        - ctx.canvas.leaf_center_width)
+        / ctx.canvas.leaf_col as f32;
+    let rh = (ctx.canvas.canvas_height - ctx.canvas.margins_top - ctx.canvas.margins_bottom)
+        / ctx.book.row_num as f32;
+
+    let mut x = ctx.canvas.canvas_width - ctx.canvas.margins_right - cw * stamp.col_begin as f32;
+    if stamp.col_begin > ctx.canvas.leaf_col / 2 {
+        x -= ctx.canvas.leaf_center_width;
+    }
+    let y = ctx.canvas.margins_bottom + rh * (stamp.row_begin.saturating_sub(1)) as f32;
+    let width = cw * stamp.cols as f32;
+    let height = if source_w > 0 {
+        width * source_h as f32 / source_w as f32
+    } else {
+        0.0
+    };
+    ImagePlacement {
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{Sample, single_page};
+
+    /// 只记录调用序列的后端
+    #[derive(Default)]
+    struct Recorder {
+        calls: Vec<String>,
+    }
+
+    impl Renderer for Recorder {
+        fn begin_page(&mut self, number: usize) -> Result<()> {
+            self.calls.push(format!("begin {number}"));
+            Ok(())
+        }
+
+        fn draw_image(
+            &mut self,
+            key: &ImageKey,
+            _image: &SourceImage,
+            _target: ImagePlacement,
+        ) -> Result<()> {
+            self.calls.push(format!("image {key:?}"));
+            Ok(())
+        }
+
+        fn draw_polygon(&mut self, _polygon: &PolygonSpec) {
+            self.calls.push("polygon".into());
+        }
+
+        fn draw_line(&mut self, _line: &LineSpec) {
+            self.calls.push("line".into());
+        }
+
+        fn draw_glyph(&mut self, face: FontFace, glyph: &GlyphSpec) -> Result<()> {
+            self.calls.push(format!("glyph {face:?} {}", glyph.ch));
+            Ok(())
+        }
+
+        fn end_page(&mut self) -> Result<()> {
+            self.calls.push("end".into());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn render_plan_draws_cover_then_pages_in_plan_order() {
+        let sample = Sample::load(None);
+        let font_idx = sample.text_font();
+        let background = SourceImage::from(DynamicImage::new_rgb8(4, 4));
+        let glyph = GlyphSpec::new(
+            '甲',
+            font_idx,
+            48.0,
+            100.0,
+            100.0,
+            sample.book.text_font_color,
+        );
+        let plan = single_page(3, vec![glyph], Vec::new());
+        let ctx = RenderContext {
+            background: Some(&background),
+            draw_frame: false,
+            ..sample.context()
+        };
+
+        let mut recorder = Recorder::default();
+        let document = std::env::temp_dir().join("vrain_backend_order.pdf");
+        render_plan(&plan, &ctx, Some(&document), &mut recorder).expect("drive recorder");
+
+        let calls = &recorder.calls;
+        let pages = calls
+            .iter()
+            .filter(|call| call.starts_with("begin"))
+            .count();
+        assert_eq!(pages, 2, "cover plus one page: {calls:?}");
+        assert_eq!(calls[0], "begin 0");
+        let page_start = calls
+            .iter()
+            .position(|call| call == "begin 3")
+            .expect("page 3 must be drawn");
+        assert_eq!(calls[page_start + 1], "image Background");
+        assert_eq!(
+            calls[calls.len() - 2],
+            format!("glyph Slot({font_idx}) 甲"),
+            "body glyphs are drawn last, above title and page number"
+        );
+        assert_eq!(calls.last().map(String::as_str), Some("end"));
+    }
+
+    /// 以 ctx 绘制一页空白页，返回记录的调用
+    fn record_blank_page(ctx: &RenderContext) -> Vec<String> {
+        let plan = single_page(1, Vec::new(), Vec::new());
+        let mut recorder = Recorder::default();
+        render_plan(&plan, ctx, None, &mut recorder).expect("drive recorder");
+        recorder.calls
+    }
+
+    #[test]
+    fn logo_is_drawn_in_the_center_strip_only_with_the_vector_frame() {
+        let mut sample = Sample::load(Some("18_blue"));
+        sample
+            .fonts
+            .load_logo_font(&sample.canvas, Path::new("fonts"))
+            .expect("load logo font");
+        let canvas = &sample.canvas;
+        let mut ctx = sample.context();
+
+        let logo = logo_placement(&ctx);
+        let count = canvas.logo.text.as_ref().unwrap().chars().count();
+        assert_eq!(logo.text.len(), count);
+        let (strip_l, strip_r) = center_strip(canvas);
+        let size = canvas.logo.font_size;
+        let top = canvas.canvas_height - canvas.logo.y;
+        for (i, &(_, x, y)) in logo.text.iter().enumerate() {
+            assert_eq!(x, (strip_l + strip_r) / 2.0 - size / 2.0);
+            assert_eq!(y, top - size * (i + 1) as f32);
+        }
+
+        let logo_glyphs = |ctx: &RenderContext| {
+            record_blank_page(ctx)
+                .iter()
+                .filter(|call| call.starts_with("glyph Logo"))
+                .count()
+        };
+        assert_eq!(logo_glyphs(&ctx), count);
+        // 预制背景图已含书房名
+        ctx.draw_frame = false;
+        assert_eq!(logo_glyphs(&ctx), 0);
+    }
+
+    #[test]
+    fn baked_multirows_canvases_get_no_extra_lines() {
+        let sample = Sample::load(Some("mr_5"));
+        let mut ctx = sample.context();
+        let lines = |ctx: &RenderContext| {
+            record_blank_page(ctx)
+                .iter()
+                .filter(|call| *call == "line")
+                .count()
+        };
+        assert!(lines(&ctx) > 0);
+        // mr_5.jpg 已含分栏线
+        ctx.draw_frame = false;
+        assert_eq!(lines(&ctx), 0);
+    }
+
+    #[test]
+    fn svg_glyph_images_are_rasterized_and_tinted_as_ink() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10">
+            <rect width="10" height="10" fill="black"/></svg>"#;
+        let image = SourceImage::from(rasterize_svg(svg).expect("render svg"));
+        assert_eq!((image.width(), image.height()), (512, 256));
+
+        let red = RgbColor::new_u8(200, 0, 0);
+        let tinted = tint_image(&image, red).image.to_rgba8();
+        assert_eq!(
+            tinted.get_pixel(5, 5).0,
+            [200, 0, 0, 255],
+            "ink takes the glyph color"
+        );
+        assert_eq!(
+            tinted.get_pixel(500, 5).0[3],
+            0,
+            "blank areas stay transparent"
+        );
+    }
+}
@@ -1,6 +1,70 @@
-use crate::config::CanvasConfig;
+use crate::backend::SourceImage;
+use crate::color::RgbColor;
+use crate::config::{CanvasBackground, CanvasConfig};
 use image::{ImageBuffer, Rgba, RgbaImage};
 
+/// 解析后的画布底层：底色、背景图，以及是否需要另绘矢量版框
+pub struct CanvasLayers {
+    pub color: Option<RgbColor>,
+    pub image: Option<SourceImage>,
+    pub draw_frame: bool,
+}
+
+pub fn load_canvas_layers(canvas: &CanvasConfig) -> CanvasLayers {
+    match &canvas.background {
+        CanvasBackground::Baked(path) => match SourceImage::open(path) {
+            Ok(image) => CanvasLayers {
+                color: None,
+                image: Some(image),
+                draw_frame: false,
+            },
+            Err(err) => {
+                eprintln!("读取画布背景失败 ({}): {}", path.display(), err);
+                generated_layers(canvas)
+            }
+        },
+        CanvasBackground::Texture { path, color } if !path.exists() => {
+            eprintln!("canvas_background_image not found: {}", path.display());
+            match color {
+                Some(color) => solid_layers(*color),
+                None => generated_layers(canvas),
+            }
+        }
+        CanvasBackground::Texture { path, color } => match SourceImage::open(path) {
+            Ok(image) => CanvasLayers {
+                color: *color,
+                image: Some(image),
+                draw_frame: true,
+            },
+            Err(err) => {
+                eprintln!("读取纸张纹理失败 ({}): {}", path.display(), err);
+                match color {
+                    Some(color) => solid_layers(*color),
+                    None => generated_layers(canvas),
+                }
+            }
+        },
+        CanvasBackground::Solid(color) => solid_layers(*color),
+        CanvasBackground::Generated => generated_layers(canvas),
+    }
+}
+
+fn solid_layers(color: RgbColor) -> CanvasLayers {
+    CanvasLayers {
+        color: Some(color),
+        image: None,
+        draw_frame: true,
+    }
+}
+
+fn generated_layers(canvas: &CanvasConfig) -> CanvasLayers {
+    CanvasLayers {
+        color: None,
+        image: Some(generate_bamboo_background(canvas).into()),
+        draw_frame: true,
+    }
+}
+
 // 简单的宣纸/竹简纹理生成器，用于在缺少背景图时兜底
 pub fn generate_bamboo_background(canvas: &CanvasConfig) -> image::DynamicImage {
     let width = canvas.canvas_width.max(1.0) as u32;
@@ -28,8 +92,24 @@ pub fn generate_bamboo_background(canvas: &CanvasConfig) -> image::DynamicImage
         fill_rect(&mut img, x_start, y_start, x_end, y_end, bc1);
 
         // 右侧淡阴影
-        draw_line(&mut img, x_end, y_start, x_end, y_end, [210, 210, 210, 255], 2.0);
-        draw_line(&mut img, x_start, y_end, x_end, y_end, [210, 210, 210, 255], 2.0);
+        draw_line(
+            &mut img,
+            x_end,
+            y_start,
+            x_end,
+            y_end,
+            [210, 210, 210, 255],
+            2.0,
+        );
+        draw_line(
+            &mut img,
+            x_start,
+            y_end,
+            x_end,
+            y_end,
+            [210, 210, 210, 255],
+            2.0,
+        );
 
         // 顶/底部绑带
         let band_h = (cw * 0.1).max(4.0);
@@ -57,24 +137,8 @@ pub fn generate_bamboo_background(canvas: &CanvasConfig) -> image::DynamicImage
                 continue;
             }
             let lx = x_start - cw * 0.02 + step * j as f32;
-            draw_line(
-                &mut img,
-                lx,
-                y_start - band_h,
-                lx + step,
-                y_start,
-                bc2,
-                2.0,
-            );
-            draw_line(
-                &mut img,
-                lx,
-                y_end,
-                lx + step,
-                y_end + band_h,
-                bc2,
-                2.0,
-            );
+            draw_line(&mut img, lx, y_start - band_h, lx + step, y_start, bc2, 2.0);
+            draw_line(&mut img, lx, y_end, lx + step, y_end + band_h, bc2, 2.0);
         }
 
         // 竖向纹理
@@ -155,9 +219,9 @@ fn add_noise(img: &mut RgbaImage, magnitude: f32) {
             let noise = pseudo_noise(x, y) as f32 / 255.0;
             let delta = (noise - 0.5) * magnitude * 255.0;
             let mut p = img.get_pixel(x, y).0;
-            for c in 0..3 {
-                let v = (p[c] as f32 + delta).clamp(0.0, 255.0);
-                p[c] = v as u8;
+            for c in p.iter_mut().take(3) {
+                let v = (*c as f32 + delta).clamp(0.0, 255.0);
+                *c = v as u8;
             }
             img.put_pixel(x, y, Rgba(p));
         }
@@ -171,4 +235,3 @@ fn pseudo_noise(x: u32, y: u32) -> u8 {
     v = v.wrapping_mul(0x85ebca6b);
     ((v >> 8) & 0xFF) as u8
 }
-
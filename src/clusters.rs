@@ -0,0 +1,297 @@
+//! 字形簇：基字连同其后的组合附加符、异体字选择符（IVS）与零宽连接符所连之字，
+//! 作为一个整体占位、选字体与整形；`⿰木夋` 这样的构形描述序列（IDS）与登记了外字图片的
+//! `{名称}` 记号也作一字处理，未登记的 `{…}` 仍是普通文字。`{font3:文字}` 标记的一段文字逐字记下所指定的字体槽位。
+//!
+//! 排版引擎逐字处理文本，预处理时把多字的字形簇换成补充私用区 B 中的占位字，
+//! 排版时再由 [`ClusterTable`] 取回原字串交给字体整形。
+
+use crate::ids::{is_ids_operator, read_sequence};
+use std::collections::{HashMap, HashSet};
+use std::iter::{self, Peekable};
+use std::str::Chars;
+use unicode_properties::{GeneralCategoryGroup, UnicodeGeneralCategory};
+
+/// 占位字自补充私用区 B 起编号
+const PLACEHOLDER_START: u32 = 0x10_0000;
+const PLACEHOLDER_END: u32 = 0x10_FFFD;
+const ZWJ: char = '\u{200D}';
+const TOKEN_OPEN: char = '{';
+const TOKEN_CLOSE: char = '}';
+/// 外字记号名称的最大字数
+const TOKEN_MAX_CHARS: usize = 32;
+/// 字体切换标记 `{fontN:…}` 的前缀
+const FONT_RUN_PREFIX: &str = "font";
+
+#[derive(Debug, Clone, Default)]
+pub struct ClusterTable {
+    clusters: Vec<String>,
+    /// 各占位字指定的字体槽位（1-5），来自字体切换标记
+    slots: Vec<Option<usize>>,
+    index: HashMap<(String, Option<usize>), char>,
+    /// 有外字图片的 `{名称}` 记号，含花括号
+    tokens: HashSet<String>,
+}
+
+impl ClusterTable {
+    /// 只把 tokens 中的 `{名称}` 当作外字记号，如 [`GlyphImages::tokens`] 所列
+    ///
+    /// [`GlyphImages::tokens`]: crate::glyph_images::GlyphImages::tokens
+    pub fn with_tokens<S: Into<String>>(tokens: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            tokens: tokens.into_iter().map(Into::into).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// 将 text 中的多字字形簇、完整的 IDS 与已登记的外字记号换成占位字，相同的字形簇共用一个占位字；
+    /// 字体切换标记内的各字（ASCII 与标点除外）连同槽位换成占位字。占位字用尽时原样保留
+    pub fn encode(&mut self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut chars = text.chars().peekable();
+        while let Some(base) = chars.next() {
+            if is_ids_operator(base) {
+                let mut rest = chars.clone();
+                if let Some(sequence) = read_sequence(&mut iter::once(base).chain(rest.by_ref())) {
+                    chars = rest;
+                    self.push(sequence, &mut result);
+                    continue;
+                }
+            }
+            if base == TOKEN_OPEN
+                && let Some((slot, run)) = read_font_run(&mut chars)
+            {
+                self.push_font_run(&run, slot, &mut result);
+                continue;
+            }
+            if base == TOKEN_OPEN
+                && !is_font_markup(&chars)
+                && let Some(token) = read_token(&mut chars, &self.tokens)
+            {
+                self.push(token, &mut result);
+                continue;
+            }
+            let mut cluster = String::from(base);
+            while let Some(&next) = chars.peek() {
+                if next == ZWJ {
+                    cluster.push(next);
+                    chars.next();
+                    if let Some(joined) = chars.next() {
+                        cluster.push(joined);
+                    }
+                } else if is_extender(next) {
+                    cluster.push(next);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            if cluster.len() == base.len_utf8() {
+                result.push(base);
+            } else {
+                self.push(cluster, &mut result);
+            }
+        }
+        result
+    }
+
+    fn push(&mut self, cluster: String, result: &mut String) {
+        self.push_with_slot(cluster, None, result);
+    }
+
+    fn push_with_slot(&mut self, cluster: String, slot: Option<usize>, result: &mut String) {
+        match self.placeholder(cluster, slot) {
+            Ok(ch) => result.push(ch),
+            Err(cluster) => result.push_str(&cluster),
+        }
+    }
+
+    /// 字体切换标记内的文字：字形簇与各字带上槽位，ASCII（供后续替换）与标点保持原样
+    fn push_font_run(&mut self, run: &str, slot: usize, result: &mut String) {
+        for ch in self.encode(run).chars() {
+            let cluster = match self.get(ch) {
+                Some(cluster) => cluster.to_string(),
+                None if ch.is_ascii()
+                    || ch.general_category_group() == GeneralCategoryGroup::Punctuation =>
+                {
+                    result.push(ch);
+                    continue;
+                }
+                None => ch.to_string(),
+            };
+            self.push_with_slot(cluster, Some(slot), result);
+        }
+    }
+
+    /// 占位字对应的字形簇；ch 不是占位字时为 None
+    pub fn get(&self, ch: char) -> Option<&str> {
+        let idx = (ch as u32).checked_sub(PLACEHOLDER_START)?;
+        self.clusters.get(idx as usize).map(String::as_str)
+    }
+
+    /// 占位字所在字体切换标记指定的槽位
+    pub fn font_slot(&self, ch: char) -> Option<usize> {
+        let idx = (ch as u32).checked_sub(PLACEHOLDER_START)?;
+        self.slots.get(idx as usize).copied().flatten()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clusters.is_empty()
+    }
+
+    fn placeholder(&mut self, cluster: String, slot: Option<usize>) -> Result<char, String> {
+        let key = (cluster, slot);
+        if let Some(&ch) = self.index.get(&key) {
+            return Ok(ch);
+        }
+        let code = PLACEHOLDER_START + self.clusters.len() as u32;
+        let Some(ch) = char::from_u32(code).filter(|_| code <= PLACEHOLDER_END) else {
+            return Err(key.0);
+        };
+        self.clusters.push(key.0.clone());
+        self.slots.push(slot);
+        self.index.insert(key, ch);
+        Ok(ch)
+    }
+}
+
+/// 读取 `{` 之后至 `}` 的记号；不在 known 中（含名称为空、过长或未闭合）时不消耗任何字
+fn read_token(chars: &mut Peekable<Chars<'_>>, known: &HashSet<String>) -> Option<String> {
+    let mut rest = chars.clone();
+    let mut token = String::from(TOKEN_OPEN);
+    let mut count = 0;
+    while let Some(ch) = rest.next() {
+        match ch {
+            TOKEN_CLOSE if count > 0 => {
+                token.push(ch);
+                if !known.contains(&token) {
+                    return None;
+                }
+                *chars = rest;
+                return Some(token);
+            }
+            TOKEN_OPEN | TOKEN_CLOSE | '\n' => return None,
+            _ if count >= TOKEN_MAX_CHARS => return None,
+            _ => token.push(ch),
+        }
+        count += 1;
+    }
+    None
+}
+
+/// `{` 之后是否形如 `fontN:`；槽位无效的字体切换标记原样保留，不当作外字记号
+fn is_font_markup(chars: &Peekable<Chars<'_>>) -> bool {
+    let mut rest = chars.clone();
+    FONT_RUN_PREFIX
+        .chars()
+        .all(|expected| rest.next() == Some(expected))
+        && rest.next().is_some_and(|ch| ch.is_ascii_digit())
+        && rest.next() == Some(':')
+}
+
+/// 读取 `{` 之后的 `fontN:文字}`，返回槽位 N（1-5）与文字；文字为空、含 `{`、未闭合时
+/// 不消耗任何字
+fn read_font_run(chars: &mut Peekable<Chars<'_>>) -> Option<(usize, String)> {
+    let mut rest = chars.clone();
+    if !FONT_RUN_PREFIX
+        .chars()
+        .all(|expected| rest.next() == Some(expected))
+    {
+        return None;
+    }
+    let slot = rest
+        .next()?
+        .to_digit(10)
+        .map(|digit| digit as usize)
+        .filter(|slot| (1..=5).contains(slot))?;
+    if rest.next() != Some(':') {
+        return None;
+    }
+    let mut run = String::new();
+    for ch in rest.by_ref() {
+        match ch {
+            TOKEN_CLOSE if !run.is_empty() => {
+                *chars = rest;
+                return Some((slot, run));
+            }
+            TOKEN_OPEN | TOKEN_CLOSE | '\n' => return None,
+            _ => run.push(ch),
+        }
+    }
+    None
+}
+
+/// 字形簇是否为 `{名称}` 外字记号
+pub fn is_token(cluster: &str) -> bool {
+    cluster.starts_with(TOKEN_OPEN) && cluster.ends_with(TOKEN_CLOSE)
+}
+
+/// 附于前字的字符：组合附加符（含各类异体字选择符）
+fn is_extender(ch: char) -> bool {
+    ch.general_category_group() == GeneralCategoryGroup::Mark
+}
+
+/// 字形簇中的异体字选择符，需字体 cmap 14 明确收录才能取到对应字形
+pub fn is_variation_selector(ch: char) -> bool {
+    matches!(ch, '\u{FE00}'..='\u{FE0F}' | '\u{E0100}'..='\u{E01EF}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClusterTable;
+
+    #[test]
+    fn marks_and_variation_selectors_join_their_base() {
+        let mut table = ClusterTable::default();
+        let encoded = table.encode("e\u{301}葛\u{E0100}x葛\u{E0100}");
+        let chars: Vec<char> = encoded.chars().collect();
+        assert_eq!(chars.len(), 4, "each cluster becomes one slot");
+        assert_eq!(table.get(chars[0]), Some("e\u{301}"));
+        assert_eq!(table.get(chars[1]), Some("葛\u{E0100}"));
+        assert_eq!(chars[2], 'x');
+        assert_eq!(chars[1], chars[3], "identical clusters share a placeholder");
+    }
+
+    #[test]
+    fn font_runs_tag_each_char_with_the_slot() {
+        // 即便登记了同名图片，槽位超出 1-5 的标记也不作外字记号
+        let mut table = ClusterTable::with_tokens(["{font9:x}"]);
+        let encoded = table.encode("葛{font3:葛，e\u{301}1}{font9:x}");
+        let chars: Vec<char> = encoded.chars().collect();
+        assert_eq!(chars[0], '葛');
+        assert_eq!(table.get(chars[1]), Some("葛"));
+        assert_eq!(table.font_slot(chars[1]), Some(3));
+        assert_eq!(chars[2], '，', "punctuation keeps its role in the run");
+        assert_eq!(table.get(chars[3]), Some("e\u{301}"));
+        assert_eq!(table.font_slot(chars[3]), Some(3));
+        assert_ne!(chars[3], table.encode("e\u{301}").chars().next().unwrap());
+        assert_eq!(chars[4], '1', "ASCII is left for replacements");
+        assert_eq!(
+            chars[5..].iter().collect::<String>(),
+            "{font9:x}",
+            "no such slot: plain text"
+        );
+    }
+
+    #[test]
+    fn only_tokens_with_images_take_one_slot() {
+        let mut table = ClusterTable::with_tokens(["{䄂異}"]);
+        let encoded = table.encode("甲{䄂異}{}{艸乞}乙");
+        let chars: Vec<char> = encoded.chars().collect();
+        assert_eq!(table.get(chars[1]), Some("{䄂異}"));
+        assert_eq!(chars[2..4], ['{', '}'], "an empty token stays as text");
+        assert_eq!(
+            encoded.chars().skip(4).collect::<String>(),
+            "{艸乞}乙",
+            "a name without an image stays as text"
+        );
+    }
+
+    #[test]
+    fn ideographic_description_sequences_take_one_slot() {
+        let mut table = ClusterTable::default();
+        let encoded = table.encode("⿰木夋⿱");
+        let chars: Vec<char> = encoded.chars().collect();
+        assert_eq!(table.get(chars[0]), Some("⿰木夋"));
+        assert_eq!(chars[1], '⿱', "an incomplete sequence stays as text");
+    }
+}
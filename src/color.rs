@@ -1,4 +1,4 @@
-use anyhow::{anyhow, Result};
+use anyhow::{Result, anyhow};
 use serde::Serialize;
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize)]
@@ -37,6 +37,11 @@ impl RgbColor {
             b: b as f32 / 255.0,
         }
     }
+
+    /// 各分量换算为 0–255
+    pub fn to_u8(&self) -> [u8; 3] {
+        [self.r, self.g, self.b].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
 }
 
 fn parse_hex(hex: &str) -> Result<RgbColor> {
@@ -18,22 +18,47 @@ impl RawConfig {
         let path = path.as_ref();
         let content =
             fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Ok(Self::parse(path, &content))
+    }
+
+    /// 解析内存中的配置文本；source 用于报错，并作为相对路径的参照
+    pub fn parse(source: impl AsRef<Path>, content: &str) -> Self {
         let mut data = BTreeMap::new();
         for raw_line in content.lines() {
             if let Some((k, v)) = parse_line(raw_line) {
                 data.insert(k, v);
             }
         }
-        Ok(Self {
-            source: path.to_path_buf(),
+        Self {
+            source: source.as_ref().to_path_buf(),
             data,
-        })
+        }
     }
 
     pub fn get(&self, key: &str) -> Option<&str> {
         self.data.get(key).map(|s| s.as_str())
     }
 
+    /// 覆盖或新增一项配置，优先于文件中的同名项
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.data.insert(key.into(), value.into());
+    }
+
+    /// 按 `key=value` 逐项覆盖，写法与配置文件中的一行相同
+    pub fn apply_overrides<S: AsRef<str>>(
+        &mut self,
+        overrides: impl IntoIterator<Item = S>,
+    ) -> Result<()> {
+        for spec in overrides {
+            let spec = spec.as_ref();
+            match parse_line(spec) {
+                Some((key, value)) if spec.contains('=') => self.set(key, value),
+                _ => return Err(anyhow!("invalid override '{}', expected key=value", spec)),
+            }
+        }
+        Ok(())
+    }
+
     pub fn require(&self, key: &str) -> Result<&str> {
         self.get(key)
             .ok_or_else(|| anyhow!("missing key '{}' in {}", key, self.source.display()))
@@ -56,10 +81,16 @@ fn parse_line(raw: &str) -> Option<(String, String)> {
         return None;
     }
 
-    let without_inline = if trimmed.contains("=#") {
-        trimmed
-    } else {
-        trimmed.split('#').next().unwrap_or("").trim()
+    // 颜色值以 # 开头（如 key=#f5f5f5 #注释），仅截去其后的行内注释
+    let without_inline = match trimmed.find("=#") {
+        Some(idx) => {
+            let value_start = idx + "=#".len();
+            match trimmed[value_start..].find('#') {
+                Some(rel) => trimmed[..value_start + rel].trim(),
+                None => trimmed,
+            }
+        }
+        None => trimmed.split('#').next().unwrap_or("").trim(),
     };
 
     if without_inline.is_empty() {
@@ -72,10 +103,7 @@ fn parse_line(raw: &str) -> Option<(String, String)> {
         .collect();
     let mut parts = collapsed.splitn(2, '=');
     let key = parts.next()?.to_string();
-    let value = parts
-        .next()
-        .map(|v| v.to_string())
-        .unwrap_or_else(String::new);
+    let value = parts.next().map(|v| v.to_string()).unwrap_or_default();
     if key.is_empty() {
         None
     } else {
@@ -168,6 +196,8 @@ pub struct PunctuationConfig {
     pub comment_nop: MarkAdjust,
     pub comment_rotate: MarkAdjust,
     pub comment_strip_chars: Vec<char>,
+    /// 落在列末字之后的正文不占位标点悬挂于版框下方
+    pub text_nop_hang: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -176,6 +206,84 @@ pub struct BookLineConfig {
     pub color: RgbColor,
 }
 
+/// 版式：固定字格，按字形实际高度排列的比例竖排，或自左向右的横排
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutMode {
+    Grid,
+    Proportional,
+    Horizontal,
+}
+
+/// 行首、行尾禁则：head 中的字不居列首，tail 中的字不居列尾
+#[derive(Debug, Clone, Default)]
+pub struct LineBreakRules {
+    pub head: Vec<char>,
+    pub tail: Vec<char>,
+    /// 列首禁则字悬挂于上一列末字之下，而非与上一列末字一同移至下一列
+    pub hang: bool,
+}
+
+/// 批注出处标签的标记，如 `【〘集解〙徐廣曰…】`
+pub const ANNOTATION_LABEL_OPEN: char = '〘';
+pub const ANNOTATION_LABEL_CLOSE: char = '〙';
+
+/// 一种批注标记及其字体、字号与颜色；font_size 为 None 时沿用各字体的 comment_fontN_size
+#[derive(Debug, Clone)]
+pub struct AnnotationStyle {
+    pub open: char,
+    pub close: char,
+    pub stack: Vec<usize>,
+    pub font_size: Option<f32>,
+    pub color: RgbColor,
+}
+
+/// 全部批注样式，首项为 `【】`；批注可相互嵌套
+#[derive(Debug, Clone)]
+pub struct AnnotationConfig {
+    pub styles: Vec<AnnotationStyle>,
+    /// 每个字位容纳的批注小行数，1 至 3
+    pub columns: usize,
+    /// 批注末段各小行均分字数，相差不超过一字
+    pub balance: bool,
+    /// 横排时批注改作页脚注，正文处只留序号
+    pub footnote: bool,
+    /// 出处标签颜色，None 时与所在批注同色
+    pub label_color: Option<RgbColor>,
+}
+
+impl AnnotationConfig {
+    pub fn opening(&self, ch: char) -> Option<usize> {
+        self.styles.iter().position(|style| style.open == ch)
+    }
+
+    pub fn closing(&self, ch: char) -> Option<usize> {
+        self.styles.iter().position(|style| style.close == ch)
+    }
+
+    /// 批注标记与出处标签标记，不占字位
+    pub fn is_markup(&self, ch: char) -> bool {
+        ch == ANNOTATION_LABEL_OPEN
+            || ch == ANNOTATION_LABEL_CLOSE
+            || self.opening(ch).is_some()
+            || self.closing(ch).is_some()
+    }
+}
+
+/// 名框样式：方框或两端圆弧的椭圆框
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameFrameStyle {
+    Rect,
+    Cartouche,
+}
+
+/// `<…>` 名框的线宽、颜色与样式
+#[derive(Debug, Clone)]
+pub struct NameFrameConfig {
+    pub style: NameFrameStyle,
+    pub width: f32,
+    pub color: RgbColor,
+}
+
 #[derive(Debug, Clone)]
 pub struct BookConfig {
     pub title: String,
@@ -183,24 +291,36 @@ pub struct BookConfig {
     pub canvas_id: String,
     pub row_num: usize,
     pub row_delta_y: f32,
+    pub layout_mode: LayoutMode,
     pub multirows_horizontal_layout: usize,
     pub fonts: FontMapping,
     pub try_st: bool,
     pub text_font_color: RgbColor,
     pub comment_font_color: RgbColor,
+    pub annotations: AnnotationConfig,
     pub cover: CoverConfig,
     pub title_style: TitleConfig,
     pub pager_style: PagerConfig,
     pub replacements: ReplacementRules,
     pub text_modes: TextModes,
     pub punctuation: PunctuationConfig,
+    pub line_break: LineBreakRules,
     pub bookline: Option<BookLineConfig>,
     pub book_line_flag: bool,
+    pub name_frame: Option<NameFrameConfig>,
 }
 
 impl BookConfig {
     pub fn load(path: impl AsRef<Path>) -> Result<Self> {
-        let raw = RawConfig::load(path)?;
+        Self::from_raw(&RawConfig::load(path)?)
+    }
+
+    /// 从内存中的 book.cfg 文本构建
+    pub fn parse(content: &str) -> Result<Self> {
+        Self::from_raw(&RawConfig::parse("book.cfg", content))
+    }
+
+    pub fn from_raw(raw: &RawConfig) -> Result<Self> {
         let title = raw.require("title")?.to_string();
         let author = raw.require("author")?.to_string();
         let canvas_id = raw.require("canvas_id")?.to_string();
@@ -210,12 +330,18 @@ impl BookConfig {
             .unwrap_or("0")
             .parse::<f32>()
             .unwrap_or(0.0);
+        let layout_mode = match raw.get("layout_mode").unwrap_or("") {
+            "" | "grid" => LayoutMode::Grid,
+            "proportional" => LayoutMode::Proportional,
+            "horizontal" => LayoutMode::Horizontal,
+            other => return Err(anyhow!("unknown layout_mode: {other}")),
+        };
         let multirows_horizontal_layout = raw
             .get("multirows_horizontal_layout")
             .and_then(|s| s.parse::<usize>().ok())
             .unwrap_or(1);
 
-        let fonts = parse_font_mapping(&raw)?;
+        let fonts = parse_font_mapping(raw)?;
         if fonts.text_stack.is_empty() {
             return Err(anyhow!("text_fonts_array is empty"));
         }
@@ -227,6 +353,7 @@ impl BookConfig {
         let text_font_color = parse_color(raw.get("text_font_color"), RgbColor::new_u8(0, 0, 0))?;
         let comment_font_color =
             parse_color(raw.get("comment_font_color"), RgbColor::new_u8(0, 0, 0))?;
+        let annotations = parse_annotations(raw, &fonts.comment_stack, comment_font_color)?;
 
         let cover = CoverConfig {
             title_font_size: parse_f32(raw.get("cover_title_font_size"), 120.0)?,
@@ -252,21 +379,52 @@ impl BookConfig {
             y: parse_f32(raw.get("pager_y"), 500.0)?,
         };
 
+        let name_frame = if parse_bool(raw.get("if_name_frame")) {
+            let style = match raw.get("name_frame_style").unwrap_or("rect") {
+                "" | "rect" => NameFrameStyle::Rect,
+                "cartouche" => NameFrameStyle::Cartouche,
+                other => return Err(anyhow!("unknown name_frame_style: {other}")),
+            };
+            Some(NameFrameConfig {
+                style,
+                width: parse_f32(raw.get("name_frame_width"), 1.0)?,
+                color: parse_color(raw.get("name_frame_color"), RgbColor::new_u8(0, 0, 0))?,
+            })
+        } else {
+            None
+        };
+
+        // 标记符号优先于标点替换、删除与归一化规则，如 exp_replace_comma 中的 <〔|>〕
+        let is_markup = |ch: char| {
+            annotations.is_markup(ch) || (name_frame.is_some() && (ch == '<' || ch == '>'))
+        };
+        let keep_token = |token: &String| {
+            let mut chars = token.chars();
+            !matches!((chars.next(), chars.next()), (Some(ch), None) if is_markup(ch))
+        };
+        let mut comma_pairs = parse_replace_pairs(raw.get("exp_replace_comma"));
+        comma_pairs.retain(|(from, _)| !is_markup(*from));
+        let mut delete_tokens = parse_token_list(raw.get("exp_delete_comma"));
+        delete_tokens.retain(keep_token);
         let replacements = ReplacementRules {
-            comma_pairs: parse_replace_pairs(raw.get("exp_replace_comma")),
+            comma_pairs,
             number_pairs: parse_replace_pairs(raw.get("exp_replace_number")),
-            delete_tokens: parse_token_list(raw.get("exp_delete_comma")),
+            delete_tokens,
         };
 
+        let mut remove_tokens = parse_token_list(raw.get("exp_nocomma"));
+        remove_tokens.retain(keep_token);
+        let mut only_period_tokens = parse_token_list(raw.get("exp_onlyperiod"));
+        only_period_tokens.retain(keep_token);
         let text_modes = TextModes {
             remove_punctuations: parse_bool(raw.get("if_nocomma")),
-            remove_tokens: parse_token_list(raw.get("exp_nocomma")),
+            remove_tokens,
             only_period: parse_bool(raw.get("if_onlyperiod")),
-            only_period_tokens: parse_token_list(raw.get("exp_onlyperiod")),
+            only_period_tokens,
             only_period_color: raw
                 .get("onlyperiod_color")
                 .filter(|s| !s.is_empty())
-                .map(|s| RgbColor::parse(s))
+                .map(RgbColor::parse)
                 .transpose()?,
         };
 
@@ -296,6 +454,13 @@ impl BookConfig {
                 raw.get("comment_comma_90_y"),
             )?,
             comment_strip_chars: parse_char_list_from_pipe(raw.get("comment_comma_nop")),
+            text_nop_hang: parse_bool(raw.get("text_comma_nop_hang")),
+        };
+
+        let line_break = LineBreakRules {
+            head: parse_char_set(raw.get("exp_line_head")),
+            tail: parse_char_set(raw.get("exp_line_tail")),
+            hang: parse_bool(raw.get("if_line_head_hang")),
         };
 
         let book_line_flag = parse_bool(raw.get("if_book_vline"));
@@ -314,19 +479,23 @@ impl BookConfig {
             canvas_id,
             row_num,
             row_delta_y,
+            layout_mode,
             multirows_horizontal_layout,
             fonts,
             try_st,
             text_font_color,
             comment_font_color,
+            annotations,
             cover,
             title_style,
             pager_style,
             replacements,
             text_modes,
             punctuation,
+            line_break,
             bookline,
             book_line_flag,
+            name_frame,
         })
     }
 
@@ -371,17 +540,8 @@ fn parse_font_mapping(raw: &RawConfig) -> Result<FontMapping> {
         }));
     }
 
-    let parse_stack = |raw_value: Option<&str>| -> Vec<usize> {
-        raw_value
-            .unwrap_or("")
-            .chars()
-            .filter_map(|c| c.to_digit(10).map(|d| d as usize))
-            .filter(|&idx| idx >= 1 && idx <= 5)
-            .collect()
-    };
-
-    let text_stack = parse_stack(raw.get("text_fonts_array"));
-    let comment_stack = parse_stack(raw.get("comment_fonts_array"));
+    let text_stack = parse_font_stack(raw.get("text_fonts_array"));
+    let comment_stack = parse_font_stack(raw.get("comment_fonts_array"));
 
     Ok(FontMapping {
         slots,
@@ -390,6 +550,103 @@ fn parse_font_mapping(raw: &RawConfig) -> Result<FontMapping> {
     })
 }
 
+fn parse_font_stack(value: Option<&str>) -> Vec<usize> {
+    value
+        .unwrap_or("")
+        .chars()
+        .filter_map(|c| c.to_digit(10).map(|d| d as usize))
+        .filter(|idx| (1..=5).contains(idx))
+        .collect()
+}
+
+/// 【】 之外的批注由 annotationN_tag 等键定义，N 取 1..=9，未设置的字体与颜色沿用批注设置
+fn parse_annotations(
+    raw: &RawConfig,
+    comment_stack: &[usize],
+    comment_color: RgbColor,
+) -> Result<AnnotationConfig> {
+    let mut styles = vec![AnnotationStyle {
+        open: '【',
+        close: '】',
+        stack: comment_stack.to_vec(),
+        font_size: None,
+        color: comment_color,
+    }];
+    for idx in 1..=9 {
+        let Some(tag) = raw
+            .get(&format!("annotation{idx}_tag"))
+            .filter(|s| !s.is_empty())
+        else {
+            continue;
+        };
+        let chars: Vec<char> = tag.chars().collect();
+        let [open, close] = chars[..] else {
+            return Err(anyhow!(
+                "annotation{idx}_tag must be an opening and a closing mark, got '{tag}'"
+            ));
+        };
+        let reserved = [ANNOTATION_LABEL_OPEN, ANNOTATION_LABEL_CLOSE];
+        if open == close
+            || reserved.contains(&open)
+            || reserved.contains(&close)
+            || styles.iter().any(|style| {
+                [style.open, style.close]
+                    .iter()
+                    .any(|c| *c == open || *c == close)
+            })
+        {
+            return Err(anyhow!(
+                "annotation{idx}_tag '{tag}' conflicts with another tag"
+            ));
+        }
+        let mut stack = parse_font_stack(raw.get(&format!("annotation{idx}_fonts_array")));
+        if stack.is_empty() {
+            stack = comment_stack.to_vec();
+        }
+        let font_size = raw
+            .get(&format!("annotation{idx}_font_size"))
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<f32>()
+                    .map_err(|_| anyhow!("annotation{idx}_font_size is not a number: {s}"))
+            })
+            .transpose()?;
+        styles.push(AnnotationStyle {
+            open,
+            close,
+            stack,
+            font_size,
+            color: parse_color(
+                raw.get(&format!("annotation{idx}_font_color")),
+                comment_color,
+            )?,
+        });
+    }
+    let columns = raw
+        .get("comment_col_num")
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse::<usize>()
+                .map_err(|_| anyhow!("comment_col_num is not a number: {s}"))
+        })
+        .transpose()?
+        .unwrap_or(2);
+    if !(1..=3).contains(&columns) {
+        return Err(anyhow!("comment_col_num must be 1, 2 or 3, got {columns}"));
+    }
+    Ok(AnnotationConfig {
+        styles,
+        columns,
+        balance: parse_bool(raw.get("if_comment_balance")),
+        footnote: parse_bool(raw.get("if_comment_footnote")),
+        label_color: raw
+            .get("comment_label_color")
+            .filter(|s| !s.is_empty())
+            .map(RgbColor::parse)
+            .transpose()?,
+    })
+}
+
 fn parse_color(value: Option<&str>, default: RgbColor) -> Result<RgbColor> {
     match value {
         Some(v) if !v.is_empty() => RgbColor::parse(v),
@@ -453,29 +710,88 @@ fn parse_char_list_from_pipe(value: Option<&str>) -> Vec<char> {
         .collect()
 }
 
+/// 以 | 分隔或直接连写的字符集合
+fn parse_char_set(value: Option<&str>) -> Vec<char> {
+    match value {
+        Some(raw) if raw.contains('|') => parse_char_list_from_pipe(value),
+        Some(raw) => raw.chars().collect(),
+        None => Vec::new(),
+    }
+}
+
 fn parse_mark_adjust(
     chars_raw: Option<&str>,
     scale_raw: Option<&str>,
     ox_raw: Option<&str>,
     oy_raw: Option<&str>,
 ) -> Result<MarkAdjust> {
-    let chars = if let Some(raw) = chars_raw {
-        if raw.contains('|') {
-            parse_char_list_from_pipe(chars_raw)
-        } else {
-            raw.chars().collect()
-        }
-    } else {
-        Vec::new()
-    };
     Ok(MarkAdjust {
-        chars,
+        chars: parse_char_set(chars_raw),
         scale: parse_f32(scale_raw, 1.0)?,
         offset_x: parse_f32(ox_raw, 0.0)?,
         offset_y: parse_f32(oy_raw, 0.0)?,
     })
 }
 
+#[derive(Debug, Clone)]
+pub struct FishTailConfig {
+    pub y: f32,
+    pub color: RgbColor,
+    pub rect_height: f32,
+    pub tria_height: f32,
+    pub line_width: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameConfig {
+    pub outline_width: f32,
+    pub outline_color: RgbColor,
+    pub outline_hmargin: f32,
+    pub outline_vmargin: f32,
+    pub inline_width: f32,
+    pub inline_color: RgbColor,
+    pub fish_flower: bool,
+    pub fish_top: FishTailConfig,
+    pub fish_btm: FishTailConfig,
+    pub fish_btm_upward: bool,
+    pub fish_line_color: RgbColor,
+    pub fish_line_width: f32,
+    pub fish_line_margin: f32,
+}
+
+/// 画布背景来源，按优先级自 canvas 配置解析
+#[derive(Debug, Clone, PartialEq)]
+pub enum CanvasBackground {
+    /// 已合成版框的整幅画布图（canvas/<canvas_id>.jpg|png）
+    Baked(PathBuf),
+    /// 纸张纹理（canvas_background_image），其上绘制矢量版框
+    Texture {
+        path: PathBuf,
+        color: Option<RgbColor>,
+    },
+    /// 纯色画布（canvas_color），其上绘制矢量版框
+    Solid(RgbColor),
+    /// 无可用背景，使用竹简纹理生成器兜底
+    Generated,
+}
+
+impl CanvasBackground {
+    /// 背景图本身不含版框时需由渲染器绘制
+    pub fn needs_frame(&self) -> bool {
+        !matches!(self, CanvasBackground::Baked(_))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogoConfig {
+    pub image: Option<String>,
+    pub text: Option<String>,
+    pub y: f32,
+    pub color: RgbColor,
+    pub font: Option<String>,
+    pub font_size: f32,
+}
+
 #[derive(Debug, Clone)]
 pub struct CanvasConfig {
     pub canvas_width: f32,
@@ -486,14 +802,26 @@ pub struct CanvasConfig {
     pub margins_right: f32,
     pub leaf_col: usize,
     pub leaf_center_width: f32,
-    pub logo_text: Option<String>,
+    pub logo: LogoConfig,
     pub multirows_enabled: bool,
     pub multirows_count: usize,
+    pub multirows_line_width: f32,
+    pub multirows_col_color: RgbColor,
+    pub frame: Option<FrameConfig>,
+    pub background: CanvasBackground,
 }
 
 impl CanvasConfig {
     pub fn load(path: impl AsRef<Path>) -> Result<Self> {
-        let raw = RawConfig::load(path)?;
+        Self::from_raw(&RawConfig::load(path)?)
+    }
+
+    /// 从内存中的画布配置文本构建；背景图按 source 所在目录与文件名查找，source 本身可不存在
+    pub fn parse(source: impl AsRef<Path>, content: &str) -> Result<Self> {
+        Self::from_raw(&RawConfig::parse(source, content))
+    }
+
+    pub fn from_raw(raw: &RawConfig) -> Result<Self> {
         let getf = |key: &str| -> Result<f32> { raw.parse_value::<f32>(key) };
         Ok(Self {
             canvas_width: getf("canvas_width")?,
@@ -504,12 +832,26 @@ impl CanvasConfig {
             margins_right: getf("margins_right")?,
             leaf_col: raw.parse_value::<usize>("leaf_col")?,
             leaf_center_width: getf("leaf_center_width")?,
-            logo_text: parse_optional_string(raw.get("logo_text")),
+            logo: LogoConfig {
+                image: parse_optional_string(raw.get("logo_image")),
+                text: parse_optional_string(raw.get("logo_text")),
+                y: parse_f32(raw.get("logo_y"), 0.0)?,
+                color: parse_color(raw.get("logo_color"), RgbColor::new_u8(255, 255, 255))?,
+                font: parse_optional_string(raw.get("logo_font")),
+                font_size: parse_f32(raw.get("logo_font_size"), 40.0)?,
+            },
             multirows_enabled: parse_bool(raw.get("if_multirows")),
             multirows_count: raw
                 .get("multirows_num")
                 .and_then(|s| s.parse::<usize>().ok())
                 .unwrap_or(1),
+            multirows_line_width: parse_f32(raw.get("multirows_linewidth"), 1.0)?,
+            multirows_col_color: parse_color(
+                raw.get("multirows_colcolor"),
+                RgbColor::new_u8(245, 245, 245),
+            )?,
+            frame: parse_frame(raw)?,
+            background: parse_background(raw)?,
         })
     }
 
@@ -537,3 +879,137 @@ impl CanvasConfig {
         Ok(())
     }
 }
+
+fn parse_frame(raw: &RawConfig) -> Result<Option<FrameConfig>> {
+    // 无边框参数的画布（如竹简）不绘制版框
+    if raw.get("outline_width").is_none() && raw.get("inline_width").is_none() {
+        return Ok(None);
+    }
+    let black = RgbColor::new_u8(0, 0, 0);
+    let parse_fish = |prefix: &str, default_y: f32| -> Result<FishTailConfig> {
+        Ok(FishTailConfig {
+            y: parse_f32(raw.get(&format!("{prefix}_y")), default_y)?,
+            color: parse_color(raw.get(&format!("{prefix}_color")), black)?,
+            rect_height: parse_f32(raw.get(&format!("{prefix}_rectheight")), 0.0)?,
+            tria_height: parse_f32(raw.get(&format!("{prefix}_triaheight")), 0.0)?,
+            line_width: parse_f32(raw.get(&format!("{prefix}_linewidth")), 0.0)?,
+        })
+    };
+    Ok(Some(FrameConfig {
+        outline_width: parse_f32(raw.get("outline_width"), 0.0)?,
+        outline_color: parse_color(raw.get("outline_color"), black)?,
+        outline_hmargin: parse_f32(raw.get("outline_hmargin"), 0.0)?,
+        outline_vmargin: parse_f32(raw.get("outline_vmargin"), 0.0)?,
+        inline_width: parse_f32(raw.get("inline_width"), 1.0)?,
+        inline_color: parse_color(raw.get("inline_color"), black)?,
+        fish_flower: parse_bool(raw.get("if_fishflower")),
+        fish_top: parse_fish("fish_top", 0.0)?,
+        fish_btm: parse_fish("fish_btm", 0.0)?,
+        fish_btm_upward: parse_bool(raw.get("fish_btm_direction")),
+        fish_line_color: parse_color(raw.get("fish_line_color"), black)?,
+        fish_line_width: parse_f32(raw.get("fish_line_width"), 1.0)?,
+        fish_line_margin: parse_f32(raw.get("fish_line_margin"), 0.0)?,
+    }))
+}
+
+/// 按画布名存放的预制背景图存在且未要求矢量版框时优先使用，其次为纹理、底色；
+/// 纹理文件是否存在留待 [`crate::background::load_canvas_layers`] 读取时判断
+fn parse_background(raw: &RawConfig) -> Result<CanvasBackground> {
+    let dir = raw.source.parent().unwrap_or_else(|| Path::new("."));
+    let color = raw
+        .get("canvas_color")
+        .filter(|s| !s.is_empty())
+        .map(RgbColor::parse)
+        .transpose()?;
+
+    if !parse_bool(raw.get("if_vector_frame"))
+        && let Some(stem) = raw.source.file_stem()
+        && let Some(baked) = ["jpg", "png"]
+            .iter()
+            .map(|ext| dir.join(stem).with_extension(ext))
+            .find(|path| path.exists())
+    {
+        return Ok(CanvasBackground::Baked(baked));
+    }
+    if let Some(name) = parse_optional_string(raw.get("canvas_background_image")) {
+        return Ok(CanvasBackground::Texture {
+            path: dir.join(name),
+            color,
+        });
+    }
+    Ok(match color {
+        Some(color) => CanvasBackground::Solid(color),
+        None => CanvasBackground::Generated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CanvasBackground, RawConfig, parse_background, parse_line};
+    use crate::color::RgbColor;
+
+    #[test]
+    fn parse_line_strips_inline_comments_after_hex_colors() {
+        assert_eq!(
+            parse_line("multirows_colcolor=#f5f5f5 #栏内细线颜色"),
+            Some(("multirows_colcolor".into(), "#f5f5f5".into()))
+        );
+        assert_eq!(
+            parse_line("canvas_color=#eeeeee"),
+            Some(("canvas_color".into(), "#eeeeee".into()))
+        );
+        assert_eq!(
+            parse_line("row_num=30 #每列字数"),
+            Some(("row_num".into(), "30".into()))
+        );
+        assert_eq!(parse_line("# comment"), None);
+    }
+
+    #[test]
+    fn apply_overrides_replaces_and_adds_entries() {
+        let mut raw = RawConfig::parse("book.cfg", "row_num=30 #每列字数\ntitle=史記\n");
+        raw.apply_overrides(["row_num=20", "canvas_color=#eeeeee #底色"])
+            .expect("apply overrides");
+        assert_eq!(raw.get("row_num"), Some("20"));
+        assert_eq!(raw.get("title"), Some("史記"));
+        assert_eq!(raw.get("canvas_color"), Some("#eeeeee"));
+        assert!(raw.apply_overrides(["row_num"]).is_err());
+    }
+
+    #[test]
+    fn baked_background_wins_unless_vector_frame_is_requested() {
+        // canvas/24_paper.jpg 随仓库提供，其配置同时设有纹理与底色
+        let paper = "canvas_background_image=paper.jpg\ncanvas_color=white\n";
+        let raw = RawConfig::parse("canvas/24_paper.cfg", paper);
+        assert!(matches!(
+            parse_background(&raw).expect("background"),
+            CanvasBackground::Baked(path) if path.ends_with("canvas/24_paper.jpg")
+        ));
+
+        let raw = RawConfig::parse(
+            "canvas/24_paper.cfg",
+            &format!("{paper}if_vector_frame=1\n"),
+        );
+        assert!(matches!(
+            parse_background(&raw).expect("background"),
+            CanvasBackground::Texture { path, color: Some(_) } if path.ends_with("canvas/paper.jpg")
+        ));
+
+        // 没有预制背景图时依次为纹理、底色
+        let raw = RawConfig::parse("canvas/no_such_canvas.cfg", paper);
+        assert!(matches!(
+            parse_background(&raw).expect("background"),
+            CanvasBackground::Texture { .. }
+        ));
+        let raw = RawConfig::parse("canvas/no_such_canvas.cfg", "canvas_color=white\n");
+        assert!(matches!(
+            parse_background(&raw).expect("background"),
+            CanvasBackground::Solid(color) if color == RgbColor::parse("white").expect("color")
+        ));
+        let raw = RawConfig::parse("canvas/no_such_canvas.cfg", "");
+        assert_eq!(
+            parse_background(&raw).expect("background"),
+            CanvasBackground::Generated
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! 测试共用的样例：以 books/01 的书籍配置排版一段文字，或载入其配置、画布、字体与数字表，
+//! 与只有一页的排版计划一同交给各输出后端
+
+use crate::backend::RenderContext;
+use crate::config::{BookConfig, CanvasConfig, RawConfig};
+use crate::fonts::FontManager;
+use crate::numerals::NumeralMap;
+use crate::plan::{CoverPlan, DocumentPlan, GlyphSpec, LineSpec, PagePlan, TypesetOptions};
+use crate::preprocess::TextCorpus;
+use crate::project::Project;
+use std::path::Path;
+
+pub struct Sample {
+    pub book: BookConfig,
+    pub canvas: CanvasConfig,
+    pub fonts: FontManager,
+    pub numerals: NumeralMap,
+}
+
+impl Sample {
+    /// 载入样例书籍；canvas_id 缺省时取书中所配的画布
+    pub fn load(canvas_id: Option<&str>) -> Self {
+        let book = BookConfig::load("books/01/book.cfg").expect("load sample book configuration");
+        let canvas_id = canvas_id.unwrap_or(&book.canvas_id);
+        let canvas = CanvasConfig::load(format!("canvas/{canvas_id}.cfg"))
+            .expect("load canvas configuration");
+        let fonts = FontManager::new(&book, Path::new("fonts")).expect("load fonts");
+        let numerals = NumeralMap::load("db/num2zh_jid.txt").expect("load numeral mapping");
+        Self {
+            book,
+            canvas,
+            fonts,
+            numerals,
+        }
+    }
+
+    /// 正文首选字体的槽位
+    pub fn text_font(&self) -> usize {
+        self.book.fonts.text_stack[0]
+    }
+
+    /// 无背景图、以矢量绘制版框的渲染上下文
+    pub fn context(&self) -> RenderContext<'_> {
+        RenderContext {
+            book: &self.book,
+            canvas: &self.canvas,
+            fonts: &self.fonts,
+            numerals: &self.numerals,
+            background: None,
+            background_color: None,
+            cover_image: None,
+            logo_image: None,
+            draw_frame: true,
+        }
+    }
+}
+
+/// 生成封面之后只有第 number 页的排版计划
+pub fn single_page(number: usize, glyphs: Vec<GlyphSpec>, lines: Vec<LineSpec>) -> DocumentPlan {
+    DocumentPlan {
+        cover: CoverPlan::Generated,
+        cover_path: None,
+        pages: vec![PagePlan {
+            number,
+            title: "测试".into(),
+            glyphs,
+            lines,
+        }],
+        outlines: Vec::new(),
+        missing: Vec::new(),
+    }
+}
+
+pub fn typeset_sample(overrides: &[&str], text: impl Fn(&BookConfig) -> String) -> DocumentPlan {
+    typeset_with_fonts(overrides, text, |_| {})
+}
+
+/// 按 fonts/ 加载字体后交 tweak 调整（如字体映射、竖排替换表），再排版 text
+pub fn typeset_with_fonts(
+    overrides: &[&str],
+    text: impl Fn(&BookConfig) -> String,
+    tweak: impl FnOnce(&mut FontManager),
+) -> DocumentPlan {
+    let mut raw = RawConfig::load("books/01/book.cfg").expect("load sample book configuration");
+    raw.apply_overrides(overrides.iter().copied())
+        .expect("apply overrides");
+    let book = BookConfig::from_raw(&raw).expect("parse book configuration");
+    let canvas = CanvasConfig::load(format!("canvas/{}.cfg", book.canvas_id))
+        .expect("load canvas configuration");
+    let corpus = TextCorpus::from_text(&book, &text(&book)).expect("build corpus");
+    let mut fonts = FontManager::new(&book, Path::new("fonts")).expect("load fonts");
+    tweak(&mut fonts);
+    let project = Project::builder()
+        .book(book)
+        .canvas(canvas)
+        .corpus(corpus)
+        .fonts(fonts)
+        .build()
+        .expect("build project");
+    project
+        .typeset(TypesetOptions::default())
+        .expect("typeset sample text")
+}
@@ -0,0 +1,642 @@
+//! 比例竖排：不设固定字格，字高取自字体竖排度量，不占位标点占半字高，按实际高度填满各列。
+//! 列的横向位置与页面尺寸沿用 [`Layout`](crate::layout::Layout)，输出同样的 `PagePlan`。
+
+use crate::fonts::LoadedFont;
+use crate::layout_engine::{CommentChar, FrameSpan, GlyphRole, LayoutEngine};
+use crate::plan::{GlyphSpec, LineSpec, PagePlan};
+use anyhow::{Result, anyhow};
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// 当前页的列序号与本列已用高度；横排时为行序号与本行已用宽度
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FlowCursor {
+    pub column: usize,
+    pub used: f32,
+}
+
+/// 排版中的页面及其计数，换列、换页时一并更新
+pub struct FlowPage<'p> {
+    pub current_page: &'p mut PagePlan,
+    pub pages: &'p mut Vec<PagePlan>,
+    pub generated_pages: &'p mut usize,
+    pub next_page_number: &'p mut usize,
+    pub title_text: &'p str,
+}
+
+/// `<…>` 名框状态：当前列（横排为当前行）中已排入的一段，换列、换页时先画出
+#[derive(Debug, Default)]
+pub struct FlowFrame {
+    active: bool,
+    span: Option<FrameSpan>,
+    /// 当前段接续上一列或上一页，起端不封口
+    continued: bool,
+}
+
+impl FlowFrame {
+    pub fn open(&mut self) {
+        self.active = true;
+        self.continued = false;
+    }
+
+    pub fn close(&mut self, engine: &LayoutEngine<'_>, page: &mut PagePlan) {
+        self.flush(engine, page, false);
+        self.active = false;
+    }
+
+    /// 名框内的字排入后，将本段延伸至该字；span 为该字所占的一段
+    pub fn extend(&mut self, span: FrameSpan) {
+        if !self.active {
+            return;
+        }
+        match &mut self.span {
+            Some(current) => current.end = span.end,
+            None => self.span = Some(span),
+        }
+    }
+
+    /// 画出本段；open_end 表示名框在下一列（行）或下一页继续
+    pub fn flush(&mut self, engine: &LayoutEngine<'_>, page: &mut PagePlan, open_end: bool) {
+        let Some(span) = self.span.take() else {
+            return;
+        };
+        engine.draw_name_frame(page, span, self.continued, open_end);
+        self.continued = open_end;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PieceKind {
+    Upright {
+        ink_height: f32,
+        ymin: f32,
+        width: f32,
+    },
+    Nop {
+        offset_x: f32,
+    },
+    Rotated {
+        offset_x: f32,
+    },
+    Space,
+}
+
+/// 已选定字体、量好高度的一个字
+#[derive(Debug, Clone)]
+struct Piece {
+    glyph: GlyphSpec,
+    advance: f32,
+    kind: PieceKind,
+    bookline: bool,
+}
+
+pub struct FlowEngine<'e, 'a> {
+    engine: &'e LayoutEngine<'a>,
+}
+
+impl<'e, 'a> FlowEngine<'e, 'a> {
+    pub fn new(engine: &'e LayoutEngine<'a>) -> Result<Self> {
+        if engine.layout.multirows_bands > 1 {
+            return Err(anyhow!("proportional layout does not support multirows"));
+        }
+        Ok(Self { engine })
+    }
+
+    pub fn process_entry(
+        &self,
+        entry: &str,
+        page: &mut FlowPage<'_>,
+        cursor: &mut FlowCursor,
+        bookline_active: &mut bool,
+    ) -> Result<()> {
+        let book = self.engine.book;
+        let columns = self.engine.layout.columns;
+        let mut chars = entry.chars().peekable();
+        let mut comment_queue: Vec<CommentChar> = Vec::new();
+        let mut frame = FlowFrame::default();
+
+        while let Some(ch) = chars.next() {
+            if let Some(style) = book.annotations.opening(ch) {
+                frame.flush(self.engine, page.current_page, true);
+                self.engine
+                    .collect_annotation(style, &mut chars, &mut comment_queue);
+                let items = std::mem::take(&mut comment_queue);
+                if self.render_comments(&items, page, cursor, &mut frame) {
+                    break;
+                }
+                continue;
+            }
+            match ch {
+                '\r' | '^' => continue,
+                '\n' => {
+                    if cursor.used > 0.0 && self.next_column(page, cursor, &mut frame) {
+                        break;
+                    }
+                    continue;
+                }
+                '%' => {
+                    if self.next_page(page, cursor, &mut frame) {
+                        break;
+                    }
+                    continue;
+                }
+                '$' => {
+                    let half = columns / 2;
+                    let at_start =
+                        cursor.used == 0.0 && (cursor.column == 0 || cursor.column == half);
+                    if at_start {
+                        continue;
+                    }
+                    if cursor.column < half {
+                        frame.flush(self.engine, page.current_page, true);
+                        *cursor = FlowCursor {
+                            column: half,
+                            used: 0.0,
+                        };
+                    } else if self.next_page(page, cursor, &mut frame) {
+                        break;
+                    }
+                    continue;
+                }
+                '&' => {
+                    if cursor.column + 1 < columns {
+                        frame.flush(self.engine, page.current_page, true);
+                        *cursor = FlowCursor {
+                            column: columns - 1,
+                            used: 0.0,
+                        };
+                    }
+                    continue;
+                }
+                '《' | '》' => {
+                    *bookline_active = ch == '《';
+                    if book.book_line_flag {
+                        continue;
+                    }
+                }
+                '<' if self.engine.is_name_frame_char(ch) => {
+                    frame.open();
+                    continue;
+                }
+                '>' if self.engine.is_name_frame_char(ch) => {
+                    frame.close(self.engine, page.current_page);
+                    continue;
+                }
+                _ => {}
+            }
+
+            let Some(mut piece) = self.measure(ch, GlyphRole::Text) else {
+                continue;
+            };
+            piece.bookline = *bookline_active && book.book_line_flag;
+            let overflows = cursor.used > 0.0 && cursor.used + piece.advance > self.column_height();
+            let moves = if overflows {
+                !self.hangs(ch, cursor)
+            } else {
+                self.breaks_before(ch, &piece, &chars, cursor)
+            };
+            if moves && self.next_column(page, cursor, &mut frame) {
+                break;
+            }
+            let box_top = self.column_top() - cursor.used;
+            let box_bottom = box_top - piece.advance;
+            let x = self.column_x(cursor.column);
+            let cw = self.engine.layout.cw;
+            self.place(&piece, x, cw, box_bottom, page.current_page);
+            if let Some(cfg) = &book.name_frame {
+                let inset = cfg.width / 2.0;
+                frame.extend(FrameSpan {
+                    start: box_top,
+                    end: box_bottom,
+                    lo: x + inset,
+                    hi: x + cw - inset,
+                    vertical: true,
+                });
+            }
+            cursor.used += piece.advance;
+        }
+        frame.flush(self.engine, page.current_page, true);
+        Ok(())
+    }
+
+    /// 悬挂模式下，放不下的列首禁则字画在本列末字之下，每列至多悬挂一字
+    fn hangs(&self, ch: char, cursor: &FlowCursor) -> bool {
+        let rules = &self.engine.book.line_break;
+        rules.hang && rules.head.contains(&ch) && cursor.used <= self.column_height()
+    }
+
+    /// 行首、行尾禁则：本字将是列末字，而它不宜居列尾，或下一字不宜居列首时，移至下一列
+    fn breaks_before(
+        &self,
+        ch: char,
+        piece: &Piece,
+        chars: &Peekable<Chars<'_>>,
+        cursor: &FlowCursor,
+    ) -> bool {
+        let rules = &self.engine.book.line_break;
+        if cursor.used == 0.0 || (rules.head.is_empty() && rules.tail.is_empty()) {
+            return false;
+        }
+        let Some(next) = self.engine.next_slot_char(chars) else {
+            return false;
+        };
+        let Some(next_piece) = self.measure(next, GlyphRole::Text) else {
+            return false;
+        };
+        let rest = self.column_height() - cursor.used - piece.advance;
+        if next_piece.advance <= rest {
+            return false;
+        }
+        if rules.tail.contains(&ch) {
+            return true;
+        }
+        !rules.hang && !rules.head.contains(&ch) && rules.head.contains(&next)
+    }
+
+    /// 批注分为若干小行，末段各小行高度尽量相等；返回是否已达试排页数
+    fn render_comments(
+        &self,
+        items: &[CommentChar],
+        page: &mut FlowPage<'_>,
+        cursor: &mut FlowCursor,
+        frame: &mut FlowFrame,
+    ) -> bool {
+        let book = self.engine.book;
+        let mut bookline = false;
+        let mut pieces = Vec::with_capacity(items.len());
+        for item in items {
+            if self.engine.should_skip_bookline_char(item.ch) {
+                bookline = item.ch == '《';
+                continue;
+            }
+            if self.engine.is_name_frame_char(item.ch) {
+                continue;
+            }
+            if let Some(mut piece) = self.measure(item.ch, self.engine.comment_role(*item)) {
+                piece.bookline = bookline && book.book_line_flag;
+                pieces.push(piece);
+            }
+        }
+
+        let sub_columns = self.engine.layout.comment_columns;
+        let sub_width = self.engine.layout.comment_cw();
+        let mut rest = &pieces[..];
+        while !rest.is_empty() {
+            let avail = self.column_height() - cursor.used;
+            let total: f32 = rest.iter().map(|piece| piece.advance).sum();
+            let mut subs = balanced_split(rest, sub_columns, total, avail)
+                .unwrap_or_else(|| greedy_split(rest, sub_columns, avail));
+            if subs.iter().sum::<usize>() == 0 {
+                if cursor.used > 0.0 {
+                    if self.next_column(page, cursor, frame) {
+                        return true;
+                    }
+                    continue;
+                }
+                // 单字高过整列，只能越界放置
+                subs[0] = 1;
+            }
+            let taken: usize = subs.iter().sum();
+
+            let top = self.column_top() - cursor.used;
+            let column_x = self.column_x(cursor.column);
+            let mut offset = 0usize;
+            let mut tallest = 0.0f32;
+            for (sub, count) in subs.iter().enumerate() {
+                let x = column_x + (sub_columns - 1 - sub) as f32 * sub_width;
+                let mut used = 0.0;
+                for piece in &rest[offset..offset + count] {
+                    used += piece.advance;
+                    self.place(piece, x, sub_width, top - used, page.current_page);
+                }
+                tallest = tallest.max(used);
+                offset += count;
+            }
+            rest = &rest[taken..];
+            cursor.used += tallest;
+            if !rest.is_empty() && self.next_column(page, cursor, frame) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// 竖排前进高度：优先取字体的竖排度量（vmtx）；缺失时全角字占一字高，
+    /// 其余按字形墨迹高度估算。不占位标点占半字高，旋转标点取横排前进宽度；
+    /// 字体自带竖排字形的标点不旋转，按竖排字形度量；外字图片与拼合字按一字见方
+    fn measure(&self, ch: char, role: GlyphRole<'_>) -> Option<Piece> {
+        let is_comment = matches!(role, GlyphRole::Comment { .. });
+        let punctuation = &self.engine.book.punctuation;
+        let (is_nop, is_rot) = if is_comment {
+            (
+                punctuation.comment_nop.chars.contains(&ch),
+                punctuation.comment_rotate.chars.contains(&ch),
+            )
+        } else {
+            (
+                punctuation.text_nop.chars.contains(&ch),
+                punctuation.text_rotate.chars.contains(&ch),
+            )
+        };
+        let (mut glyph, font) = self.engine.styled_glyph(ch, role)?;
+        let size = glyph.font_size;
+        let vertical = if is_nop {
+            None
+        } else {
+            font.vertical_glyph(&glyph)
+        };
+        if vertical.is_some() {
+            glyph.glyph_id = vertical;
+        }
+        let gid = font.glyph_index(&glyph);
+
+        let (advance, kind) = if ch == ' ' {
+            (size, PieceKind::Space)
+        } else if glyph.is_synthetic() {
+            // 外字图片与拼合字占满一字见方的字身
+            let descent = font
+                .font
+                .horizontal_line_metrics(size)
+                .map_or(0.0, |metrics| metrics.descent);
+            (
+                size,
+                PieceKind::Upright {
+                    ink_height: size,
+                    ymin: descent,
+                    width: size,
+                },
+            )
+        } else if is_nop {
+            let adj = self.engine.nop_adjust(is_comment);
+            glyph.font_size *= adj.scale;
+            (
+                size / 2.0,
+                PieceKind::Nop {
+                    offset_x: adj.offset_x,
+                },
+            )
+        } else if is_rot && vertical.is_none() {
+            let adj = self.engine.rotate_adjust(is_comment);
+            glyph.font_size *= adj.scale;
+            glyph.rotate_deg = -90.0;
+            let metrics = font.font.metrics(glyph.ch, glyph.font_size);
+            (
+                metrics.advance_width.max(size / 2.0),
+                PieceKind::Rotated {
+                    offset_x: adj.offset_x,
+                },
+            )
+        } else {
+            let metrics = font.font.metrics_indexed(gid, size);
+            (
+                vertical_advance(font, gid, size),
+                PieceKind::Upright {
+                    ink_height: metrics.height as f32,
+                    ymin: metrics.ymin as f32,
+                    width: metrics.advance_width,
+                },
+            )
+        };
+        Some(Piece {
+            glyph,
+            advance,
+            kind,
+            bookline: false,
+        })
+    }
+
+    /// 将字放入自 x 起、宽 width 的列中，底边为 box_bottom 的字框
+    fn place(&self, piece: &Piece, x: f32, width: f32, box_bottom: f32, page: &mut PagePlan) {
+        let mut glyph = piece.glyph.clone();
+        match piece.kind {
+            PieceKind::Space => return,
+            PieceKind::Upright {
+                ink_height,
+                ymin,
+                width: advance_width,
+            } => {
+                glyph.x = x + (width - advance_width) / 2.0;
+                glyph.y = box_bottom + (piece.advance - ink_height) / 2.0 - ymin;
+            }
+            PieceKind::Nop { offset_x } => {
+                glyph.x = x + width * offset_x;
+                glyph.y = box_bottom;
+            }
+            PieceKind::Rotated { offset_x } => {
+                glyph.x = x + width * offset_x;
+                glyph.y = box_bottom + piece.advance;
+            }
+        }
+        page.glyphs.push(glyph);
+        if piece.bookline
+            && let Some(bline) = &self.engine.book.bookline
+        {
+            page.lines.push(LineSpec {
+                x1: x - bline.width,
+                x2: x - bline.width,
+                y1: box_bottom,
+                y2: box_bottom + piece.advance,
+                width: bline.width,
+                color: bline.color,
+                wavy: true,
+            });
+        }
+    }
+
+    /// 换到下一列，末列之后换页；返回是否已达试排页数
+    fn next_column(
+        &self,
+        page: &mut FlowPage<'_>,
+        cursor: &mut FlowCursor,
+        frame: &mut FlowFrame,
+    ) -> bool {
+        if cursor.column + 1 < self.engine.layout.columns {
+            frame.flush(self.engine, page.current_page, true);
+            cursor.column += 1;
+            cursor.used = 0.0;
+            return false;
+        }
+        self.next_page(page, cursor, frame)
+    }
+
+    fn next_page(
+        &self,
+        page: &mut FlowPage<'_>,
+        cursor: &mut FlowCursor,
+        frame: &mut FlowFrame,
+    ) -> bool {
+        frame.flush(self.engine, page.current_page, true);
+        self.engine.finalize_page(
+            page.current_page,
+            page.pages,
+            &mut 0,
+            page.generated_pages,
+            page.next_page_number,
+            page.title_text,
+        );
+        *cursor = FlowCursor::default();
+        self.engine.reached_limit(*page.generated_pages)
+    }
+
+    fn column_x(&self, column: usize) -> f32 {
+        let layout = self.engine.layout;
+        layout.pos_l[column * layout.rows_per_column + 1].x
+    }
+
+    fn column_top(&self) -> f32 {
+        self.engine.layout.canvas_height - self.engine.layout.margins_top
+    }
+
+    fn column_height(&self) -> f32 {
+        self.column_top() - self.engine.layout.margins_bottom - self.engine.book.row_delta_y
+    }
+}
+
+fn vertical_advance(font: &LoadedFont, gid: u16, size: f32) -> f32 {
+    let metrics = font.font.metrics_indexed(gid, size);
+    if metrics.advance_height > 0.0 {
+        metrics.advance_height
+    } else if metrics.advance_width >= size * 0.9 {
+        size
+    } else {
+        (metrics.height as f32 + size * 0.125).clamp(size / 2.0, size)
+    }
+}
+
+/// 依次填满各小行，每行高度不超过 limit；返回各小行字数
+fn greedy_split(pieces: &[Piece], sub_columns: usize, limit: f32) -> Vec<usize> {
+    let mut counts = Vec::with_capacity(sub_columns);
+    let mut offset = 0usize;
+    for _ in 0..sub_columns {
+        let mut used = 0.0;
+        let mut count = 0usize;
+        while let Some(piece) = pieces.get(offset + count) {
+            if used + piece.advance > limit + f32::EPSILON {
+                break;
+            }
+            used += piece.advance;
+            count += 1;
+        }
+        counts.push(count);
+        offset += count;
+    }
+    counts
+}
+
+/// 余下的字能在本列放完时，取能放下全部字的最小行高，使各小行高度尽量相等
+fn balanced_split(
+    pieces: &[Piece],
+    sub_columns: usize,
+    total: f32,
+    avail: f32,
+) -> Option<Vec<usize>> {
+    if total > avail * sub_columns as f32 {
+        return None;
+    }
+    let mut candidates: Vec<f32> = pieces
+        .iter()
+        .scan(0.0, |height, piece| {
+            *height += piece.advance;
+            Some(*height)
+        })
+        .filter(|&height| height * sub_columns as f32 >= total - f32::EPSILON && height <= avail)
+        .collect();
+    candidates.sort_by(f32::total_cmp);
+    candidates.into_iter().find_map(|limit| {
+        let counts = greedy_split(pieces, sub_columns, limit);
+        (counts.iter().sum::<usize>() == pieces.len()).then_some(counts)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::typeset_sample;
+    use crate::plan::{DocumentPlan, GlyphSpec};
+
+    fn typeset(mode: &str, overrides: &[&str], text: &str) -> DocumentPlan {
+        let mode = format!("layout_mode={mode}");
+        let overrides: Vec<&str> = [mode.as_str()]
+            .into_iter()
+            .chain(overrides.iter().copied())
+            .collect();
+        typeset_sample(&overrides, |_| text.to_string())
+    }
+
+    #[test]
+    fn proportional_mode_packs_narrow_glyphs_tighter_than_the_grid() {
+        let text = "iiiiii\nAB【cd】";
+        let grid = typeset("grid", &[], text);
+        let flow = typeset("proportional", &[], text);
+        let glyph = |plan: &DocumentPlan, ch: char| {
+            plan.pages[0]
+                .glyphs
+                .iter()
+                .filter(|g| g.ch == ch)
+                .map(|g| (g.x, g.y))
+                .collect::<Vec<_>>()
+        };
+        let span = |plan| {
+            let ys = glyph(plan, 'i');
+            ys[0].1 - ys[ys.len() - 1].1
+        };
+        assert!(span(&flow) < span(&grid));
+        // 换行另起一列，批注两小行并排
+        assert!(glyph(&flow, 'A')[0].0 < glyph(&flow, 'i')[0].0);
+        let (c, d) = (glyph(&flow, 'c')[0], glyph(&flow, 'd')[0]);
+        assert!(d.0 < c.0);
+        assert!((d.1 - c.1).abs() < 30.0);
+    }
+
+    #[test]
+    fn proportional_mode_draws_name_frames() {
+        let plan = typeset("proportional", &["if_name_frame=1"], "A<BC>D");
+        let page = &plan.pages[0];
+        // 一段名框：左右两边及首尾封口
+        assert_eq!(page.lines.len(), 4);
+        assert!(page.glyphs.iter().all(|g| g.ch != '<' && g.ch != '>'));
+        let glyph = |ch: char| page.glyphs.iter().find(|g| g.ch == ch).expect("glyph");
+        let top = page
+            .lines
+            .iter()
+            .map(|l| l.y1.max(l.y2))
+            .fold(f32::MIN, f32::max);
+        let bottom = page
+            .lines
+            .iter()
+            .map(|l| l.y1.min(l.y2))
+            .fold(f32::MAX, f32::min);
+        assert!(top < glyph('A').y + glyph('A').font_size);
+        assert!(bottom <= glyph('C').y && bottom > glyph('D').y);
+    }
+
+    #[test]
+    fn proportional_mode_keeps_marks_off_column_edges() {
+        let first_column = |page: &[GlyphSpec], ch: char| {
+            let first = &page[0];
+            let glyph = page.iter().rev().find(|g| g.ch == ch).expect("glyph");
+            (glyph.x - first.x).abs() < first.font_size / 2.0
+        };
+        let full = typeset("proportional", &[], &"A".repeat(300));
+        let glyphs = &full.pages[0].glyphs;
+        let per_column = glyphs
+            .iter()
+            .take_while(|g| (g.x - glyphs[0].x).abs() < g.font_size / 2.0)
+            .count();
+        let filler = "A".repeat(per_column - 1);
+        let column_of = |overrides: &[&str], tail: &str, ch: char| {
+            let plan = typeset("proportional", overrides, &format!("{filler}{tail}"));
+            first_column(&plan.pages[0].glyphs, ch)
+        };
+
+        // 无禁则时 Y、B 为列末字，C、Z 起下一列
+        assert!(column_of(&[], "YC", 'Y') && !column_of(&[], "YC", 'C'));
+        assert!(column_of(&[], "BZ", 'B') && !column_of(&[], "BZ", 'Z'));
+        // 列尾禁则字、列首禁则字之前的字移至下一列
+        assert!(!column_of(&["exp_line_tail=Y"], "YC", 'Y'));
+        assert!(!column_of(&["exp_line_head=Z"], "BZ", 'B'));
+        // 悬挂模式下列首禁则字留在本列，但只悬挂一字
+        let hang = ["exp_line_head=Z", "if_line_head_hang=1"];
+        assert!(column_of(&hang, "BZ", 'Z'));
+        assert!(!column_of(&hang, "BZZ", 'Z'));
+    }
+}
@@ -1,10 +1,17 @@
 #![allow(dead_code)]
 
-use crate::config::{BookConfig, FontSlot};
+use crate::clusters::is_variation_selector;
+use crate::config::{BookConfig, CanvasConfig, FontSlot};
+use crate::plan::GlyphSpec;
 use anyhow::{Context, Result, anyhow};
 use fontdue::Font;
+use printpdf::ParsedFont;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
+use ttf_parser::gsub::{SingleSubstitution, SubstitutionSubtable};
+use ttf_parser::opentype_layout::Coverage;
+use ttf_parser::{Face, Tag};
 
 #[derive(Debug, Clone)]
 pub struct LoadedFont {
@@ -12,6 +19,245 @@ pub struct LoadedFont {
     pub data: Vec<u8>,
     pub font: Font,
     pub path: PathBuf,
+    /// GSUB vert/vrt2 提供的竖排替换字形，以字形号对应
+    pub vertical: HashMap<u16, u16>,
+}
+
+impl LoadedFont {
+    /// 由字体文件数据构建；path 用于报错与按文件去重，内存字体可传入字体名
+    pub fn from_bytes(slot: FontSlot, data: Vec<u8>, path: PathBuf) -> Result<Self> {
+        let font = Font::from_bytes(data.clone(), fontdue::FontSettings::default())
+            .map_err(|err| anyhow!("{}: {}", slot.name, err))?;
+        let vertical = vertical_substitutions(&data);
+        Ok(Self {
+            slot,
+            data,
+            font,
+            path,
+            vertical,
+        })
+    }
+
+    /// glyph 所用字形号：整形所得字形号优先，否则按 ch 查找
+    pub fn glyph_index(&self, glyph: &GlyphSpec) -> u16 {
+        glyph
+            .glyph_id
+            .unwrap_or_else(|| self.font.lookup_glyph_index(glyph.ch))
+    }
+
+    /// glyph 的竖排替换字形号；字体无此字或未提供竖排字形时为 None
+    pub fn vertical_glyph(&self, glyph: &GlyphSpec) -> Option<u16> {
+        match self.glyph_index(glyph) {
+            0 => None,
+            gid => self.vertical.get(&gid).copied(),
+        }
+    }
+
+    /// 字体是否完整收录字形簇：各字均有字形，异体字选择符须有对应的异体字形
+    pub fn covers_cluster(&self, cluster: &str) -> bool {
+        let Ok(face) = Face::parse(&self.data, 0) else {
+            return false;
+        };
+        let mut base = None;
+        cluster.chars().all(|ch| {
+            if is_variation_selector(ch) {
+                base.is_some_and(|base| face.glyph_variation_index(base, ch).is_some())
+            } else {
+                base = Some(ch);
+                self.font.lookup_glyph_index(ch) != 0
+            }
+        })
+    }
+
+    /// 以 rustybuzz 横向整形字形簇，返回各字形及其相对首字形原点的偏移（以 em 计）；
+    /// 缺字的 .notdef 不计入
+    pub fn shape(&self, cluster: &str) -> Vec<ShapedGlyph> {
+        let Some(face) = rustybuzz::Face::from_slice(&self.data, 0) else {
+            return Vec::new();
+        };
+        let mut buffer = rustybuzz::UnicodeBuffer::new();
+        buffer.push_str(cluster);
+        buffer.guess_segment_properties();
+        buffer.set_direction(rustybuzz::Direction::LeftToRight);
+        let output = rustybuzz::shape(&face, &[], buffer);
+        let em = face.units_per_em() as f32;
+        let mut pen = 0;
+        let mut glyphs = Vec::new();
+        for (info, pos) in output.glyph_infos().iter().zip(output.glyph_positions()) {
+            if info.glyph_id != 0 {
+                glyphs.push(ShapedGlyph {
+                    glyph_id: info.glyph_id as u16,
+                    dx: (pen + pos.x_offset) as f32 / em,
+                    dy: pos.y_offset as f32 / em,
+                });
+            }
+            pen += pos.x_advance;
+        }
+        if let Some(&first) = glyphs.first() {
+            for glyph in &mut glyphs {
+                glyph.dx -= first.dx;
+                glyph.dy -= first.dy;
+            }
+        }
+        glyphs
+    }
+
+    /// 解析为 printpdf 字体，供子集化使用
+    pub fn parse(&self) -> Result<ParsedFont> {
+        let mut warnings = Vec::new();
+        ParsedFont::from_bytes(&self.data, 0, &mut warnings)
+            .ok_or_else(|| anyhow!("failed to parse font '{}'", self.slot.name))
+    }
+}
+
+/// 整形所得的一个字形，偏移相对首字形原点，以 em 为单位
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShapedGlyph {
+    pub glyph_id: u16,
+    pub dx: f32,
+    pub dy: f32,
+}
+
+/// 收集 GSUB 中 vert 与 vrt2 特性的单字替换，vrt2 覆盖 vert；无 GSUB 或解析失败时为空
+fn vertical_substitutions(data: &[u8]) -> HashMap<u16, u16> {
+    let mut map = HashMap::new();
+    let Some(gsub) = Face::parse(data, 0)
+        .ok()
+        .and_then(|face| face.tables().gsub)
+    else {
+        return map;
+    };
+    for tag in [b"vert", b"vrt2"] {
+        let tag = Tag::from_bytes(tag);
+        for feature in gsub
+            .features
+            .into_iter()
+            .filter(|feature| feature.tag == tag)
+        {
+            for lookup_index in feature.lookup_indices {
+                let Some(lookup) = gsub.lookups.get(lookup_index) else {
+                    continue;
+                };
+                for subtable in lookup.subtables.into_iter::<SubstitutionSubtable>() {
+                    if let SubstitutionSubtable::Single(single) = subtable {
+                        collect_single_substitution(single, &mut map);
+                    }
+                }
+            }
+        }
+    }
+    map
+}
+
+fn collect_single_substitution(single: SingleSubstitution<'_>, map: &mut HashMap<u16, u16>) {
+    // 被替换的字形及其覆盖序号，Format2 的替换字形按覆盖序号排列
+    let covered: Vec<(u16, u16)> = match single.coverage() {
+        Coverage::Format1 { glyphs } => (0u16..).zip(glyphs.into_iter().map(|gid| gid.0)).collect(),
+        Coverage::Format2 { records } => records
+            .into_iter()
+            .flat_map(|record| {
+                (record.start.0..=record.end.0)
+                    .map(move |gid| (record.value.wrapping_add(gid - record.start.0), gid))
+            })
+            .collect(),
+    };
+    for (index, gid) in covered {
+        let substitute = match single {
+            SingleSubstitution::Format1 { delta, .. } => Some(gid.wrapping_add_signed(delta)),
+            SingleSubstitution::Format2 { substitutes, .. } => {
+                substitutes.get(index).map(|gid| gid.0)
+            }
+        };
+        if let Some(substitute) = substitute {
+            map.insert(gid, substitute);
+        }
+    }
+}
+
+/// 生成仅含 chars 所用字形的字体子集（保留 .notdef，cmap 随之重建）；
+/// 字体中一个所需字形都没有时返回 None
+pub fn subset_font(parsed: &ParsedFont, chars: &BTreeSet<char>) -> Result<Option<Vec<u8>>> {
+    let mut glyphs = vec![(0u16, '\0')];
+    glyphs.extend(
+        chars
+            .iter()
+            .filter_map(|&ch| parsed.lookup_glyph_index(ch as u32).map(|gid| (gid, ch))),
+    );
+    glyphs.sort();
+    glyphs.dedup_by_key(|glyph| glyph.0);
+    if glyphs.len() == 1 {
+        return Ok(None);
+    }
+    parsed
+        .subset(&glyphs)
+        .map(|subset| Some(subset.bytes))
+        .map_err(|err| anyhow!("font subsetting failed: {err}"))
+}
+
+/// 字体映射文件名，位于书籍目录中
+pub const FONT_MAP_FILE: &str = "font_map.txt";
+
+/// 书籍指定的各字首选字体槽位（1-5），如某字须用 HanaMinB 的字形而前面的字体也收有此字
+#[derive(Debug, Clone, Default)]
+pub struct FontMap {
+    slots: HashMap<char, usize>,
+}
+
+impl FontMap {
+    /// 读取映射文件，文件不存在时为空
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut map = Self::default();
+        if path.is_file() {
+            let content =
+                fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+            map.parse(&content)
+                .with_context(|| path.display().to_string())?;
+        }
+        Ok(map)
+    }
+
+    /// 解析 `字|槽位` 格式的映射，左侧为 `U+5F0C` 码位或一个以上的原字；`#` 起首的行为注释
+    pub fn parse(&mut self, content: &str) -> Result<()> {
+        for (number, line) in content.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let (key, slot) = trimmed
+                .split_once('|')
+                .map(|(key, slot)| (key.trim(), slot.trim()))
+                .filter(|(key, _)| !key.is_empty())
+                .ok_or_else(|| anyhow!("line {}: expected CHARS|SLOT", number + 1))?;
+            let slot = slot
+                .parse::<usize>()
+                .ok()
+                .filter(|slot| (1..=5).contains(slot))
+                .ok_or_else(|| anyhow!("line {}: font slot must be 1-5", number + 1))?;
+            match key.strip_prefix("U+").or_else(|| key.strip_prefix("u+")) {
+                Some(hex) => {
+                    let ch = u32::from_str_radix(hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| anyhow!("line {}: invalid code point {key}", number + 1))?;
+                    self.insert(ch, slot);
+                }
+                None => key.chars().for_each(|ch| self.insert(ch, slot)),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn insert(&mut self, ch: char, slot: usize) {
+        self.slots.insert(ch, slot);
+    }
+
+    pub fn get(&self, ch: char) -> Option<usize> {
+        self.slots.get(&ch).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
 }
 
 #[derive(Debug)]
@@ -19,24 +265,32 @@ pub struct FontManager {
     pub slots: Vec<Option<LoadedFont>>,
     pub text_stack: Vec<usize>,
     pub comment_stack: Vec<usize>,
+    /// 各字首选的字体槽位，在所属字体序列中优先选用
+    pub font_map: FontMap,
+    /// 书房名字体，独立于书籍的五个字体槽位
+    pub logo: Option<LoadedFont>,
 }
 
 impl FontManager {
     pub fn new(book: &BookConfig, fonts_root: &Path) -> Result<Self> {
+        Self::from_loader(book, |name| {
+            let path = fonts_root.join(name);
+            let data =
+                fs::read(&path).with_context(|| format!("loading font {}", path.display()))?;
+            Ok((path, data))
+        })
+    }
+
+    /// 按 book 的字体槽位逐个调用 load(字体名) 取得 (路径, 字体数据)，便于从内存或其他来源加载
+    pub fn from_loader(
+        book: &BookConfig,
+        mut load: impl FnMut(&str) -> Result<(PathBuf, Vec<u8>)>,
+    ) -> Result<Self> {
         let mut slots = Vec::with_capacity(book.fonts.slots.len());
         for slot in &book.fonts.slots {
             if let Some(slot_info) = slot {
-                let path = fonts_root.join(&slot_info.name);
-                let data =
-                    fs::read(&path).with_context(|| format!("loading font {}", path.display()))?;
-                let font = Font::from_bytes(data.clone(), fontdue::FontSettings::default())
-                    .map_err(|err| anyhow!("{}: {}", slot_info.name, err))?;
-                slots.push(Some(LoadedFont {
-                    slot: slot_info.clone(),
-                    data,
-                    font,
-                    path,
-                }));
+                let (path, data) = load(&slot_info.name)?;
+                slots.push(Some(LoadedFont::from_bytes(slot_info.clone(), data, path)?));
             } else {
                 slots.push(None);
             }
@@ -45,9 +299,29 @@ impl FontManager {
             slots,
             text_stack: book.fonts.text_stack.clone(),
             comment_stack: book.fonts.comment_stack.clone(),
+            font_map: FontMap::default(),
+            logo: None,
         })
     }
 
+    pub fn load_logo_font(&mut self, canvas: &CanvasConfig, fonts_root: &Path) -> Result<()> {
+        let logo = &canvas.logo;
+        let (Some(_), Some(name)) = (&logo.text, &logo.font) else {
+            return Ok(());
+        };
+        let path = fonts_root.join(name);
+        let data = fs::read(&path).with_context(|| format!("loading font {}", path.display()))?;
+        let slot = FontSlot {
+            id: 0,
+            name: name.clone(),
+            rotate_deg: 0.0,
+            text_size: logo.font_size,
+            comment_size: logo.font_size,
+        };
+        self.logo = Some(LoadedFont::from_bytes(slot, data, path)?);
+        Ok(())
+    }
+
     pub fn font(&self, idx: usize) -> Option<&LoadedFont> {
         if idx == 0 {
             return None;
@@ -61,19 +335,58 @@ impl FontManager {
             .unwrap_or(false)
     }
 
+    /// 按 stack 的次序为 ch 选字体；字体映射指定的槽位在 stack 中时先试该槽位
     pub fn pick_font(&self, ch: char, stack: &[usize]) -> Option<FontPick<'_>> {
-        for &idx in stack {
-            if self.has_glyph(idx, ch) {
-                if let Some(font) = self.font(idx) {
-                    return Some(FontPick {
-                        font,
-                        slot_index: idx,
-                    });
-                }
+        for idx in self.preferred_order(ch, stack) {
+            if self.has_glyph(idx, ch)
+                && let Some(font) = self.font(idx)
+            {
+                return Some(FontPick {
+                    font,
+                    slot_index: idx,
+                });
             }
         }
         None
     }
+
+    /// stack 中第一个已加载的字体，供不按字选字体的外字图片定字号
+    pub fn first_font(&self, stack: &[usize]) -> Option<FontPick<'_>> {
+        stack.iter().find_map(|&idx| {
+            self.font(idx).map(|font| FontPick {
+                font,
+                slot_index: idx,
+            })
+        })
+    }
+
+    /// 为字形簇选字体：先找完整收录的字体（含所选异体），没有时退而取有基字的字体；
+    /// 次序同 [`FontManager::pick_font`]，以基字查字体映射
+    pub fn pick_cluster(&self, cluster: &str, stack: &[usize]) -> Option<FontPick<'_>> {
+        let base = cluster.chars().next()?;
+        let fonts = || {
+            self.preferred_order(base, stack)
+                .filter_map(|idx| self.font(idx).map(|font| (idx, font)))
+        };
+        fonts()
+            .find(|(_, font)| font.covers_cluster(cluster))
+            .or_else(|| fonts().find(|(_, font)| font.font.lookup_glyph_index(base) != 0))
+            .map(|(slot_index, font)| FontPick { font, slot_index })
+    }
+
+    fn preferred_order<'s>(
+        &self,
+        ch: char,
+        stack: &'s [usize],
+    ) -> impl Iterator<Item = usize> + 's {
+        let preferred = self.font_map.get(ch).filter(|slot| stack.contains(slot));
+        preferred.into_iter().chain(
+            stack
+                .iter()
+                .copied()
+                .filter(move |&idx| Some(idx) != preferred),
+        )
+    }
 }
 
 pub struct FontPick<'a> {
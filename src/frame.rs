@@ -0,0 +1,474 @@
+use crate::color::RgbColor;
+use crate::config::{CanvasConfig, FishTailConfig, FrameConfig};
+use crate::multirows::MultiRowsMode;
+use crate::plan::{LineSpec, PolygonSpec};
+
+const TAIL_SEGMENTS: usize = 12;
+
+/// 版框矢量图元：边框、界行、鱼尾与象鼻，坐标与 GlyphSpec 相同（左下为原点）。
+#[derive(Debug, Clone, Default)]
+pub struct FrameSpec {
+    pub fills: Vec<PolygonSpec>,
+    pub lines: Vec<LineSpec>,
+}
+
+pub fn build_frame(canvas: &CanvasConfig, multirows: MultiRowsMode) -> Option<FrameSpec> {
+    let frame = canvas.frame.as_ref()?;
+    let mut spec = FrameSpec::default();
+
+    let left = canvas.margins_left;
+    let right = canvas.canvas_width - canvas.margins_right;
+    let bottom = canvas.margins_bottom;
+    let top = canvas.canvas_height - canvas.margins_top;
+
+    if frame.outline_width > 0.0 {
+        let dx = frame.outline_hmargin + frame.outline_width / 2.0;
+        let dy = frame.outline_vmargin + frame.outline_width / 2.0;
+        push_rect(
+            &mut spec.lines,
+            (left - dx, bottom - dy, right + dx, top + dy),
+            frame.outline_width,
+            frame.outline_color,
+        );
+    }
+    if frame.inline_width > 0.0 {
+        push_rect(
+            &mut spec.lines,
+            (left, bottom, right, top),
+            frame.inline_width,
+            frame.inline_color,
+        );
+    }
+
+    let (strip_l, strip_r) = center_strip(canvas);
+    if frame.inline_width > 0.0 {
+        // 多栏模式下的界行由 build_multirows_lines 绘为浅色细线
+        let rules = if band_count(multirows) > 1 {
+            Vec::new()
+        } else {
+            column_rules(canvas)
+        };
+        for x in rules.into_iter().chain([strip_r]) {
+            push_vline(
+                &mut spec.lines,
+                x,
+                bottom,
+                top,
+                frame.inline_width,
+                frame.inline_color,
+            );
+        }
+        if canvas.leaf_center_width > 0.0 {
+            push_vline(
+                &mut spec.lines,
+                strip_l,
+                bottom,
+                top,
+                frame.inline_width,
+                frame.inline_color,
+            );
+        }
+    }
+
+    if canvas.leaf_center_width > 0.0 {
+        push_center_strip(
+            &mut spec,
+            frame,
+            (strip_l, strip_r),
+            (bottom, top),
+            canvas.canvas_height,
+        );
+    }
+    Some(spec)
+}
+
+/// 多栏模式的分栏横线与栏内浅色界行，不论背景图是否已含版框都需绘制
+pub fn build_multirows_lines(canvas: &CanvasConfig, multirows: MultiRowsMode) -> Vec<LineSpec> {
+    let bands = band_count(multirows);
+    let mut lines = Vec::new();
+    if bands <= 1 {
+        return lines;
+    }
+    let (color, width) = canvas
+        .frame
+        .as_ref()
+        .map_or((RgbColor::new_u8(0, 0, 0), 1.0), |frame| {
+            (frame.inline_color, frame.inline_width.max(1.0))
+        });
+    let left = canvas.margins_left;
+    let right = canvas.canvas_width - canvas.margins_right;
+    let bottom = canvas.margins_bottom;
+    let top = canvas.canvas_height - canvas.margins_top;
+    for x in column_rules(canvas) {
+        push_vline(
+            &mut lines,
+            x,
+            bottom,
+            top,
+            width,
+            canvas.multirows_col_color,
+        );
+    }
+    if canvas.multirows_line_width > 0.0 {
+        let (strip_l, strip_r) = center_strip(canvas);
+        let band_h = (top - bottom) / bands as f32;
+        for band in 1..bands {
+            let y = top - band_h * band as f32;
+            for (x1, x2) in [(left, strip_l), (strip_r, right)] {
+                lines.push(LineSpec {
+                    x1,
+                    y1: y,
+                    x2,
+                    y2: y,
+                    width: canvas.multirows_line_width,
+                    color,
+                    wavy: false,
+                });
+            }
+        }
+    }
+    lines
+}
+
+fn band_count(multirows: MultiRowsMode) -> usize {
+    match multirows {
+        MultiRowsMode::Disabled => 1,
+        MultiRowsMode::HorizontalLeaf { rows } | MultiRowsMode::HorizontalPage { rows } => rows,
+    }
+}
+
+/// 各列之间界行的横坐标，不含叶心两侧的分割线
+fn column_rules(canvas: &CanvasConfig) -> Vec<f32> {
+    let right = canvas.canvas_width - canvas.margins_right;
+    let cw = (canvas.canvas_width
+        - canvas.margins_left
+        - canvas.margins_right
+        - canvas.leaf_center_width)
+        / canvas.leaf_col.max(1) as f32;
+    let half = canvas.leaf_col / 2;
+    (1..canvas.leaf_col)
+        .filter(|&i| i != half)
+        .map(|i| {
+            let x = right - cw * i as f32;
+            if i < half {
+                x
+            } else {
+                x - canvas.leaf_center_width
+            }
+        })
+        .collect()
+}
+
+/// 版心（叶心）左右边界的横坐标
+pub fn center_strip(canvas: &CanvasConfig) -> (f32, f32) {
+    let right = canvas.canvas_width - canvas.margins_right;
+    let cw = (canvas.canvas_width
+        - canvas.margins_left
+        - canvas.margins_right
+        - canvas.leaf_center_width)
+        / canvas.leaf_col.max(1) as f32;
+    let strip_r = right - cw * (canvas.leaf_col / 2) as f32;
+    (strip_r - canvas.leaf_center_width, strip_r)
+}
+
+fn push_center_strip(
+    spec: &mut FrameSpec,
+    frame: &FrameConfig,
+    (strip_l, strip_r): (f32, f32),
+    (bottom, top): (f32, f32),
+    canvas_height: f32,
+) {
+    let cx = (strip_l + strip_r) / 2.0;
+
+    // 上鱼尾：鱼身在上，鱼尾朝下；其上为象鼻
+    let fish_top_y = canvas_height - frame.fish_top.y;
+    push_fish(
+        spec,
+        frame,
+        &frame.fish_top,
+        (strip_l, strip_r),
+        fish_top_y,
+        -1.0,
+    );
+    push_trunk(&mut spec.lines, &frame.fish_top, cx, fish_top_y, top);
+
+    let fish = &frame.fish_btm;
+    let fish_btm_y = canvas_height - fish.y;
+    if fish.rect_height <= 0.0 && fish.tria_height <= 0.0 {
+        // 单鱼尾：下鱼尾萎缩为双横线
+        for y in [fish_btm_y, fish_btm_y + frame.fish_line_margin] {
+            spec.lines.push(LineSpec {
+                x1: strip_l,
+                y1: y,
+                x2: strip_r,
+                y2: y,
+                width: frame.fish_line_width,
+                color: fish.color,
+                wavy: false,
+            });
+        }
+        push_trunk(&mut spec.lines, fish, cx, fish_btm_y, bottom);
+    } else if frame.fish_btm_upward {
+        // 对鱼尾：鱼身在下，鱼尾朝上
+        push_fish(spec, frame, fish, (strip_l, strip_r), fish_btm_y, 1.0);
+        push_trunk(&mut spec.lines, fish, cx, fish_btm_y, bottom);
+    } else {
+        // 顺鱼尾：与上鱼尾同向，象鼻接在鱼尾凹口
+        push_fish(spec, frame, fish, (strip_l, strip_r), fish_btm_y, -1.0);
+        push_trunk(
+            &mut spec.lines,
+            fish,
+            cx,
+            fish_btm_y - fish.rect_height,
+            bottom,
+        );
+    }
+}
+
+/// 绘制单个鱼尾。`anchor` 为鱼身外侧横边，`dir` 为鱼尾朝向（-1 向下，1 向上）。
+fn push_fish(
+    spec: &mut FrameSpec,
+    frame: &FrameConfig,
+    fish: &FishTailConfig,
+    (strip_l, strip_r): (f32, f32),
+    anchor: f32,
+    dir: f32,
+) {
+    if fish.rect_height <= 0.0 && fish.tria_height <= 0.0 {
+        return;
+    }
+    let base = anchor + dir * fish.rect_height;
+    let edge = tail_edge(frame, fish, strip_l, strip_r, base, dir, 0.0);
+
+    let mut points = vec![(strip_l, anchor), (strip_r, anchor)];
+    points.extend(edge.iter().rev().copied());
+    spec.fills.push(PolygonSpec {
+        points,
+        color: fish.color,
+    });
+
+    if frame.fish_line_width > 0.0 && frame.fish_line_margin > 0.0 {
+        let outer = anchor - dir * frame.fish_line_margin;
+        spec.lines.push(LineSpec {
+            x1: strip_l,
+            y1: outer,
+            x2: strip_r,
+            y2: outer,
+            width: frame.fish_line_width,
+            color: frame.fish_line_color,
+            wavy: false,
+        });
+        let outline = tail_edge(
+            frame,
+            fish,
+            strip_l,
+            strip_r,
+            base,
+            dir,
+            frame.fish_line_margin,
+        );
+        for pair in outline.windows(2) {
+            spec.lines.push(LineSpec {
+                x1: pair[0].0,
+                y1: pair[0].1,
+                x2: pair[1].0,
+                y2: pair[1].1,
+                width: frame.fish_line_width,
+                color: frame.fish_line_color,
+                wavy: false,
+            });
+        }
+    }
+}
+
+/// 鱼尾外缘，从左侧经中点到右侧。三角鱼尾为折线，花鱼尾为两段外凸弧线。
+fn tail_edge(
+    frame: &FrameConfig,
+    fish: &FishTailConfig,
+    strip_l: f32,
+    strip_r: f32,
+    base: f32,
+    dir: f32,
+    offset: f32,
+) -> Vec<(f32, f32)> {
+    let cx = (strip_l + strip_r) / 2.0;
+    let depth = |t: f32| -> f32 {
+        if frame.fish_flower {
+            fish.tria_height * (1.0 - t * t).max(0.0).sqrt()
+        } else {
+            fish.tria_height * (1.0 - t)
+        }
+    };
+    let steps = if frame.fish_flower { TAIL_SEGMENTS } else { 1 };
+    let mut points = Vec::with_capacity(steps * 2 + 1);
+    for i in 0..=steps {
+        let t = i as f32 / steps as f32;
+        let x = strip_l + (cx - strip_l) * t;
+        points.push((x, base + dir * (depth(t) + offset)));
+    }
+    for i in (0..steps).rev() {
+        let t = i as f32 / steps as f32;
+        let x = strip_r - (strip_r - cx) * t;
+        points.push((x, base + dir * (depth(t) + offset)));
+    }
+    points
+}
+
+fn push_trunk(lines: &mut Vec<LineSpec>, fish: &FishTailConfig, cx: f32, from: f32, to: f32) {
+    if fish.line_width <= 0.0 {
+        return;
+    }
+    lines.push(LineSpec {
+        x1: cx,
+        y1: from,
+        x2: cx,
+        y2: to,
+        width: fish.line_width,
+        color: fish.color,
+        wavy: false,
+    });
+}
+
+fn push_vline(
+    lines: &mut Vec<LineSpec>,
+    x: f32,
+    bottom: f32,
+    top: f32,
+    width: f32,
+    color: RgbColor,
+) {
+    lines.push(LineSpec {
+        x1: x,
+        y1: bottom,
+        x2: x,
+        y2: top,
+        width,
+        color,
+        wavy: false,
+    });
+}
+
+fn push_rect(
+    lines: &mut Vec<LineSpec>,
+    (x1, y1, x2, y2): (f32, f32, f32, f32),
+    width: f32,
+    color: RgbColor,
+) {
+    // 横线向两端各延伸半个线宽，使粗线转角闭合
+    let ext = width / 2.0;
+    let segments = [
+        (x1 - ext, y1, x2 + ext, y1),
+        (x1 - ext, y2, x2 + ext, y2),
+        (x1, y1, x1, y2),
+        (x2, y1, x2, y2),
+    ];
+    for (ax, ay, bx, by) in segments {
+        lines.push(LineSpec {
+            x1: ax,
+            y1: ay,
+            x2: bx,
+            y2: by,
+            width,
+            color,
+            wavy: false,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_frame, build_multirows_lines, center_strip};
+    use crate::config::CanvasConfig;
+    use crate::multirows::MultiRowsMode;
+
+    #[test]
+    fn frame_places_rules_borders_and_fish_tails() {
+        let canvas = CanvasConfig::load("canvas/24_paper.cfg").expect("load canvas configuration");
+        let frame = canvas.frame.as_ref().expect("canvas defines a frame");
+        let spec = build_frame(&canvas, MultiRowsMode::Disabled).expect("frame built");
+        let (left, right) = (
+            canvas.margins_left,
+            canvas.canvas_width - canvas.margins_right,
+        );
+        let (bottom, top) = (
+            canvas.margins_bottom,
+            canvas.canvas_height - canvas.margins_top,
+        );
+
+        // 界行连同叶心两侧的分割线：满高的内线，每叶 leaf_col 条
+        let rules = spec
+            .lines
+            .iter()
+            .filter(|l| l.x1 == l.x2 && l.x1 > left && l.x1 < right)
+            .filter(|l| l.y1 == bottom && l.y2 == top && l.width == frame.inline_width)
+            .count();
+        assert_eq!(rules, canvas.leaf_col);
+
+        // 内框贴版心，外框再向外让出间距与半个线宽
+        let verticals = |width: f32| -> Vec<f32> {
+            let mut xs: Vec<f32> = spec
+                .lines
+                .iter()
+                .filter(|l| l.x1 == l.x2 && l.width == width && (l.x1 <= left || l.x1 >= right))
+                .map(|l| l.x1)
+                .collect();
+            xs.sort_by(f32::total_cmp);
+            xs
+        };
+        assert_eq!(verticals(frame.inline_width), [left, right]);
+        let outer = frame.outline_hmargin + frame.outline_width / 2.0;
+        assert_eq!(
+            verticals(frame.outline_width),
+            [left - outer, right + outer]
+        );
+
+        // 上鱼尾鱼身外边在 fish_top_y，鱼尾朝下；对鱼尾的下鱼尾朝上
+        let (strip_l, strip_r) = center_strip(&canvas);
+        let top_fish = &spec.fills[0];
+        let fish_y = canvas.canvas_height - frame.fish_top.y;
+        assert_eq!(top_fish.points[..2], [(strip_l, fish_y), (strip_r, fish_y)]);
+        let lowest = top_fish.points.iter().map(|p| p.1).fold(f32::MAX, f32::min);
+        let depth = frame.fish_top.rect_height + frame.fish_top.tria_height;
+        assert!((fish_y - lowest - depth).abs() < 0.01);
+        let btm_fish = &spec.fills[1];
+        let btm_y = canvas.canvas_height - frame.fish_btm.y;
+        let highest = btm_fish.points.iter().map(|p| p.1).fold(f32::MIN, f32::max);
+        assert!(highest > btm_y);
+    }
+
+    #[test]
+    fn multirows_lines_do_not_depend_on_the_frame() {
+        let canvas = CanvasConfig::load("canvas/mr_5.cfg").expect("load canvas configuration");
+        let multirows = MultiRowsMode::HorizontalLeaf {
+            rows: canvas.multirows_count,
+        };
+        let lines = build_multirows_lines(&canvas, multirows);
+        let separators = lines.iter().filter(|l| l.y1 == l.y2).count();
+        assert_eq!(
+            separators,
+            (canvas.multirows_count - 1) * 2,
+            "both half leaves"
+        );
+        let hairlines: Vec<_> = lines.iter().filter(|l| l.x1 == l.x2).collect();
+        assert_eq!(hairlines.len(), canvas.leaf_col - 2);
+        assert!(
+            hairlines
+                .iter()
+                .all(|l| l.color == canvas.multirows_col_color)
+        );
+        assert!(build_multirows_lines(&canvas, MultiRowsMode::Disabled).is_empty());
+
+        // 版框只保留叶心分割线，界行不重复绘制
+        let frame = build_frame(&canvas, multirows).expect("frame built");
+        let (strip_l, strip_r) = center_strip(&canvas);
+        let inner: Vec<f32> = frame
+            .lines
+            .iter()
+            .filter(|l| l.x1 == l.x2 && l.x1 > canvas.margins_left)
+            .filter(|l| l.x1 < canvas.canvas_width - canvas.margins_right)
+            .filter(|l| l.y1 == canvas.margins_bottom)
+            .map(|l| l.x1)
+            .collect();
+        assert_eq!(inner, [strip_r, strip_l]);
+    }
+}
@@ -0,0 +1,116 @@
+use crate::clusters::is_token;
+use anyhow::{Context, Result, anyhow};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 外字映射文件名，位于 glyphs/ 目录中
+pub const GLYPH_MAP_FILE: &str = "glyphs.txt";
+
+/// 用户提供的外字图片（书籍目录下的 glyphs/），以所代替的文本为键：
+/// 私用区等单字、`{名称}` 记号或构形描述序列
+#[derive(Debug, Clone, Default)]
+pub struct GlyphImages {
+    images: HashMap<String, PathBuf>,
+}
+
+impl GlyphImages {
+    /// 读取 dir 中的 PNG/JPEG/SVG 图片，以文件名（不含扩展名）为键，如 `⿰木夋.png`
+    /// 代替该构形描述序列；再按 glyphs.txt 登记其余映射。目录不存在时为空
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut images = Self::default();
+        if !dir.is_dir() {
+            return Ok(images);
+        }
+        for entry in fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))? {
+            let path = entry?.path();
+            let supported = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| {
+                    ["png", "jpg", "jpeg", "svg"]
+                        .iter()
+                        .any(|known| ext.eq_ignore_ascii_case(known))
+                });
+            if let (true, Some(stem)) = (supported, path.file_stem().and_then(|s| s.to_str())) {
+                images.insert(stem.to_string(), path.clone());
+            }
+        }
+        let map_path = dir.join(GLYPH_MAP_FILE);
+        if map_path.is_file() {
+            let content = fs::read_to_string(&map_path)
+                .with_context(|| format!("reading {}", map_path.display()))?;
+            images
+                .parse_map(&content, dir)
+                .with_context(|| map_path.display().to_string())?;
+        }
+        Ok(images)
+    }
+
+    /// 解析 `键|图片文件` 格式的映射，键为 `U+E000` 码位、`{名称}` 记号或原字，
+    /// 图片路径相对 dir；`#` 起首的行为注释
+    pub fn parse_map(&mut self, content: &str, dir: &Path) -> Result<()> {
+        for (number, line) in content.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let (key, file) = trimmed
+                .split_once('|')
+                .map(|(key, file)| (key.trim(), file.trim()))
+                .filter(|(key, file)| !key.is_empty() && !file.is_empty())
+                .ok_or_else(|| anyhow!("line {}: expected KEY|FILE", number + 1))?;
+            let key = match key.strip_prefix("U+").or_else(|| key.strip_prefix("u+")) {
+                Some(hex) => u32::from_str_radix(hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| anyhow!("line {}: invalid code point {key}", number + 1))?
+                    .to_string(),
+                None => key.to_string(),
+            };
+            self.insert(key, dir.join(file));
+        }
+        Ok(())
+    }
+
+    pub fn insert(&mut self, key: impl Into<String>, path: impl Into<PathBuf>) {
+        self.images.insert(key.into(), path.into());
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Path> {
+        self.images.get(key).map(PathBuf::as_path)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    /// 登记了图片的 `{名称}` 记号，预处理时只把这些记号当作一字
+    pub fn tokens(&self) -> impl Iterator<Item = &str> {
+        self.images
+            .keys()
+            .map(String::as_str)
+            .filter(|key| is_token(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GlyphImages;
+    use std::path::Path;
+
+    #[test]
+    fn map_file_accepts_code_points_tokens_and_literals() {
+        let mut images = GlyphImages::default();
+        images
+            .parse_map(
+                "# 外字\nU+E000|e000.png\n{䄂異}|token.svg\n⿰木夋 | ids.png\n",
+                Path::new("glyphs"),
+            )
+            .expect("parse map");
+        assert_eq!(images.get("\u{E000}"), Some(Path::new("glyphs/e000.png")));
+        assert_eq!(images.get("{䄂異}"), Some(Path::new("glyphs/token.svg")));
+        assert_eq!(images.get("⿰木夋"), Some(Path::new("glyphs/ids.png")));
+        assert!(images.parse_map("U+ZZZZ|x.png", Path::new("")).is_err());
+    }
+}
@@ -0,0 +1,589 @@
+//! 横排：自左向右成行、自上而下换行，行距取竖排的列宽，字宽取自字体横排度量。
+//! 批注以小字随文排出，或在 `if_comment_footnote=1` 时作页脚注；输出同样的 `PagePlan`。
+
+use crate::flow_engine::{FlowCursor, FlowFrame, FlowPage};
+use crate::layout_engine::{CommentChar, FrameSpan, GlyphRole, LayoutEngine};
+use crate::plan::{GlyphSpec, LineSpec, PagePlan};
+use anyhow::{Result, anyhow};
+
+/// 已选定字体、量好宽度的一个字；空格只占宽度
+#[derive(Debug, Clone)]
+struct Piece {
+    glyph: Option<GlyphSpec>,
+    advance: f32,
+    /// 相对基线的抬升，用于脚注序号
+    raise: f32,
+    bookline: bool,
+}
+
+/// 本页已排入的脚注：count 为序号，lines 为折好的各行
+#[derive(Debug, Default)]
+struct Footnotes {
+    count: usize,
+    lines: Vec<Vec<Piece>>,
+}
+
+pub struct HorizontalEngine<'e, 'a> {
+    engine: &'e LayoutEngine<'a>,
+    /// 正文首选字体的字号，决定行内基线
+    text_size: f32,
+    /// 批注首选字体的字号，决定脚注行距
+    note_size: f32,
+}
+
+impl<'e, 'a> HorizontalEngine<'e, 'a> {
+    pub fn new(engine: &'e LayoutEngine<'a>) -> Result<Self> {
+        if engine.layout.multirows_bands > 1 {
+            return Err(anyhow!("horizontal layout does not support multirows"));
+        }
+        let fonts = engine.fonts;
+        let text_size = fonts
+            .text_stack
+            .iter()
+            .find_map(|&idx| fonts.font(idx))
+            .map(|font| font.slot.text_size)
+            .ok_or_else(|| anyhow!("no font loaded for text_fonts_array"))?;
+        let style = &engine.book.annotations.styles[0];
+        let note_size = style
+            .stack
+            .iter()
+            .find_map(|&idx| fonts.font(idx))
+            .map(|font| style.font_size.unwrap_or(font.slot.comment_size))
+            .unwrap_or(text_size / 2.0);
+        Ok(Self {
+            engine,
+            text_size,
+            note_size,
+        })
+    }
+
+    pub fn process_entry(
+        &self,
+        entry: &str,
+        page: &mut FlowPage<'_>,
+        cursor: &mut FlowCursor,
+        bookline_active: &mut bool,
+    ) -> Result<()> {
+        let book = self.engine.book;
+        let mut chars = entry.chars().peekable();
+        let mut comment_queue: Vec<CommentChar> = Vec::new();
+        let mut notes = Footnotes::default();
+        let mut frame = FlowFrame::default();
+
+        while let Some(ch) = chars.next() {
+            if let Some(style) = book.annotations.opening(ch) {
+                frame.flush(self.engine, page.current_page, true);
+                self.engine
+                    .collect_annotation(style, &mut chars, &mut comment_queue);
+                let items = std::mem::take(&mut comment_queue);
+                let stop = if book.annotations.footnote {
+                    self.add_footnote(&items, page, cursor, &mut notes, &mut frame)
+                } else {
+                    self.render_inline(&items, page, cursor, &mut notes, &mut frame)
+                };
+                if stop {
+                    break;
+                }
+                continue;
+            }
+            match ch {
+                '\r' | '^' => continue,
+                '\n' => {
+                    if cursor.used > 0.0 && self.next_line(page, cursor, &mut notes, &mut frame) {
+                        break;
+                    }
+                    continue;
+                }
+                '%' => {
+                    if self.next_page(page, cursor, &mut notes, &mut frame) {
+                        break;
+                    }
+                    continue;
+                }
+                '$' => {
+                    let half = self.line_count() / 2;
+                    let at_start =
+                        cursor.used == 0.0 && (cursor.column == 0 || cursor.column == half);
+                    if at_start {
+                        continue;
+                    }
+                    if cursor.column < half && self.line_fits(half, notes.lines.len()) {
+                        frame.flush(self.engine, page.current_page, true);
+                        *cursor = FlowCursor {
+                            column: half,
+                            used: 0.0,
+                        };
+                    } else if self.next_page(page, cursor, &mut notes, &mut frame) {
+                        break;
+                    }
+                    continue;
+                }
+                '&' => {
+                    let last = self.line_count() - 1;
+                    if cursor.column < last && self.line_fits(last, notes.lines.len()) {
+                        frame.flush(self.engine, page.current_page, true);
+                        *cursor = FlowCursor {
+                            column: last,
+                            used: 0.0,
+                        };
+                    }
+                    continue;
+                }
+                '《' | '》' => {
+                    *bookline_active = ch == '《';
+                    if book.book_line_flag {
+                        continue;
+                    }
+                }
+                '<' if self.engine.is_name_frame_char(ch) => {
+                    frame.open();
+                    continue;
+                }
+                '>' if self.engine.is_name_frame_char(ch) => {
+                    frame.close(self.engine, page.current_page);
+                    continue;
+                }
+                _ => {}
+            }
+
+            let Some(mut piece) = self.measure(ch, GlyphRole::Text) else {
+                continue;
+            };
+            piece.bookline = *bookline_active && book.book_line_flag;
+            let advance = piece.advance;
+            let hang = self.is_line_head(ch);
+            if self.push_piece(piece, hang, page, cursor, &mut notes, &mut frame) {
+                break;
+            }
+            if let Some(cfg) = &book.name_frame {
+                let inset = cfg.width / 2.0;
+                let top = self.line_top() - self.pitch() * cursor.column as f32;
+                let end = self.engine.layout.margins_left + cursor.used;
+                frame.extend(FrameSpan {
+                    start: end - advance,
+                    end,
+                    lo: top - self.pitch() + inset,
+                    hi: top - inset,
+                    vertical: false,
+                });
+            }
+        }
+        frame.flush(self.engine, page.current_page, true);
+        self.flush_footnotes(&mut notes, page.current_page);
+        Ok(())
+    }
+
+    /// 批注以小字接排于正文之中，与正文同一基线；返回是否已达试排页数
+    fn render_inline(
+        &self,
+        items: &[CommentChar],
+        page: &mut FlowPage<'_>,
+        cursor: &mut FlowCursor,
+        notes: &mut Footnotes,
+        frame: &mut FlowFrame,
+    ) -> bool {
+        for (ch, piece) in self.comment_pieces(items) {
+            if self.push_piece(piece, self.is_line_head(ch), page, cursor, notes, frame) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// 正文处排上标序号，批注折行后记入本页脚注；本页余下的行容不下脚注时，
+    /// 序号与脚注一同移至下一页。返回是否已达试排页数
+    fn add_footnote(
+        &self,
+        items: &[CommentChar],
+        page: &mut FlowPage<'_>,
+        cursor: &mut FlowCursor,
+        notes: &mut Footnotes,
+        frame: &mut FlowFrame,
+    ) -> bool {
+        let Some(first) = items.first() else {
+            return false;
+        };
+        let pieces: Vec<Piece> = self
+            .comment_pieces(items)
+            .into_iter()
+            .map(|(_, piece)| piece)
+            .collect();
+        if pieces.is_empty() {
+            return false;
+        }
+        let marker_role = self.engine.comment_role(CommentChar {
+            ch: first.ch,
+            style: first.style,
+            label: false,
+        });
+        loop {
+            let number = notes.count + 1;
+            let lines = self.wrap_footnote(number, &pieces, marker_role);
+            let fresh_page = cursor.column == 0 && notes.lines.is_empty();
+            if fresh_page || self.line_fits(cursor.column, notes.lines.len() + lines.len()) {
+                for digit in number.to_string().chars() {
+                    if let Some(mut piece) = self.measure(digit, marker_role) {
+                        piece.raise = self.text_size * 0.4;
+                        // 序号紧随前字，不单独换行
+                        if self.push_piece(piece, true, page, cursor, notes, frame) {
+                            return true;
+                        }
+                    }
+                }
+                notes.count = number;
+                notes.lines.extend(lines);
+                return false;
+            }
+            if self.next_page(page, cursor, notes, frame) {
+                return true;
+            }
+        }
+    }
+
+    /// 量好批注各字，去掉书名号与名框标记并记下书名线
+    fn comment_pieces(&self, items: &[CommentChar]) -> Vec<(char, Piece)> {
+        let book = self.engine.book;
+        let mut bookline = false;
+        let mut pieces = Vec::with_capacity(items.len());
+        for item in items {
+            if self.engine.should_skip_bookline_char(item.ch) {
+                bookline = item.ch == '《';
+                continue;
+            }
+            if self.engine.is_name_frame_char(item.ch) {
+                continue;
+            }
+            if let Some(mut piece) = self.measure(item.ch, self.engine.comment_role(*item)) {
+                piece.bookline = bookline && book.book_line_flag;
+                pieces.push((item.ch, piece));
+            }
+        }
+        pieces
+    }
+
+    /// 脚注以序号开头，按版心宽度折行
+    fn wrap_footnote(
+        &self,
+        number: usize,
+        pieces: &[Piece],
+        marker_role: GlyphRole<'_>,
+    ) -> Vec<Vec<Piece>> {
+        let mut prefix: Vec<Piece> = number
+            .to_string()
+            .chars()
+            .filter_map(|digit| self.measure(digit, marker_role))
+            .collect();
+        prefix.push(Piece {
+            glyph: None,
+            advance: self.note_size / 2.0,
+            raise: 0.0,
+            bookline: false,
+        });
+        let width = self.line_width();
+        let mut lines: Vec<Vec<Piece>> = vec![Vec::new()];
+        let mut used = 0.0;
+        for piece in prefix.iter().chain(pieces) {
+            if used > 0.0 && used + piece.advance > width {
+                lines.push(Vec::new());
+                used = 0.0;
+            }
+            used += piece.advance;
+            lines
+                .last_mut()
+                .expect("at least one line")
+                .push(piece.clone());
+        }
+        lines
+    }
+
+    /// 横排前进宽度取字体的横排度量，缺失时按字号；外字图片、拼合字与空格按所在角色的字号
+    fn measure(&self, ch: char, role: GlyphRole<'_>) -> Option<Piece> {
+        if ch == ' ' {
+            let advance = match role {
+                GlyphRole::Text => self.text_size,
+                GlyphRole::Comment { .. } => self.note_size,
+            };
+            return Some(Piece {
+                glyph: None,
+                advance,
+                raise: 0.0,
+                bookline: false,
+            });
+        }
+        let (glyph, font) = self.engine.styled_glyph(ch, role)?;
+        let metrics = font
+            .font
+            .metrics_indexed(font.glyph_index(&glyph), glyph.font_size);
+        let advance = if glyph.is_synthetic() {
+            glyph.font_size
+        } else if metrics.advance_width > 0.0 {
+            metrics.advance_width
+        } else {
+            glyph.font_size
+        };
+        Some(Piece {
+            glyph: Some(glyph),
+            advance,
+            raise: 0.0,
+            bookline: false,
+        })
+    }
+
+    /// 将字排在当前行末，行满则换行；hang 为真时允许越出行尾（行首禁则字悬挂），
+    /// 每行至多悬挂一字。返回是否已达试排页数
+    fn push_piece(
+        &self,
+        piece: Piece,
+        hang: bool,
+        page: &mut FlowPage<'_>,
+        cursor: &mut FlowCursor,
+        notes: &mut Footnotes,
+        frame: &mut FlowFrame,
+    ) -> bool {
+        let width = self.line_width();
+        let hangs = hang && cursor.used <= width;
+        if cursor.used > 0.0
+            && !hangs
+            && cursor.used + piece.advance > width
+            && self.next_line(page, cursor, notes, frame)
+        {
+            return true;
+        }
+        let x = self.engine.layout.margins_left + cursor.used;
+        self.place(&piece, x, self.baseline(cursor.column), page.current_page);
+        cursor.used += piece.advance;
+        false
+    }
+
+    fn place(&self, piece: &Piece, x: f32, baseline: f32, page: &mut PagePlan) {
+        let Some(glyph) = &piece.glyph else {
+            return;
+        };
+        let mut glyph = glyph.clone();
+        glyph.x = x;
+        glyph.y = baseline + piece.raise;
+        if piece.bookline
+            && let Some(bline) = &self.engine.book.bookline
+        {
+            let y = baseline - glyph.font_size * 0.15;
+            page.lines.push(LineSpec {
+                x1: x,
+                x2: x + piece.advance,
+                y1: y,
+                y2: y,
+                width: bline.width,
+                color: bline.color,
+                wavy: true,
+            });
+        }
+        page.glyphs.push(glyph);
+    }
+
+    /// 脚注自版框底边向上排，其上以短线与正文隔开
+    fn flush_footnotes(&self, notes: &mut Footnotes, page: &mut PagePlan) {
+        if notes.lines.is_empty() {
+            return;
+        }
+        let layout = self.engine.layout;
+        let pitch = self.note_pitch();
+        let top = layout.margins_bottom + self.footnotes_height(notes.lines.len());
+        page.lines.push(LineSpec {
+            x1: layout.margins_left,
+            x2: layout.margins_left + self.line_width() / 4.0,
+            y1: top - pitch / 2.0,
+            y2: top - pitch / 2.0,
+            width: 1.0,
+            color: self.engine.book.text_font_color,
+            wavy: false,
+        });
+        for (idx, line) in notes.lines.iter().enumerate() {
+            let baseline = top - pitch * (idx + 2) as f32 + (pitch - self.note_size) / 2.0;
+            let mut x = layout.margins_left;
+            for piece in line {
+                self.place(piece, x, baseline, page);
+                x += piece.advance;
+            }
+        }
+        *notes = Footnotes::default();
+    }
+
+    /// 换到下一行，本页余下的行让给脚注或已排满时换页；返回是否已达试排页数
+    fn next_line(
+        &self,
+        page: &mut FlowPage<'_>,
+        cursor: &mut FlowCursor,
+        notes: &mut Footnotes,
+        frame: &mut FlowFrame,
+    ) -> bool {
+        if self.line_fits(cursor.column + 1, notes.lines.len()) {
+            frame.flush(self.engine, page.current_page, true);
+            cursor.column += 1;
+            cursor.used = 0.0;
+            return false;
+        }
+        self.next_page(page, cursor, notes, frame)
+    }
+
+    fn next_page(
+        &self,
+        page: &mut FlowPage<'_>,
+        cursor: &mut FlowCursor,
+        notes: &mut Footnotes,
+        frame: &mut FlowFrame,
+    ) -> bool {
+        frame.flush(self.engine, page.current_page, true);
+        self.flush_footnotes(notes, page.current_page);
+        self.engine.finalize_page(
+            page.current_page,
+            page.pages,
+            &mut 0,
+            page.generated_pages,
+            page.next_page_number,
+            page.title_text,
+        );
+        *cursor = FlowCursor::default();
+        self.engine.reached_limit(*page.generated_pages)
+    }
+
+    fn is_line_head(&self, ch: char) -> bool {
+        self.engine.book.line_break.head.contains(&ch)
+    }
+
+    /// 第 line 行与 note_lines 行脚注能否同在本页；首行总能排入
+    fn line_fits(&self, line: usize, note_lines: usize) -> bool {
+        let layout = self.engine.layout;
+        let bottom = self.line_top() - self.pitch() * (line + 1) as f32;
+        line == 0 || bottom >= layout.margins_bottom + self.footnotes_height(note_lines) - 0.01
+    }
+
+    fn footnotes_height(&self, note_lines: usize) -> f32 {
+        if note_lines == 0 {
+            0.0
+        } else {
+            (note_lines + 1) as f32 * self.note_pitch()
+        }
+    }
+
+    fn line_count(&self) -> usize {
+        let layout = self.engine.layout;
+        let height = self.line_top() - layout.margins_bottom;
+        ((height / self.pitch()).floor() as usize).max(1)
+    }
+
+    fn baseline(&self, line: usize) -> f32 {
+        let pitch = self.pitch();
+        self.line_top() - pitch * (line + 1) as f32 + (pitch - self.text_size) / 2.0
+    }
+
+    fn pitch(&self) -> f32 {
+        self.engine.layout.cw
+    }
+
+    fn note_pitch(&self) -> f32 {
+        self.pitch() * self.note_size / self.text_size
+    }
+
+    fn line_top(&self) -> f32 {
+        self.engine.layout.canvas_height - self.engine.layout.margins_top
+    }
+
+    fn line_width(&self) -> f32 {
+        let layout = self.engine.layout;
+        layout.canvas_width - layout.margins_left - layout.margins_right
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::typeset_sample;
+    use crate::plan::DocumentPlan;
+
+    fn typeset(overrides: &[&str], text: &str) -> DocumentPlan {
+        let overrides: Vec<&str> = ["layout_mode=horizontal"]
+            .into_iter()
+            .chain(overrides.iter().copied())
+            .collect();
+        typeset_sample(&overrides, |_| text.to_string())
+    }
+
+    #[test]
+    fn horizontal_mode_runs_left_to_right_with_inline_annotations() {
+        let plan = typeset(&[], "AB【CD】E\nF");
+        let glyphs = &plan.pages[0].glyphs;
+        let glyph = |ch: char| glyphs.iter().find(|g| g.ch == ch).expect("glyph placed");
+        assert!(glyph('A').x < glyph('B').x);
+        assert_eq!(glyph('A').y, glyph('B').y);
+        assert_eq!(glyph('C').y, glyph('B').y);
+        assert!(glyph('C').font_size < glyph('B').font_size);
+        assert!(glyph('B').x < glyph('C').x && glyph('D').x < glyph('E').x);
+        // 换行另起一行，自左边距开始
+        assert!(glyph('F').y < glyph('A').y);
+        assert_eq!(glyph('F').x, glyph('A').x);
+    }
+
+    #[test]
+    fn footnote_annotations_leave_a_marker_and_sit_below_the_text() {
+        let plan = typeset(&["if_comment_footnote=1"], "AB【CD】E");
+        let page = &plan.pages[0];
+        let glyph = |ch: char| {
+            page.glyphs
+                .iter()
+                .find(|g| g.ch == ch)
+                .expect("glyph placed")
+        };
+        let markers: Vec<_> = page.glyphs.iter().filter(|g| g.ch == '1').collect();
+        assert_eq!(markers.len(), 2);
+        // 正文中的序号抬高，脚注排在页底
+        assert!(markers.iter().any(|m| m.y > glyph('A').y));
+        assert!(glyph('C').y < glyph('A').y);
+        assert!(glyph('C').x < glyph('D').x);
+        assert_eq!(glyph('C').y, glyph('D').y);
+        assert!(glyph('B').x < glyph('E').x);
+        assert!(
+            page.lines
+                .iter()
+                .any(|line| line.y1 == line.y2 && !line.wavy)
+        );
+    }
+
+    #[test]
+    fn line_head_marks_hang_one_at_a_time() {
+        let full = typeset(&[], &"A".repeat(300));
+        let glyphs = &full.pages[0].glyphs;
+        let per_line = glyphs.iter().take_while(|g| g.y == glyphs[0].y).count();
+        let plan = typeset(&["exp_line_head=Z"], &format!("{}ZZ", "A".repeat(per_line)));
+        let glyphs = &plan.pages[0].glyphs;
+        let marks: Vec<_> = glyphs.iter().filter(|g| g.ch == 'Z').collect();
+        // 第一个 Z 悬于行尾，第二个另起一行
+        assert_eq!(marks[0].y, glyphs[0].y);
+        assert!(marks[1].y < glyphs[0].y);
+        assert_eq!(marks[1].x, glyphs[0].x);
+    }
+
+    #[test]
+    fn name_frames_enclose_the_run_within_its_line() {
+        let plan = typeset(&["if_name_frame=1"], "A<BC>D");
+        let page = &plan.pages[0];
+        let glyph = |ch: char| {
+            page.glyphs
+                .iter()
+                .find(|g| g.ch == ch)
+                .expect("glyph placed")
+        };
+        assert_eq!(page.lines.len(), 4);
+        assert!(page.glyphs.iter().all(|g| g.ch != '<' && g.ch != '>'));
+        let left = page
+            .lines
+            .iter()
+            .map(|l| l.x1.min(l.x2))
+            .fold(f32::MAX, f32::min);
+        let right = page
+            .lines
+            .iter()
+            .map(|l| l.x1.max(l.x2))
+            .fold(f32::MIN, f32::max);
+        assert!(left > glyph('A').x && left <= glyph('B').x);
+        assert!(right > glyph('C').x && right <= glyph('D').x);
+        assert!(page.lines.iter().any(|l| l.y1.min(l.y2) < glyph('B').y));
+    }
+}
@@ -0,0 +1,148 @@
+//! 汉字构形描述序列（IDS）：以 `⿰木夋` 之类的写法拼合字库中没有的字。
+//!
+//! 序列按前缀式解析为部件树，再把各部件分配到一字框内的子框中。
+
+/// 子框，以字框为 1×1 计，左下为原点
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IdsBox {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl IdsBox {
+    const FULL: Self = Self {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0,
+    };
+
+    /// 取本框中按比例划出的子框
+    fn sub(self, x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x: self.x + self.width * x,
+            y: self.y + self.height * y,
+            width: self.width * width,
+            height: self.height * height,
+        }
+    }
+}
+
+/// ch 是否为构形描述符（⿰…⿻）
+pub fn is_ids_operator(ch: char) -> bool {
+    arity(ch).is_some()
+}
+
+fn arity(ch: char) -> Option<usize> {
+    match ch {
+        '⿲' | '⿳' => Some(3),
+        '\u{2FF0}'..='\u{2FFB}' => Some(2),
+        _ => None,
+    }
+}
+
+/// 自 chars 起读取一个完整的 IDS（或单个部件），返回所读的字；序列不完整时为 None
+pub fn read_sequence(chars: &mut impl Iterator<Item = char>) -> Option<String> {
+    let mut sequence = String::new();
+    let mut pending = 1usize;
+    while pending > 0 {
+        let ch = chars.next()?;
+        sequence.push(ch);
+        pending = pending - 1 + arity(ch).unwrap_or(0);
+    }
+    Some(sequence)
+}
+
+/// 将 IDS 拆为各部件及其所在子框；序列不完整时为 None
+pub fn compose(sequence: &str) -> Option<Vec<(char, IdsBox)>> {
+    let mut chars = sequence.chars();
+    let mut parts = Vec::new();
+    place(&mut chars, IdsBox::FULL, &mut parts)?;
+    Some(parts)
+}
+
+fn place(
+    chars: &mut impl Iterator<Item = char>,
+    frame: IdsBox,
+    parts: &mut Vec<(char, IdsBox)>,
+) -> Option<()> {
+    let ch = chars.next()?;
+    // 各部件子框依次为 (x, y, 宽, 高)；包围结构先排外框，内部部件退入空白处
+    let boxes: &[(f32, f32, f32, f32)] = match ch {
+        '⿰' => &[(0.0, 0.0, 0.5, 1.0), (0.5, 0.0, 0.5, 1.0)],
+        '⿱' => &[(0.0, 0.5, 1.0, 0.5), (0.0, 0.0, 1.0, 0.5)],
+        '⿲' => &[
+            (0.0, 0.0, 1.0 / 3.0, 1.0),
+            (1.0 / 3.0, 0.0, 1.0 / 3.0, 1.0),
+            (2.0 / 3.0, 0.0, 1.0 / 3.0, 1.0),
+        ],
+        '⿳' => &[
+            (0.0, 2.0 / 3.0, 1.0, 1.0 / 3.0),
+            (0.0, 1.0 / 3.0, 1.0, 1.0 / 3.0),
+            (0.0, 0.0, 1.0, 1.0 / 3.0),
+        ],
+        '⿴' => &[(0.0, 0.0, 1.0, 1.0), (0.25, 0.25, 0.5, 0.5)],
+        '⿵' => &[(0.0, 0.0, 1.0, 1.0), (0.2, 0.05, 0.6, 0.65)],
+        '⿶' => &[(0.0, 0.0, 1.0, 1.0), (0.2, 0.3, 0.6, 0.65)],
+        '⿷' => &[(0.0, 0.0, 1.0, 1.0), (0.3, 0.15, 0.65, 0.7)],
+        '⿸' => &[(0.0, 0.0, 1.0, 1.0), (0.3, 0.05, 0.65, 0.65)],
+        '⿹' => &[(0.0, 0.0, 1.0, 1.0), (0.05, 0.05, 0.65, 0.65)],
+        '⿺' => &[(0.0, 0.0, 1.0, 1.0), (0.3, 0.3, 0.65, 0.65)],
+        '⿻' => &[(0.0, 0.0, 1.0, 1.0), (0.0, 0.0, 1.0, 1.0)],
+        _ => {
+            parts.push((ch, frame));
+            return Some(());
+        }
+    };
+    for &(x, y, width, height) in boxes {
+        place(chars, frame.sub(x, y, width, height), parts)?;
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IdsBox, compose, read_sequence};
+
+    #[test]
+    fn nested_sequences_split_the_cell() {
+        let mut chars = "⿰木⿱口夋其".chars();
+        assert_eq!(read_sequence(&mut chars).as_deref(), Some("⿰木⿱口夋"));
+        assert_eq!(chars.next(), Some('其'));
+        assert_eq!(read_sequence(&mut "⿰木".chars()), None);
+
+        let parts = compose("⿰木⿱口夋").expect("complete sequence");
+        let boxes: Vec<(char, IdsBox)> = vec![
+            (
+                '木',
+                IdsBox {
+                    x: 0.0,
+                    y: 0.0,
+                    width: 0.5,
+                    height: 1.0,
+                },
+            ),
+            (
+                '口',
+                IdsBox {
+                    x: 0.5,
+                    y: 0.5,
+                    width: 0.5,
+                    height: 0.5,
+                },
+            ),
+            (
+                '夋',
+                IdsBox {
+                    x: 0.5,
+                    y: 0.0,
+                    width: 0.5,
+                    height: 0.5,
+                },
+            ),
+        ];
+        assert_eq!(parts, boxes);
+    }
+}
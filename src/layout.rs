@@ -16,13 +16,18 @@ pub type CellPosition = Cell;
 pub struct Layout {
     pub per_page: usize,
     pub pos_l: Vec<Cell>,
-    pub pos_r: Vec<Cell>,
+    /// 批注各小行的字位，pos_c[k] 为自右向左第 k 行
+    pub pos_c: Vec<Vec<Cell>>,
     pub cw: f32,
+    /// 批注小行数：1 为单行小注，2 为双行夹注，3 为三行
+    pub comment_columns: usize,
     pub rh: f32,
     pub canvas_width: f32,
     pub canvas_height: f32,
     pub margins_top: f32,
     pub margins_bottom: f32,
+    pub margins_left: f32,
+    pub margins_right: f32,
     pub rows_per_column: usize,
     pub columns: usize,
     pub multirows_bands: usize,
@@ -51,7 +56,7 @@ impl Layout {
         let rows_per_column = match multirows {
             MultiRowsMode::Disabled => row_num,
             MultiRowsMode::HorizontalLeaf { rows } | MultiRowsMode::HorizontalPage { rows } => {
-                if rows == 0 || row_num % rows != 0 {
+                if rows == 0 || !row_num.is_multiple_of(rows) {
                     return Err(anyhow!(
                         "row_num {} not divisible by multirows {}",
                         row_num,
@@ -64,13 +69,16 @@ impl Layout {
 
         let per_page = col_num * row_num;
 
+        let comment_columns = book.annotations.columns;
         let mut pos_l = Vec::with_capacity(per_page + 1);
-        let mut pos_r = Vec::with_capacity(per_page + 1);
+        let mut pos_c = vec![Vec::with_capacity(per_page + 1); comment_columns];
         pos_l.push(Cell { x: 0.0, y: 0.0 });
-        pos_r.push(Cell { x: 0.0, y: 0.0 });
+        for sub in &mut pos_c {
+            sub.push(Cell { x: 0.0, y: 0.0 });
+        }
 
         let push_position =
-            |pos_x: f32, pos_y: f32, pos_l: &mut Vec<Cell>, pos_r: &mut Vec<Cell>| {
+            |pos_x: f32, pos_y: f32, pos_l: &mut Vec<Cell>, pos_c: &mut Vec<Vec<Cell>>| {
                 let mut x = (pos_x * 1000.0).round() / 1000.0;
                 let mut y = (pos_y * 1000.0).round() / 1000.0;
                 if x.is_nan() {
@@ -80,7 +88,10 @@ impl Layout {
                     y = 0.0;
                 }
                 pos_l.push(Cell { x, y });
-                pos_r.push(Cell { x: x + cw / 2.0, y });
+                for (k, sub) in pos_c.iter_mut().enumerate() {
+                    let offset = (comment_columns - 1 - k) as f32 * cw / comment_columns as f32;
+                    sub.push(Cell { x: x + offset, y });
+                }
             };
 
         match multirows {
@@ -97,7 +108,7 @@ impl Layout {
                     for j in 1..=rows_per_column {
                         let pos_y = canvas.canvas_height - canvas.margins_top - rh * j as f32
                             + book.row_delta_y;
-                        push_position(base_x, pos_y, &mut pos_l, &mut pos_r);
+                        push_position(base_x, pos_y, &mut pos_l, &mut pos_c);
                     }
                 }
             }
@@ -119,7 +130,7 @@ impl Layout {
                                 - band_offset
                                 - rh * j as f32
                                 + book.row_delta_y;
-                            push_position(base_x, pos_y, &mut pos_l, &mut pos_r);
+                            push_position(base_x, pos_y, &mut pos_l, &mut pos_c);
                         }
                     }
                 }
@@ -137,7 +148,7 @@ impl Layout {
                                     - band_offset
                                     - rh * j as f32
                                     + book.row_delta_y;
-                                push_position(base_x, pos_y, &mut pos_l, &mut pos_r);
+                                push_position(base_x, pos_y, &mut pos_l, &mut pos_c);
                             }
                         }
                     }
@@ -155,7 +166,7 @@ impl Layout {
                                 - band_offset
                                 - rh * j as f32
                                 + book.row_delta_y;
-                            push_position(base_x, pos_y, &mut pos_l, &mut pos_r);
+                            push_position(base_x, pos_y, &mut pos_l, &mut pos_c);
                         }
                     }
                 }
@@ -165,13 +176,16 @@ impl Layout {
         Ok(Self {
             per_page,
             pos_l,
-            pos_r,
+            pos_c,
             cw,
+            comment_columns,
             rh,
             canvas_width: canvas.canvas_width,
             canvas_height: canvas.canvas_height,
             margins_top: canvas.margins_top,
             margins_bottom: canvas.margins_bottom,
+            margins_left: canvas.margins_left,
+            margins_right: canvas.margins_right,
             rows_per_column,
             columns: col_num,
             multirows_bands: match multirows {
@@ -191,7 +205,13 @@ impl Layout {
         self.pos_l.get(idx)
     }
 
-    pub fn pos_right(&self, idx: usize) -> Option<&Cell> {
-        self.pos_r.get(idx)
+    /// 第 idx 字位中自右向左第 sub 个批注小行
+    pub fn pos_comment(&self, idx: usize, sub: usize) -> Option<&Cell> {
+        self.pos_c.get(sub)?.get(idx)
+    }
+
+    /// 批注小行宽度
+    pub fn comment_cw(&self) -> f32 {
+        self.cw / self.comment_columns as f32
     }
 }
@@ -1,7 +1,13 @@
-use crate::config::BookConfig;
-use crate::fonts::{FontManager, FontPick};
+use crate::clusters::{ClusterTable, is_token};
+use crate::config::{
+    ANNOTATION_LABEL_CLOSE, ANNOTATION_LABEL_OPEN, AnnotationStyle, BookConfig, MarkAdjust,
+    NameFrameStyle,
+};
+use crate::fonts::{FontManager, FontPick, LoadedFont};
+use crate::glyph_images::GlyphImages;
+use crate::ids::{compose, is_ids_operator};
 use crate::layout::{Cell, Layout};
-use crate::plan::{GlyphSpec, LineSpec, PagePlan, TypesetOptions};
+use crate::plan::{GlyphPart, GlyphSpec, LineSpec, PagePlan, TypesetOptions};
 use anyhow::{Result, anyhow};
 use zhconv::{Variant, zhconv};
 
@@ -9,40 +15,121 @@ use std::collections::VecDeque;
 use std::iter::Peekable;
 use std::str::Chars;
 
+/// `<…>` 名框状态，跨页、跨列保持；同列相邻的字合为一段框线
+#[derive(Debug, Default)]
+pub struct NameFrameState {
+    active: bool,
+    segment: Option<FrameSegment>,
+    /// 当前段接续上一列或上一页，顶端不封口
+    continued: bool,
+}
+
+/// 名框的一段：沿文字方向自 start 至 end，横贯方向占 lo..hi；vertical 为竖排（沿 y 轴）
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FrameSpan {
+    pub start: f32,
+    pub end: f32,
+    pub lo: f32,
+    pub hi: f32,
+    pub vertical: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FrameSegment {
+    top: Cell,
+    bottom: Cell,
+    last_slot: usize,
+}
+
+/// 批注中的一个字及其所属批注样式，label 表示处于出处标签内
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CommentChar {
+    pub ch: char,
+    pub style: usize,
+    pub label: bool,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum GlyphRole<'s> {
+    Text,
+    Comment {
+        style: &'s AnnotationStyle,
+        label: bool,
+    },
+}
+
 pub struct LayoutEngine<'a> {
     pub book: &'a BookConfig,
     pub layout: &'a Layout,
     pub fonts: &'a FontManager,
     pub options: &'a TypesetOptions,
+    pub clusters: &'a ClusterTable,
+    pub glyph_images: &'a GlyphImages,
 }
 
 impl<'a> LayoutEngine<'a> {
+    #[allow(clippy::too_many_arguments)]
     pub fn process_entry(
         &self,
         entry: &str,
         title_text: &str,
-        mut current_page: &mut PagePlan,
+        current_page: &mut PagePlan,
         pages: &mut Vec<PagePlan>,
         pcnt: &mut usize,
         generated_pages: &mut usize,
         next_page_number: &mut usize,
         bookline_active: &mut bool,
+        name_frame: &mut NameFrameState,
     ) -> Result<()> {
         let pos_l = &self.layout.pos_l;
         let pos_left = |idx: usize| pos_l.get(idx).copied();
-        let mut chars = entry.chars().peekable();
+        // 逐段排入：段末补齐空格不逐字排，段末按移位一并校正
+        let mut paragraphs = entry.split_inclusive('\n');
+        let mut chars = "".chars().peekable();
+        let mut padding = 0;
         let mut last_pos: Option<Cell> = None;
-        let mut comment_queue: Vec<char> = Vec::new();
+        let mut comment_queue: Vec<CommentChar> = Vec::new();
+        // 本段因禁则多占（正）或悬挂少占（负）的字位数；预处理按不移位补齐段末空格，段末据此校正
+        let mut shift: isize = 0;
 
-        while let Some(ch) = chars.next() {
-            if ch == '\n' || ch == '\r' {
+        loop {
+            let Some(ch) = chars.next() else {
+                self.realign_paragraph(pcnt, padding, shift);
+                shift = 0;
+                let Some(paragraph) = paragraphs.next() else {
+                    break;
+                };
+                let body = paragraph.trim_end_matches('\n').trim_end_matches(' ');
+                padding = paragraph.trim_end_matches('\n').len() - body.len();
+                chars = body.chars().peekable();
+                continue;
+            };
+            if ch == '\r' {
+                continue;
+            }
+            if let Some(style) = self.book.annotations.opening(ch) {
+                self.flush_name_frame(name_frame, current_page, true);
+                self.collect_annotation(style, &mut chars, &mut comment_queue);
+                if !comment_queue.is_empty() {
+                    self.render_comments(
+                        current_page,
+                        pages,
+                        pcnt,
+                        generated_pages,
+                        next_page_number,
+                        &mut comment_queue,
+                        title_text,
+                    )?;
+                    last_pos = None;
+                }
                 continue;
             }
             match ch {
                 '%' => {
-                    self.skip_row_padding(&mut chars);
+                    padding = padding.saturating_sub(self.skip_row_padding(&mut chars));
+                    self.flush_name_frame(name_frame, current_page, true);
                     self.finalize_page(
-                        &mut current_page,
+                        current_page,
                         pages,
                         pcnt,
                         generated_pages,
@@ -56,7 +143,7 @@ impl<'a> LayoutEngine<'a> {
                     continue;
                 }
                 '$' => {
-                    self.skip_row_padding(&mut chars);
+                    padding = padding.saturating_sub(self.skip_row_padding(&mut chars));
                     let half = self.layout.per_page / 2;
                     if *pcnt == 0 || *pcnt == half {
                         continue;
@@ -68,21 +155,19 @@ impl<'a> LayoutEngine<'a> {
                     }
                     continue;
                 }
-                '^' => {
-                    if self.layout.multirows_bands > 1 {
-                        let band = self.layout.columns * self.layout.rows_per_column;
-                        let next_band_start = ((*pcnt) / band + 1) * band;
-                        if next_band_start < self.layout.per_page {
-                            *pcnt = next_band_start;
-                        } else {
-                            *pcnt = self.layout.per_page;
-                        }
-                        last_pos = None;
-                        continue;
+                '^' if self.layout.multirows_bands > 1 => {
+                    let band = self.layout.columns * self.layout.rows_per_column;
+                    let next_band_start = ((*pcnt) / band + 1) * band;
+                    if next_band_start < self.layout.per_page {
+                        *pcnt = next_band_start;
+                    } else {
+                        *pcnt = self.layout.per_page;
                     }
+                    last_pos = None;
+                    continue;
                 }
                 '&' => {
-                    self.skip_row_padding(&mut chars);
+                    padding = padding.saturating_sub(self.skip_row_padding(&mut chars));
                     let rows_per_column = self.layout.rows_per_column;
                     if rows_per_column == 0 {
                         continue;
@@ -106,25 +191,14 @@ impl<'a> LayoutEngine<'a> {
                         continue;
                     }
                 }
-                '【' => {
-                    while let Some(next) = chars.next() {
-                        if next == '】' {
-                            break;
-                        }
-                        comment_queue.push(next);
-                    }
-                    if !comment_queue.is_empty() {
-                        self.render_comments(
-                            &mut current_page,
-                            pages,
-                            pcnt,
-                            generated_pages,
-                            next_page_number,
-                            &mut comment_queue,
-                            title_text,
-                        )?;
-                        last_pos = None;
-                    }
+                '<' if self.book.name_frame.is_some() => {
+                    name_frame.active = true;
+                    name_frame.continued = false;
+                    continue;
+                }
+                '>' if self.book.name_frame.is_some() => {
+                    self.flush_name_frame(name_frame, current_page, false);
+                    name_frame.active = false;
                     continue;
                 }
                 _ => {}
@@ -134,9 +208,35 @@ impl<'a> LayoutEngine<'a> {
             let is_rot = self.book.punctuation.text_rotate.chars.contains(&ch);
             let consumes_slot = !is_nop;
 
+            if consumes_slot {
+                if let Some(pos) = self.hanging_cell(ch, *pcnt, last_pos) {
+                    if let Some(glyph) =
+                        self.build_text_glyph(pos, ch, GlyphRole::Text, false, is_rot)
+                    {
+                        current_page.glyphs.push(glyph);
+                        last_pos = Some(pos);
+                        self.mark_text_cell(
+                            name_frame,
+                            current_page,
+                            *bookline_active,
+                            *pcnt,
+                            pos,
+                            ch,
+                        );
+                    }
+                    shift -= 1;
+                    continue;
+                }
+                if self.breaks_before(ch, *pcnt, &chars) {
+                    *pcnt += 1;
+                    shift += 1;
+                }
+            }
+
             if consumes_slot && *pcnt == self.layout.per_page {
+                self.flush_name_frame(name_frame, current_page, true);
                 self.finalize_page(
-                    &mut current_page,
+                    current_page,
                     pages,
                     pcnt,
                     generated_pages,
@@ -153,22 +253,10 @@ impl<'a> LayoutEngine<'a> {
                 *pcnt += 1;
                 let pos =
                     pos_left(*pcnt).ok_or_else(|| anyhow!("layout index {} out of range", pcnt))?;
-                if let Some(glyph) = self.build_text_glyph(pos, ch, false, false, true) {
+                if let Some(glyph) = self.build_text_glyph(pos, ch, GlyphRole::Text, false, true) {
                     current_page.glyphs.push(glyph);
                     last_pos = Some(pos);
-                    if *bookline_active && self.book.book_line_flag && ch != ' ' {
-                        if let Some(bline) = &self.book.bookline {
-                            current_page.lines.push(LineSpec {
-                                x1: pos.x - bline.width,
-                                x2: pos.x - bline.width,
-                                y1: pos.y - self.layout.rh * 0.3,
-                                y2: pos.y + self.layout.rh * 0.7,
-                                width: bline.width,
-                                color: bline.color,
-                                wavy: true,
-                            });
-                        }
-                    }
+                    self.mark_text_cell(name_frame, current_page, *bookline_active, *pcnt, pos, ch);
                 }
                 continue;
             }
@@ -177,7 +265,7 @@ impl<'a> LayoutEngine<'a> {
                 let pos_prev = last_pos
                     .or_else(|| pos_left((*pcnt).max(1)))
                     .unwrap_or(Cell { x: 0.0, y: 0.0 });
-                if let Some(glyph) = self.build_text_glyph(pos_prev, ch, false, true, false) {
+                if let Some(glyph) = self.build_nop_glyph(pos_prev, ch, *pcnt, last_pos) {
                     current_page.glyphs.push(glyph);
                 }
                 continue;
@@ -187,7 +275,7 @@ impl<'a> LayoutEngine<'a> {
             let pos =
                 pos_left(*pcnt).ok_or_else(|| anyhow!("layout index {} out of range", pcnt))?;
 
-            if let Some(glyph) = self.build_text_glyph(pos, ch, false, false, false) {
+            if let Some(glyph) = self.build_text_glyph(pos, ch, GlyphRole::Text, false, false) {
                 if self.options.verbose {
                     println!(
                         "[page {} slot {}] char '{}'",
@@ -196,38 +284,308 @@ impl<'a> LayoutEngine<'a> {
                 }
                 current_page.glyphs.push(glyph);
                 last_pos = Some(pos);
-                if *bookline_active && self.book.book_line_flag && ch != ' ' {
-                    if let Some(bline) = &self.book.bookline {
-                        current_page.lines.push(LineSpec {
-                            x1: pos.x - bline.width,
-                            x2: pos.x - bline.width,
-                            y1: pos.y - self.layout.rh * 0.3,
-                            y2: pos.y + self.layout.rh * 0.7,
-                            width: bline.width,
-                            color: bline.color,
-                            wavy: true,
-                        });
-                    }
+                self.mark_text_cell(name_frame, current_page, *bookline_active, *pcnt, pos, ch);
+            }
+
+            if *pcnt == self.layout.per_page
+                && let Some(&next) = chars.peek()
+                && self.book.punctuation.text_nop.chars.contains(&next)
+            {
+                chars.next();
+                let pos_prev = last_pos.unwrap_or(pos);
+                if let Some(pglyph) = self.build_nop_glyph(pos_prev, next, *pcnt, last_pos) {
+                    current_page.glyphs.push(pglyph);
                 }
             }
+        }
+        self.flush_name_frame(name_frame, current_page, true);
+        Ok(())
+    }
 
-            if *pcnt == self.layout.per_page {
-                if let Some(&next) = chars.peek() {
-                    if self.book.punctuation.text_nop.chars.contains(&next) {
-                        chars.next();
-                        let pos_prev = last_pos.unwrap_or(pos);
-                        if let Some(pglyph) =
-                            self.build_text_glyph(pos_prev, next, false, true, false)
-                        {
-                            current_page.glyphs.push(pglyph);
-                        }
+    /// 行首、行尾禁则：下一字将落在列尾，而它不宜居列尾，或其后的占位字不宜居列首时，
+    /// 空出列尾使其移至下一列
+    fn breaks_before(&self, ch: char, pcnt: usize, chars: &Peekable<Chars<'_>>) -> bool {
+        let rules = &self.book.line_break;
+        let rows = self.layout.rows_per_column;
+        if rows < 2 || pcnt >= self.layout.per_page || pcnt % rows != rows - 1 {
+            return false;
+        }
+        if rules.tail.contains(&ch) {
+            return true;
+        }
+        if rules.hang || rules.head.contains(&ch) {
+            return false;
+        }
+        self.next_slot_char(chars)
+            .is_some_and(|next| rules.head.contains(&next))
+    }
+
+    /// 段末补齐空格与移位相抵后，前移至下一个整列（以 row_num 计，与预处理补齐一致）
+    fn realign_paragraph(&self, pcnt: &mut usize, padding: usize, shift: isize) {
+        let row_num = self.book.row_num as isize;
+        let gap = if row_num == 0 {
+            padding
+        } else {
+            (padding as isize - shift).rem_euclid(row_num) as usize
+        };
+        *pcnt = (*pcnt + gap).min(self.layout.per_page);
+    }
+
+    /// 悬挂模式下，落在列首的列首禁则字画在上一列末字之下，不占字位
+    fn hanging_cell(&self, ch: char, pcnt: usize, last_pos: Option<Cell>) -> Option<Cell> {
+        let rules = &self.book.line_break;
+        if !rules.hang || !rules.head.contains(&ch) {
+            return None;
+        }
+        self.cell_below_column(pcnt, last_pos)
+    }
+
+    /// 上一字占据第 pcnt 格且为列末字时，返回其下方版框外的一格；多栏时仅限最下一栏
+    fn cell_below_column(&self, pcnt: usize, last_pos: Option<Cell>) -> Option<Cell> {
+        let rows = self.layout.rows_per_column;
+        if pcnt == 0 || !pcnt.is_multiple_of(rows) {
+            return None;
+        }
+        let band = self.layout.columns * rows;
+        if (pcnt - 1) / band + 1 != self.layout.multirows_bands {
+            return None;
+        }
+        let last = last_pos?;
+        let prev = self.layout.pos_left(pcnt)?;
+        (prev.x == last.x && prev.y == last.y).then_some(Cell {
+            x: last.x,
+            y: last.y - self.layout.rh,
+        })
+    }
+
+    /// 正文不占位标点：默认按偏移叠于上一字，悬挂模式下列末字之后的标点居中画在版框下方
+    fn build_nop_glyph(
+        &self,
+        pos_prev: Cell,
+        ch: char,
+        pcnt: usize,
+        last_pos: Option<Cell>,
+    ) -> Option<GlyphSpec> {
+        let hang_cell = self
+            .cell_below_column(pcnt, last_pos)
+            .filter(|_| self.book.punctuation.text_nop_hang);
+        let Some(cell) = hang_cell else {
+            return self.build_text_glyph(pos_prev, ch, GlyphRole::Text, true, false);
+        };
+        let mut glyph = self.build_text_glyph(cell, ch, GlyphRole::Text, true, false)?;
+        glyph.x = cell.x + (self.layout.cw - glyph.font_size) / 2.0;
+        glyph.y = cell.y + self.layout.rh - glyph.font_size;
+        Some(glyph)
+    }
+
+    pub(crate) fn next_slot_char(&self, chars: &Peekable<Chars<'_>>) -> Option<char> {
+        chars.clone().find(|&next| {
+            !matches!(next, '\n' | '\r')
+                && !self.book.punctuation.text_nop.chars.contains(&next)
+                && !self.should_skip_bookline_char(next)
+                && !self.is_name_frame_char(next)
+        })
+    }
+
+    /// 读取至与 style 配对的收尾标记，嵌套批注与出处标签逐字记入 queue
+    pub(crate) fn collect_annotation(
+        &self,
+        style: usize,
+        chars: &mut Peekable<Chars<'_>>,
+        queue: &mut Vec<CommentChar>,
+    ) {
+        let annotations = &self.book.annotations;
+        let mut open_styles = vec![style];
+        let mut label = false;
+        for next in chars.by_ref() {
+            if let Some(inner) = annotations.opening(next) {
+                open_styles.push(inner);
+                continue;
+            }
+            if let Some(depth) = open_styles
+                .iter()
+                .rposition(|&open| annotations.styles[open].close == next)
+            {
+                open_styles.truncate(depth);
+                label = false;
+                if open_styles.is_empty() {
+                    break;
+                }
+                continue;
+            }
+            match next {
+                ANNOTATION_LABEL_OPEN => label = true,
+                ANNOTATION_LABEL_CLOSE => label = false,
+                _ => queue.push(CommentChar {
+                    ch: next,
+                    style: open_styles[open_styles.len() - 1],
+                    label,
+                }),
+            }
+        }
+    }
+
+    /// 正文字落格后延伸名框、加书名线；悬挂于列下的字沿用上一字的字位
+    fn mark_text_cell(
+        &self,
+        name_frame: &mut NameFrameState,
+        page: &mut PagePlan,
+        bookline_active: bool,
+        slot: usize,
+        pos: Cell,
+        ch: char,
+    ) {
+        self.extend_name_frame(name_frame, page, slot, pos);
+        if bookline_active
+            && self.book.book_line_flag
+            && ch != ' '
+            && let Some(bline) = &self.book.bookline
+        {
+            page.lines.push(LineSpec {
+                x1: pos.x - bline.width,
+                x2: pos.x - bline.width,
+                y1: pos.y - self.layout.rh * 0.3,
+                y2: pos.y + self.layout.rh * 0.7,
+                width: bline.width,
+                color: bline.color,
+                wavy: true,
+            });
+        }
+    }
+
+    fn extend_name_frame(
+        &self,
+        frame: &mut NameFrameState,
+        page: &mut PagePlan,
+        slot: usize,
+        pos: Cell,
+    ) {
+        if !frame.active {
+            return;
+        }
+        if let Some(segment) = frame.segment.as_mut()
+            && (slot == segment.last_slot
+                || slot == segment.last_slot + 1
+                    && !(slot - 1).is_multiple_of(self.layout.rows_per_column))
+        {
+            segment.bottom = pos;
+            segment.last_slot = slot;
+            return;
+        }
+        self.flush_name_frame(frame, page, true);
+        frame.segment = Some(FrameSegment {
+            top: pos,
+            bottom: pos,
+            last_slot: slot,
+        });
+    }
+
+    /// 绘制当前段名框；open_end 表示名框在下一列或下一页继续，底端不封口
+    fn flush_name_frame(&self, frame: &mut NameFrameState, page: &mut PagePlan, open_end: bool) {
+        let Some(cfg) = &self.book.name_frame else {
+            return;
+        };
+        let Some(segment) = frame.segment.take() else {
+            return;
+        };
+        let open_start = frame.continued;
+        frame.continued = open_end;
+
+        let inset = cfg.width / 2.0;
+        let span = FrameSpan {
+            start: segment.top.y + self.layout.rh * 0.7,
+            end: segment.bottom.y - self.layout.rh * 0.3,
+            lo: segment.top.x + inset,
+            hi: segment.top.x + self.layout.cw - inset,
+            vertical: true,
+        };
+        self.draw_name_frame(page, span, open_start, open_end);
+    }
+
+    /// 按名框样式绘制一段框线；open_start、open_end 表示该端接续别列（行）或别页，不封口
+    pub(crate) fn draw_name_frame(
+        &self,
+        page: &mut PagePlan,
+        span: FrameSpan,
+        open_start: bool,
+        open_end: bool,
+    ) {
+        let Some(cfg) = &self.book.name_frame else {
+            return;
+        };
+        let FrameSpan {
+            start,
+            end,
+            lo,
+            hi,
+            vertical,
+        } = span;
+        let mut push = |a1: f32, c1: f32, a2: f32, c2: f32| {
+            let ((x1, y1), (x2, y2)) = if vertical {
+                ((c1, a1), (c2, a2))
+            } else {
+                ((a1, c1), (a2, c2))
+            };
+            page.lines.push(LineSpec {
+                x1,
+                y1,
+                x2,
+                y2,
+                width: cfg.width,
+                color: cfg.color,
+                wavy: false,
+            });
+        };
+
+        match cfg.style {
+            NameFrameStyle::Rect => {
+                push(start, lo, end, lo);
+                push(start, hi, end, hi);
+                if !open_start {
+                    push(start, lo, start, hi);
+                }
+                if !open_end {
+                    push(end, lo, end, hi);
+                }
+            }
+            NameFrameStyle::Cartouche => {
+                const ARC_STEPS: usize = 12;
+                let dir = if end < start { -1.0 } else { 1.0 };
+                let r_across = (hi - lo) / 2.0;
+                let r_along = r_across.min((end - start).abs() / 2.0);
+                let center = lo + r_across;
+                let side_start = if open_start {
+                    start
+                } else {
+                    start + dir * r_along
+                };
+                let side_end = if open_end { end } else { end - dir * r_along };
+                push(side_start, lo, side_end, lo);
+                push(side_start, hi, side_end, hi);
+                // 半椭圆端头自 hi 经过 outward 方向凸出，绕至 lo
+                let mut arc = |at: f32, outward: f32| {
+                    for step in 0..ARC_STEPS {
+                        let a0 = std::f32::consts::PI * step as f32 / ARC_STEPS as f32;
+                        let a1 = std::f32::consts::PI * (step + 1) as f32 / ARC_STEPS as f32;
+                        push(
+                            at + outward * r_along * a0.sin(),
+                            center + r_across * a0.cos(),
+                            at + outward * r_along * a1.sin(),
+                            center + r_across * a1.cos(),
+                        );
                     }
+                };
+                if !open_start {
+                    arc(side_start, -dir);
+                }
+                if !open_end {
+                    arc(side_end, dir);
                 }
             }
         }
-        Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn render_comments(
         &self,
         current_page: &mut PagePlan,
@@ -235,17 +593,17 @@ impl<'a> LayoutEngine<'a> {
         pcnt: &mut usize,
         generated_pages: &mut usize,
         next_page_number: &mut usize,
-        queue: &mut Vec<char>,
+        queue: &mut Vec<CommentChar>,
         title_text: &str,
     ) -> Result<()> {
         if queue.is_empty() {
             return Ok(());
         }
-        let mut remaining: VecDeque<char> = queue.drain(..).collect();
+        let mut remaining: VecDeque<CommentChar> = queue.drain(..).collect();
         let mut comment_bookline_active = false;
         let mut comment_last_slot: Option<Cell> = None;
 
-        'outer: while let Some(_) = remaining.front() {
+        'outer: while !remaining.is_empty() {
             if *pcnt >= self.layout.per_page {
                 self.finalize_page(
                     current_page,
@@ -276,22 +634,24 @@ impl<'a> LayoutEngine<'a> {
 
             let slot_needed = self.count_comment_slots(&remaining);
             if slot_needed == 0 {
-                while let Some(ch) = remaining.pop_front() {
-                    if self.should_skip_bookline_char(ch) {
+                while let Some(item) = remaining.pop_front() {
+                    let ch = item.ch;
+                    if self.should_skip_bookline_char(ch) || self.is_name_frame_char(ch) {
                         continue;
                     }
-                    if let Some(last) = comment_last_slot {
-                        if self.book.punctuation.comment_nop.chars.contains(&ch) {
-                            if let Some(spec) = self.build_text_glyph(last, ch, true, true, false) {
-                                current_page.glyphs.push(spec);
-                            }
-                        }
+                    if let Some(last) = comment_last_slot
+                        && self.book.punctuation.comment_nop.chars.contains(&ch)
+                        && let Some(spec) =
+                            self.build_text_glyph(last, ch, self.comment_role(item), true, false)
+                    {
+                        current_page.glyphs.push(spec);
                     }
                 }
                 break;
             }
-            let take_pairs = slots_in_column.min(slot_needed);
-            if *pcnt + take_pairs > self.layout.per_page {
+            let columns = self.layout.comment_columns;
+            let take_rows = slots_in_column.min(slot_needed);
+            if *pcnt + take_rows > self.layout.per_page {
                 self.finalize_page(
                     current_page,
                     pages,
@@ -305,54 +665,38 @@ impl<'a> LayoutEngine<'a> {
             }
 
             let mut local_chars = Vec::new();
-            let mut needed_chars = take_pairs * 2;
-            while let Some(ch) = remaining.pop_front() {
-                if self.comment_char_consumes_slot(ch) {
+            let mut needed_chars = take_rows * columns;
+            while let Some(item) = remaining.pop_front() {
+                if self.comment_char_consumes_slot(item.ch) {
                     needed_chars = needed_chars.saturating_sub(1);
                 }
-                local_chars.push(ch);
+                local_chars.push(item);
                 if needed_chars == 0 || remaining.is_empty() {
                     break;
                 }
             }
 
-            let mut positions: Vec<Cell> = Vec::with_capacity(take_pairs * 2);
-            for offset in 1..=take_pairs {
-                if let Some(pos) = self.layout.pos_right(*pcnt + offset) {
-                    positions.push(*pos);
-                } else {
-                    self.finalize_page(
-                        current_page,
-                        pages,
-                        pcnt,
-                        generated_pages,
-                        next_page_number,
-                        title_text,
-                    );
-                    comment_last_slot = None;
-                    for ch in local_chars.into_iter().rev() {
-                        remaining.push_front(ch);
-                    }
-                    continue 'outer;
-                }
-            }
-            for offset in 1..=take_pairs {
-                if let Some(pos) = self.layout.pos_left(*pcnt + offset) {
-                    positions.push(*pos);
-                } else {
-                    self.finalize_page(
-                        current_page,
-                        pages,
-                        pcnt,
-                        generated_pages,
-                        next_page_number,
-                        title_text,
-                    );
-                    comment_last_slot = None;
-                    for ch in local_chars.into_iter().rev() {
-                        remaining.push_front(ch);
+            let sub_rows = self.comment_sub_rows(&local_chars, take_rows);
+            let mut positions: Vec<Cell> = Vec::with_capacity(take_rows * columns);
+            for (sub, rows) in sub_rows.into_iter().enumerate() {
+                for offset in 1..=rows {
+                    if let Some(pos) = self.layout.pos_comment(*pcnt + offset, sub) {
+                        positions.push(*pos);
+                    } else {
+                        self.finalize_page(
+                            current_page,
+                            pages,
+                            pcnt,
+                            generated_pages,
+                            next_page_number,
+                            title_text,
+                        );
+                        comment_last_slot = None;
+                        for item in local_chars.into_iter().rev() {
+                            remaining.push_front(item);
+                        }
+                        continue 'outer;
                     }
-                    continue 'outer;
                 }
             }
 
@@ -361,7 +705,12 @@ impl<'a> LayoutEngine<'a> {
             let mut non_nop_count = 0usize;
 
             for idx in 0..local_chars.len() {
-                let ch = local_chars[idx];
+                let item = local_chars[idx];
+                let ch = item.ch;
+                let role = self.comment_role(item);
+                if self.is_name_frame_char(ch) {
+                    continue;
+                }
                 if self.book.book_line_flag {
                     if ch == '《' {
                         comment_bookline_active = true;
@@ -374,17 +723,17 @@ impl<'a> LayoutEngine<'a> {
                 let is_rot = self.book.punctuation.comment_rotate.chars.contains(&ch);
                 let is_nop = self.book.punctuation.comment_nop.chars.contains(&ch);
                 if is_nop {
-                    if let Some(last) = last_pos {
-                        if let Some(spec) = self.build_text_glyph(last, ch, true, true, false) {
-                            current_page.glyphs.push(spec);
-                        }
+                    if let Some(last) = last_pos
+                        && let Some(spec) = self.build_text_glyph(last, ch, role, true, false)
+                    {
+                        current_page.glyphs.push(spec);
                     }
                     continue;
                 }
                 let pos = match pos_iter.next() {
                     Some(p) => p,
                     None => {
-                        remaining.push_front(ch);
+                        remaining.push_front(item);
                         for rest in local_chars[idx + 1..].iter().rev() {
                             remaining.push_front(*rest);
                         }
@@ -395,132 +744,300 @@ impl<'a> LayoutEngine<'a> {
                 comment_last_slot = Some(pos);
                 non_nop_count += 1;
                 if is_rot {
-                    if let Some(spec) = self.build_text_glyph(pos, ch, true, false, true) {
+                    if let Some(spec) = self.build_text_glyph(pos, ch, role, false, true) {
                         current_page.glyphs.push(spec);
                     }
-                } else if let Some(spec) = self.build_text_glyph(pos, ch, true, false, false) {
+                } else if let Some(spec) = self.build_text_glyph(pos, ch, role, false, false) {
                     current_page.glyphs.push(spec);
                 }
-                if comment_bookline_active && self.book.book_line_flag && ch != ' ' {
-                    if let Some(bline) = &self.book.bookline {
-                        current_page.lines.push(LineSpec {
-                            x1: pos.x - bline.width,
-                            x2: pos.x - bline.width,
-                            y1: pos.y - self.layout.rh * 0.3,
-                            y2: pos.y + self.layout.rh * 0.7,
-                            width: bline.width,
-                            color: bline.color,
-                            wavy: true,
-                        });
-                    }
+                if comment_bookline_active
+                    && self.book.book_line_flag
+                    && ch != ' '
+                    && let Some(bline) = &self.book.bookline
+                {
+                    current_page.lines.push(LineSpec {
+                        x1: pos.x - bline.width,
+                        x2: pos.x - bline.width,
+                        y1: pos.y - self.layout.rh * 0.3,
+                        y2: pos.y + self.layout.rh * 0.7,
+                        width: bline.width,
+                        color: bline.color,
+                        wavy: true,
+                    });
                 }
             }
 
             if non_nop_count == 0 {
                 continue 'outer;
             }
-            let slots_used = (non_nop_count + 1) / 2;
+            let slots_used = non_nop_count.div_ceil(columns);
             *pcnt += slots_used;
         }
 
         if !remaining.is_empty() {
-            queue.extend(remaining.into_iter());
+            queue.extend(remaining);
         }
         Ok(())
     }
 
+    /// 各批注小行本段的字数。默认自右向左逐行填满；均分模式下末段字数不足时
+    /// 平均分配，多出的字放在右侧小行。本段行数取各小行所需的最大值，
+    /// 双行时逐行填满已是右多左少至多一字，均分只对三行批注有区别
+    fn comment_sub_rows(&self, local_chars: &[CommentChar], take_rows: usize) -> Vec<usize> {
+        let columns = self.layout.comment_columns;
+        let consuming = local_chars
+            .iter()
+            .filter(|item| self.comment_char_consumes_slot(item.ch))
+            .count();
+        if !self.book.annotations.balance || consuming >= take_rows * columns {
+            return vec![take_rows; columns];
+        }
+        (0..columns)
+            .map(|sub| {
+                (consuming / columns + usize::from(sub < consuming % columns)).min(take_rows)
+            })
+            .collect()
+    }
+
+    pub(crate) fn comment_role(&self, item: CommentChar) -> GlyphRole<'a> {
+        GlyphRole::Comment {
+            style: &self.book.annotations.styles[item.style],
+            label: item.label,
+        }
+    }
+
+    /// 按角色选字体并确定字号、颜色与字体自带旋转，坐标留待调用方决定；
+    /// 有外字图片的字、记号以图片代替；字形簇占位字经整形取得字形号，ch 记为其基字；
+    /// 字体均缺字（或外字记号没有图片）时以 □ 代替。字体切换标记中的字先只用所指定的槽位，
+    /// 该槽位缺字时照常按角色的字体序列选取
+    pub(crate) fn styled_glyph(
+        &self,
+        ch: char,
+        role: GlyphRole<'_>,
+    ) -> Option<(GlyphSpec, &LoadedFont)> {
+        if let Some(slot) = self.clusters.font_slot(ch)
+            && let Some(forced) = self
+                .stacked_glyph(ch, role, &[slot])
+                .filter(|(glyph, _)| glyph.missing.is_none())
+        {
+            return Some(forced);
+        }
+        self.stacked_glyph(ch, role, self.role_stack(role))
+    }
+
+    fn stacked_glyph(
+        &self,
+        ch: char,
+        role: GlyphRole<'_>,
+        stack: &[usize],
+    ) -> Option<(GlyphSpec, &LoadedFont)> {
+        let (ch, cluster) = match self.clusters.get(ch) {
+            // 字体切换标记中的单字按原字处理
+            Some(cluster) if cluster.chars().nth(1).is_none() => (cluster.chars().next()?, None),
+            cluster => (ch, cluster),
+        };
+        let mut buf = [0; 4];
+        let text = match cluster {
+            Some(cluster) => cluster,
+            None => &*ch.encode_utf8(&mut buf),
+        };
+        if let Some(path) = self.glyph_images.get(text) {
+            let pick = self.fonts.first_font(stack)?;
+            let mut glyph = self.role_glyph(ch, &pick, role);
+            glyph.image = Some(path.to_path_buf());
+            return Some((glyph, pick.font));
+        }
+        if let Some(sequence) = cluster.filter(|cluster| cluster.starts_with(is_ids_operator)) {
+            return self.composed_glyph(sequence, role, stack);
+        }
+        let (mut ch, mut pick) = match cluster {
+            Some(cluster) if is_token(cluster) => ('□', None),
+            Some(cluster) => (
+                cluster.chars().next()?,
+                self.fonts.pick_cluster(cluster, stack),
+            ),
+            None => self.pick_with_try_st(ch, stack),
+        };
+        let missing = pick.is_none().then(|| text.to_string());
+        if missing.is_some() {
+            ch = '□';
+            pick = self.fonts.pick_font(ch, stack);
+        }
+        let font_pick = pick?;
+        let mut glyph = self.role_glyph(ch, &font_pick, role);
+        glyph.missing = missing;
+        let shaped = cluster
+            .filter(|_| ch != '□')
+            .map(|cluster| font_pick.font.shape(cluster))
+            .unwrap_or_default();
+        match shaped.as_slice() {
+            [] => {}
+            [single] => glyph.glyph_id = Some(single.glyph_id),
+            [first, ..] => {
+                glyph.glyph_id = Some(first.glyph_id);
+                glyph.parts = shaped
+                    .iter()
+                    .map(|shaped| GlyphPart {
+                        ch,
+                        font_idx: font_pick.slot_index,
+                        glyph_id: Some(shaped.glyph_id),
+                        dx: shaped.dx,
+                        dy: shaped.dy,
+                        scale: 1.0,
+                        scale_x: 1.0,
+                    })
+                    .collect();
+            }
+        }
+        Some((glyph, font_pick.font))
+    }
+
+    /// IDS 拼合的字：把各部件缩放后排入字框中的子框。
+    /// 字号、颜色取自首个部件所用字体，各部件以字框底边对齐其字身
+    fn composed_glyph(
+        &self,
+        sequence: &str,
+        role: GlyphRole<'_>,
+        stack: &[usize],
+    ) -> Option<(GlyphSpec, &LoadedFont)> {
+        let components = compose(sequence)?;
+        let picks: Vec<(char, FontPick<'_>)> = components
+            .iter()
+            .map(|&(ch, _)| match self.pick_with_try_st(ch, stack) {
+                (ch, Some(pick)) => Some((ch, pick)),
+                (_, None) => self.fonts.pick_font('□', stack).map(|pick| ('□', pick)),
+            })
+            .collect::<Option<_>>()?;
+        let (first, main) = picks.first()?;
+        let mut glyph = self.role_glyph(*first, main, role);
+        // 有部件以 □ 拼入时整个序列记为缺字，便于以外字图片代替
+        let incomplete = components
+            .iter()
+            .zip(&picks)
+            .any(|(&(component, _), &(ch, _))| ch == '□' && component != '□');
+        glyph.missing = incomplete.then(|| sequence.to_string());
+        // 字身底边相对基线的位置（以字号计），各部件缩小后仍使字身落在子框内
+        let descent = main
+            .font
+            .font
+            .horizontal_line_metrics(1.0)
+            .map_or(0.0, |metrics| metrics.descent);
+        glyph.parts = components
+            .iter()
+            .zip(&picks)
+            .map(|(&(_, cell), &(ch, ref pick))| GlyphPart {
+                ch,
+                font_idx: pick.slot_index,
+                glyph_id: None,
+                dx: cell.x,
+                dy: cell.y + descent * (1.0 - cell.height),
+                scale: cell.height,
+                scale_x: cell.width / cell.height,
+            })
+            .collect();
+        Some((glyph, main.font))
+    }
+
+    fn role_stack<'s>(&'s self, role: GlyphRole<'s>) -> &'s [usize] {
+        match role {
+            GlyphRole::Text => &self.fonts.text_stack,
+            GlyphRole::Comment { style, .. } => &style.stack,
+        }
+    }
+
+    /// 以所选字体与角色定出字号与颜色的字形，坐标待定
+    fn role_glyph(&self, ch: char, pick: &FontPick<'_>, role: GlyphRole<'_>) -> GlyphSpec {
+        let font_size = match role {
+            GlyphRole::Text => pick.font.slot.text_size,
+            GlyphRole::Comment { style, .. } => {
+                style.font_size.unwrap_or(pick.font.slot.comment_size)
+            }
+        };
+        let mut color = match role {
+            GlyphRole::Text => self.book.text_font_color,
+            GlyphRole::Comment { style, label } => {
+                let label_color = self.book.annotations.label_color.filter(|_| label);
+                label_color.unwrap_or(style.color)
+            }
+        };
+        if self.book.text_modes.only_period && ch == '。' {
+            color = self.book.text_modes.only_period_color.unwrap_or(color);
+        }
+        GlyphSpec {
+            rotate_deg: pick.font.slot.rotate_deg,
+            ..GlyphSpec::new(ch, pick.slot_index, font_size, 0.0, 0.0, color)
+        }
+    }
+
     fn build_text_glyph(
         &self,
         pos: Cell,
         ch: char,
-        is_comment: bool,
+        role: GlyphRole<'_>,
         is_nop: bool,
         is_rot: bool,
     ) -> Option<GlyphSpec> {
-        let stack = if is_comment {
-            &self.fonts.comment_stack
+        let (mut glyph, font) = self.styled_glyph(ch, role)?;
+        // 字体自带竖排字形时直接竖写，只在缺少时才旋转横排字形
+        let vertical = if is_nop || !glyph.parts.is_empty() || glyph.image.is_some() {
+            None
         } else {
-            &self.fonts.text_stack
+            font.vertical_glyph(&glyph)
         };
-        let (mut ch, mut pick) = self.pick_with_try_st(ch, stack);
-        if pick.is_none() {
-            ch = '□';
-            pick = self.fonts.pick_font(ch, stack);
+        if vertical.is_some() {
+            glyph.glyph_id = vertical;
         }
-        pick.map(|font_pick| {
-            let mut font_size = if is_comment {
-                font_pick.font.slot.comment_size
-            } else {
-                font_pick.font.slot.text_size
-            };
-            let width = if is_comment {
-                self.layout.cw / 2.0
-            } else {
-                self.layout.cw
-            };
+        let is_rot = is_rot && vertical.is_none();
+        let is_comment = matches!(role, GlyphRole::Comment { .. });
+        let width = if is_comment {
+            self.layout.comment_cw()
+        } else {
+            self.layout.cw
+        };
 
-            let mut fx = pos.x;
-            let mut fy = pos.y;
-            let mut color = if is_comment {
-                self.book.comment_font_color
-            } else {
-                self.book.text_font_color
-            };
-            if self.book.text_modes.only_period && ch == '。' {
-                color = self.book.text_modes.only_period_color.unwrap_or(color);
-            }
-            let mut rotate_deg = font_pick.font.slot.rotate_deg;
+        let mut fx = pos.x;
+        let mut fy = pos.y;
+        if !is_nop && !is_rot {
+            fx += (width - glyph.font_size) / 2.0;
+        }
+        if is_comment {
+            fy += (self.layout.rh - glyph.font_size) / 4.0;
+        }
 
-            if !is_nop && !is_rot {
-                fx += (width - font_size) / 2.0;
-            }
-            if is_comment {
-                fy += (self.layout.rh - font_size) / 4.0;
-            }
+        if is_nop {
+            let adj = self.nop_adjust(is_comment);
+            glyph.font_size *= adj.scale;
+            fx += width * adj.offset_x;
+            fy -= self.layout.rh * adj.offset_y;
+        }
 
-            if is_nop {
-                let adj = if is_comment {
-                    &self.book.punctuation.comment_nop
-                } else {
-                    &self.book.punctuation.text_nop
-                };
-                font_size *= adj.scale;
-                let cw = if is_comment {
-                    self.layout.cw / 2.0
-                } else {
-                    self.layout.cw
-                };
-                fx += cw * adj.offset_x;
-                fy -= self.layout.rh * adj.offset_y;
-            }
+        if is_rot {
+            let adj = self.rotate_adjust(is_comment);
+            glyph.font_size *= adj.scale;
+            fx += width * adj.offset_x;
+            fy += self.layout.rh * adj.offset_y;
+            glyph.rotate_deg = -90.0;
+        }
 
-            if is_rot {
-                let adj = if is_comment {
-                    &self.book.punctuation.comment_rotate
-                } else {
-                    &self.book.punctuation.text_rotate
-                };
-                font_size *= adj.scale;
-                let cw = if is_comment {
-                    self.layout.cw / 2.0
-                } else {
-                    self.layout.cw
-                };
-                fx += cw * adj.offset_x;
-                fy += self.layout.rh * adj.offset_y;
-                rotate_deg = -90.0;
-            }
+        glyph.x = fx;
+        glyph.y = fy;
+        Some(glyph)
+    }
 
-            GlyphSpec {
-                ch,
-                font_idx: font_pick.slot_index,
-                font_size,
-                x: fx,
-                y: fy,
-                rotate_deg,
-                color,
-            }
-        })
+    pub(crate) fn nop_adjust(&self, is_comment: bool) -> &MarkAdjust {
+        if is_comment {
+            &self.book.punctuation.comment_nop
+        } else {
+            &self.book.punctuation.text_nop
+        }
+    }
+
+    pub(crate) fn rotate_adjust(&self, is_comment: bool) -> &MarkAdjust {
+        if is_comment {
+            &self.book.punctuation.comment_rotate
+        } else {
+            &self.book.punctuation.text_rotate
+        }
     }
 
     fn pick_with_try_st<'font>(
@@ -560,7 +1077,7 @@ impl<'a> LayoutEngine<'a> {
             .map(|pick| (candidate, pick))
     }
 
-    fn finalize_page(
+    pub(crate) fn finalize_page(
         &self,
         current_page: &mut PagePlan,
         pages: &mut Vec<PagePlan>,
@@ -576,45 +1093,350 @@ impl<'a> LayoutEngine<'a> {
         *pcnt = 0;
         *next_page_number += 1;
         current_page.glyphs.clear();
+        current_page.lines.clear();
         current_page.number = *next_page_number;
         current_page.title = title_text.to_string();
     }
 
-    fn skip_row_padding<'b>(&self, chars: &mut Peekable<Chars<'b>>) {
-        if self.book.row_num == 0 {
-            return;
-        }
-        for _ in 0..self.book.row_num.saturating_sub(1) {
-            if chars.next().is_none() {
-                break;
-            }
+    /// 跳过控制符所在行的补齐字符，返回本段正文不足、须从段末补齐空格中扣除的个数
+    fn skip_row_padding<'b>(&self, chars: &mut Peekable<Chars<'b>>) -> usize {
+        let mut left = self.book.row_num.saturating_sub(1);
+        while left > 0 && chars.next().is_some() {
+            left -= 1;
         }
+        left
     }
 
     fn comment_char_consumes_slot(&self, ch: char) -> bool {
-        if self.should_skip_bookline_char(ch) {
+        if self.should_skip_bookline_char(ch) || self.is_name_frame_char(ch) {
             return false;
         }
         !self.book.punctuation.comment_nop.chars.contains(&ch)
     }
 
-    fn count_comment_slots(&self, queue: &VecDeque<char>) -> usize {
+    fn count_comment_slots(&self, queue: &VecDeque<CommentChar>) -> usize {
         let consuming = queue
             .iter()
-            .copied()
-            .filter(|ch| self.comment_char_consumes_slot(*ch))
+            .filter(|item| self.comment_char_consumes_slot(item.ch))
             .count();
-        (consuming + 1) / 2
+        consuming.div_ceil(self.layout.comment_columns)
     }
 
-    fn should_skip_bookline_char(&self, ch: char) -> bool {
+    pub(crate) fn should_skip_bookline_char(&self, ch: char) -> bool {
         self.book.book_line_flag && (ch == '《' || ch == '》')
     }
 
-    fn reached_limit(&self, generated_pages: usize) -> bool {
+    /// 批注中不绘制名框，只去掉标记
+    pub(crate) fn is_name_frame_char(&self, ch: char) -> bool {
+        self.book.name_frame.is_some() && (ch == '<' || ch == '>')
+    }
+
+    pub(crate) fn reached_limit(&self, generated_pages: usize) -> bool {
         self.options
             .test_pages
             .map(|limit| generated_pages >= limit)
             .unwrap_or(false)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::color::RgbColor;
+    use crate::config::BookConfig;
+    use crate::fixtures::{typeset_sample, typeset_with_fonts};
+    use crate::fonts::FontMap;
+    use crate::glyph_images::GlyphImages;
+    use crate::plan::{DocumentPlan, GlyphSpec};
+    use crate::preprocess::TextCorpus;
+
+    /// 首页上第一个 ch 字
+    fn glyph(plan: &DocumentPlan, ch: char) -> &GlyphSpec {
+        plan.pages[0]
+            .glyphs
+            .iter()
+            .find(|g| g.ch == ch)
+            .expect("glyph placed")
+    }
+
+    #[test]
+    fn name_frame_splits_at_column_break() {
+        let plan = typeset_sample(&["if_name_frame=1"], |book| {
+            format!("{}<乙丙>", "甲".repeat(book.row_num - 1))
+        });
+        let lines = &plan.pages[0].lines;
+        // 两段各有左右两边，只在名框首尾封口
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines.iter().filter(|line| line.y1 == line.y2).count(), 2);
+        assert!(
+            plan.pages[0]
+                .glyphs
+                .iter()
+                .all(|g| g.ch != '<' && g.ch != '>')
+        );
+    }
+
+    #[test]
+    fn combining_sequences_are_shaped_into_one_slot() {
+        let plan = typeset_sample(&[], |_| "Ae\u{301}x\u{301}B".to_string());
+        let glyphs = &plan.pages[0].glyphs;
+        let chars: Vec<char> = glyphs.iter().map(|g| g.ch).collect();
+        assert_eq!(
+            chars[..4],
+            ['A', 'e', 'x', 'B'],
+            "each cluster takes one slot"
+        );
+        // e + 尖音符由整形合成单个字形；x 无预组字，附加符随基字另行绘出
+        assert!(glyphs[1].glyph_id.is_some() && glyphs[1].parts.is_empty());
+        assert_eq!(glyphs[2].parts.len(), 2);
+        let pitch = |a: &GlyphSpec, b: &GlyphSpec| a.y - b.y;
+        assert!((pitch(&glyphs[0], &glyphs[1]) - pitch(&glyphs[1], &glyphs[2])).abs() < 0.01);
+    }
+
+    #[test]
+    fn ideographic_description_sequences_compose_from_components() {
+        let plan = typeset_sample(&[], |_| "A⿰ab⿱c".to_string());
+        let glyphs = &plan.pages[0].glyphs;
+        let parts = &glyphs[1].parts;
+        assert_eq!(parts.len(), 2, "the sequence fills one slot with two parts");
+        assert_eq!((parts[0].ch, parts[1].ch), ('a', 'b'));
+        assert_eq!((parts[0].scale, parts[0].scale_x), (1.0, 0.5));
+        assert_eq!((parts[0].dx, parts[1].dx), (0.0, 0.5));
+        // 不完整的序列按原字排，描述符与部件各占一格
+        assert!(glyphs[2].parts.is_empty());
+        assert_eq!(glyphs[3].ch, 'c');
+    }
+
+    #[test]
+    fn braces_without_a_glyph_image_are_replaced_like_text() {
+        // books/01 的 exp_replace_comma 含 {〔|}〕；没有对应外字图片的 {艸乞} 照常替换
+        let plan = typeset_sample(&["if_onlyperiod=0"], |_| "甲{艸乞}乙".to_string());
+        // 测试字体不含汉字，按缺字记录的原字比对
+        let text: String = plan.pages[0]
+            .glyphs
+            .iter()
+            .filter_map(|g| g.missing.as_deref())
+            .collect();
+        assert_eq!(text, "甲〔艸乞〕乙");
+
+        let book = BookConfig::load("books/01/book.cfg").expect("load sample book configuration");
+        let mut images = GlyphImages::default();
+        images.insert("{艸乞}", "glyphs/cao.png");
+        let corpus = TextCorpus::from_texts_with_glyphs(&book, &images, [(1, "甲{艸乞}乙")])
+            .expect("build corpus");
+        let data = &corpus.entry(1).expect("entry").data;
+        let token = data.chars().nth(1).expect("token placeholder");
+        assert_eq!(corpus.clusters.get(token), Some("{艸乞}"));
+        assert_eq!(data.chars().nth(2), Some('乙'));
+    }
+
+    #[test]
+    fn missing_glyphs_are_reported_by_file_and_page() {
+        let plan = typeset_sample(&[], |_| "A\u{E000}B\u{E000}⿰a\u{E001}".to_string());
+        let glyphs = &plan.pages[0].glyphs;
+        assert_eq!(glyphs[1].ch, '□');
+        assert_eq!(glyphs[1].missing.as_deref(), Some("\u{E000}"));
+        assert_eq!(glyphs[2].missing, None);
+        let texts: Vec<&str> = plan.missing.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(texts, ["⿰a\u{E001}", "\u{E000}"], "sorted by code point");
+        let report = &plan.missing[1];
+        assert_eq!(report.count, 2);
+        assert_eq!(report.chapters, ["001.txt"]);
+        assert_eq!(report.pages, [1]);
+        assert!(
+            plan.missing_report()
+                .contains("\u{E000}\tU+E000\t2\t001.txt\t1\n")
+        );
+    }
+
+    #[test]
+    fn font_markup_and_font_map_choose_the_slot() {
+        let mut font_map = FontMap::default();
+        font_map
+            .parse("β|2\nU+03B3|9\n")
+            .expect_err("slot out of range");
+        font_map
+            .parse("# 字体映射\nβδ|2\n")
+            .expect("parse font map");
+        let plan = typeset_with_fonts(
+            &[],
+            |_| "αβ{font3:αβ}γ{font4:α}".to_string(),
+            |fonts| fonts.font_map = font_map,
+        );
+        let slots: Vec<(char, usize)> = plan.pages[0].glyphs[..6]
+            .iter()
+            .map(|glyph| (glyph.ch, glyph.font_idx))
+            .collect();
+        // 标记优先于映射；槽位 4 未配置字体时照常按字体序列选取
+        assert_eq!(
+            slots,
+            [('α', 1), ('β', 2), ('α', 3), ('β', 3), ('γ', 1), ('α', 1)]
+        );
+    }
+
+    #[test]
+    fn vertical_forms_replace_rotation_when_the_font_has_them() {
+        // 以 V 的字形冒充 X 的 vert/vrt2 竖排替换字形；Y 没有替换字形
+        let mut stub = 0;
+        let plan = typeset_with_fonts(
+            &["text_comma_90=XY"],
+            |_| "XY".to_string(),
+            |fonts| {
+                for font in fonts.slots.iter_mut().flatten() {
+                    stub = font.font.lookup_glyph_index('V');
+                    let x = font.font.lookup_glyph_index('X');
+                    font.vertical.insert(x, stub);
+                }
+            },
+        );
+        assert_ne!(stub, 0);
+        assert_eq!(glyph(&plan, 'X').glyph_id, Some(stub));
+        assert_eq!(glyph(&plan, 'X').rotate_deg, 0.0);
+        assert_eq!(glyph(&plan, 'Y').glyph_id, None);
+        assert_eq!(glyph(&plan, 'Y').rotate_deg, -90.0);
+    }
+
+    #[test]
+    fn nested_annotations_keep_their_own_style() {
+        let plan = typeset_sample(
+            &[
+                "annotation1_tag=〖〗",
+                "annotation1_font_size=12",
+                "annotation1_font_color=#ff0000",
+                "comment_label_color=#0000ff",
+            ],
+            |_| "A【〘B〙C〖DE〗F】G".to_string(),
+        );
+        let glyphs = &plan.pages[0].glyphs;
+        let red = RgbColor::parse("#ff0000").unwrap();
+        assert_eq!(glyph(&plan, 'B').color, RgbColor::parse("#0000ff").unwrap());
+        assert_eq!(glyph(&plan, 'D').color, red);
+        assert_eq!(glyph(&plan, 'E').font_size, 12.0);
+        assert_eq!(glyph(&plan, 'C').color, glyph(&plan, 'F').color);
+        assert_ne!(glyph(&plan, 'F').color, red);
+        // 五个批注字占三格，正文 G 落在第五格
+        assert_eq!(glyphs.iter().filter(|g| g.ch != ' ').count(), 7);
+        assert!(glyph(&plan, 'G').y < glyph(&plan, 'F').y);
+    }
+
+    #[test]
+    fn three_line_annotations_fill_sub_columns_right_to_left() {
+        let plan = typeset_sample(&["comment_col_num=3"], |_| "【ABCDEFG】H".to_string());
+        assert_eq!(glyph(&plan, 'A').x, glyph(&plan, 'C').x);
+        assert!(glyph(&plan, 'D').x < glyph(&plan, 'A').x);
+        assert!(glyph(&plan, 'G').x < glyph(&plan, 'D').x);
+        assert_eq!(glyph(&plan, 'G').y, glyph(&plan, 'A').y);
+        // 七个批注字占三格，正文 H 落在第四格
+        assert!(glyph(&plan, 'H').y < glyph(&plan, 'C').y);
+    }
+
+    #[test]
+    fn balanced_annotations_even_out_the_last_segment() {
+        let plan = typeset_sample(&["comment_col_num=3", "if_comment_balance=1"], |_| {
+            "【ABCDEFG】H".to_string()
+        });
+        // 三、二、二分布
+        assert_eq!(glyph(&plan, 'D').x, glyph(&plan, 'E').x);
+        assert!(glyph(&plan, 'F').x < glyph(&plan, 'E').x);
+        assert_eq!(glyph(&plan, 'G').y, glyph(&plan, 'E').y);
+        assert!(glyph(&plan, 'H').y < glyph(&plan, 'C').y);
+    }
+
+    #[test]
+    fn two_column_annotations_are_balanced_with_or_without_the_flag() {
+        for balance in ["if_comment_balance=0", "if_comment_balance=1"] {
+            let plan = typeset_sample(&["comment_col_num=2", balance], |_| {
+                "【ABCDE】H".to_string()
+            });
+            // 右三左二
+            assert_eq!(glyph(&plan, 'A').x, glyph(&plan, 'C').x, "{balance}");
+            assert!(glyph(&plan, 'D').x < glyph(&plan, 'C').x, "{balance}");
+            assert_eq!(glyph(&plan, 'D').x, glyph(&plan, 'E').x, "{balance}");
+            assert_eq!(glyph(&plan, 'D').y, glyph(&plan, 'A').y, "{balance}");
+            assert!(glyph(&plan, 'H').y < glyph(&plan, 'C').y, "{balance}");
+        }
+    }
+
+    #[test]
+    fn line_break_rules_keep_marks_off_column_edges() {
+        let rules = ["exp_line_head=Z", "exp_line_tail=Y"];
+        let filler = |book: &BookConfig| "A".repeat(book.row_num - 1);
+        let column_x = |plan: &DocumentPlan, ch: char| glyph(plan, ch).x;
+        let first_column = |plan: &DocumentPlan| column_x(plan, 'A');
+
+        // 列首禁则字连同前一字移至下一列
+        let plan = typeset_sample(&rules, |book| format!("{}BZ", filler(book)));
+        assert!(column_x(&plan, 'B') < first_column(&plan));
+        assert_eq!(column_x(&plan, 'B'), column_x(&plan, 'Z'));
+
+        // 列尾禁则字移至下一列
+        let plan = typeset_sample(&rules, |book| format!("{}YC", filler(book)));
+        assert!(column_x(&plan, 'Y') < first_column(&plan));
+
+        // 悬挂模式下列首禁则字留在本列末字之下
+        let plan = typeset_sample(&[rules[0], "if_line_head_hang=1"], |book| {
+            format!("{}BZC", filler(book))
+        });
+        assert_eq!(glyph(&plan, 'Z').x, glyph(&plan, 'B').x);
+        assert!(glyph(&plan, 'Z').y < glyph(&plan, 'B').y);
+        assert!(glyph(&plan, 'C').x < glyph(&plan, 'B').x);
+    }
+
+    #[test]
+    fn line_breaks_do_not_shift_the_following_paragraph() {
+        let top_row = |plan: &DocumentPlan| glyph(plan, 'A').y;
+        let filler = |book: &BookConfig| "A".repeat(book.row_num - 1);
+
+        // 列尾禁则字移至下一列多占一格，下一段仍从列首起排
+        let plan = typeset_sample(&["exp_line_tail=Y"], |book| {
+            format!("{}YC\nD", filler(book))
+        });
+        assert!(glyph(&plan, 'D').x < glyph(&plan, 'C').x);
+        assert_eq!(glyph(&plan, 'D').y, top_row(&plan));
+
+        // 悬挂的列首禁则字少占一格，下一段同样从列首起排
+        let plan = typeset_sample(&["exp_line_head=Z", "if_line_head_hang=1"], |book| {
+            format!("{}BZ\nD", filler(book))
+        });
+        assert!(glyph(&plan, 'D').x < glyph(&plan, 'B').x);
+        assert_eq!(glyph(&plan, 'D').y, top_row(&plan));
+    }
+
+    #[test]
+    fn hung_marks_stay_inside_name_frames_and_book_lines() {
+        let hang = ["exp_line_head=Z", "if_line_head_hang=1"];
+        let filler = |book: &BookConfig| "A".repeat(book.row_num - 1);
+
+        let plan = typeset_sample(&[hang[0], hang[1], "if_name_frame=1"], |book| {
+            format!("{}<BZ>C", filler(book))
+        });
+        let lowest = plan.pages[0]
+            .lines
+            .iter()
+            .map(|line| line.y1.min(line.y2))
+            .fold(f32::INFINITY, f32::min);
+        assert!(lowest < glyph(&plan, 'Z').y);
+
+        let plan = typeset_sample(&[hang[0], hang[1], "if_book_vline=1"], |book| {
+            format!("{}《BZ》C", filler(book))
+        });
+        let wavy = plan.pages[0].lines.iter().filter(|line| line.wavy).count();
+        assert_eq!(wavy, 2);
+    }
+
+    #[test]
+    fn hanging_punctuation_goes_below_the_column() {
+        let plan = typeset_sample(&["text_comma_nop=Q", "text_comma_nop_hang=1"], |book| {
+            format!("{}Q", "A".repeat(book.row_num))
+        });
+        let glyphs = &plan.pages[0].glyphs;
+        let mark = glyph(&plan, 'Q');
+        let lowest = glyphs
+            .iter()
+            .filter(|g| g.ch == 'A')
+            .min_by(|a, b| a.y.total_cmp(&b.y))
+            .expect("column placed");
+        assert!(mark.y < lowest.y);
+        let center = |g: &GlyphSpec| g.x + g.font_size / 2.0;
+        assert!((center(mark) - center(lowest)).abs() < 0.01);
+    }
+}
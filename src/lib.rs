@@ -0,0 +1,64 @@
+//! vRain 古籍刻本风格竖排排版。
+//!
+//! 流程分三步：读入配置与文本（[`BookConfig`]、[`CanvasConfig`]、[`TextCorpus`]），
+//! 由 [`Typesetter`] 排出与输出格式无关的 [`DocumentPlan`]，再交给 PDF、图片或 SVG 后端绘制。
+//! [`Project`] 把这些步骤串起来；配置、字体与文本均可来自文件或内存。
+//!
+//! ```no_run
+//! use vrain_rs::{BookConfig, CanvasConfig, PdfOptions, Project, TextCorpus, TypesetOptions};
+//! use std::path::Path;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let book = BookConfig::load("books/01/book.cfg")?;
+//! let canvas = CanvasConfig::load(format!("canvas/{}.cfg", book.canvas_id))?;
+//! let corpus = TextCorpus::from_texts(&book, [(1, "太史公曰：【注文】正文。")])?;
+//! let project = Project::builder()
+//!     .book(book)
+//!     .canvas(canvas)
+//!     .corpus(corpus)
+//!     .fonts_dir("fonts")
+//!     .build()?;
+//! let plan = project.typeset(TypesetOptions::default())?;
+//! project.render_pdf(&plan, Path::new("out.pdf"), &PdfOptions::default())?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod backend;
+pub mod background;
+pub mod clusters;
+pub mod color;
+pub mod config;
+#[cfg(test)]
+mod fixtures;
+mod flow_engine;
+pub mod fonts;
+mod frame;
+pub mod glyph_images;
+mod horizontal_engine;
+pub mod ids;
+pub mod layout;
+mod layout_engine;
+pub mod multirows;
+pub mod numerals;
+pub mod plan;
+pub mod preprocess;
+pub mod project;
+pub mod raster;
+pub mod renderer;
+pub mod svg;
+pub mod typesetter;
+
+pub use backend::{RenderContext, Renderer, SourceImage, render_plan};
+pub use config::{BookConfig, CanvasConfig, RawConfig};
+pub use fonts::{FONT_MAP_FILE, FontManager, FontMap};
+pub use glyph_images::GlyphImages;
+pub use layout::Layout;
+pub use numerals::NumeralMap;
+pub use plan::{DocumentPlan, TypesetOptions};
+pub use preprocess::{TextCorpus, load_corpus};
+pub use project::{Project, ProjectBuilder};
+pub use raster::{RasterFormat, RasterOptions, render_raster, render_raster_pages};
+pub use renderer::{PdfOptions, render_document, render_document_bytes};
+pub use svg::{SvgOptions, render_svg};
+pub use typesetter::Typesetter;
@@ -1,31 +1,15 @@
 mod args;
-mod color;
-mod config;
-mod fonts;
-mod layout;
-mod layout_engine;
-mod background;
-mod multirows;
-mod numerals;
-mod plan;
-mod preprocess;
-mod renderer;
-mod typesetter;
-
-use anyhow::{Result, anyhow, bail, Context};
+
+use anyhow::{Context, Result, anyhow, bail};
 use args::Cli;
 use clap::Parser;
-use config::{BookConfig, CanvasConfig};
-use fonts::FontManager;
-use layout::Layout;
-use multirows::MultiRowsMode;
-use numerals::NumeralMap;
-use plan::TypesetOptions;
-use preprocess::load_corpus;
-use image::{self as pdf_image, DynamicImage};
-use renderer::{RenderContext, render_document};
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use typesetter::Typesetter;
+use vrain_rs::background;
+use vrain_rs::{
+    BookConfig, CanvasConfig, FONT_MAP_FILE, FontMap, GlyphImages, NumeralMap, PdfOptions, Project,
+    RasterOptions, RawConfig, SourceImage, SvgOptions, TextCorpus, TypesetOptions, load_corpus,
+};
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
@@ -34,18 +18,31 @@ fn main() -> Result<()> {
         bail!("--to must be >= --from");
     }
 
-    let book_dir = cli.books_root.join(&cli.book_id);
-    let text_dir = book_dir.join("text");
-    ensure_exists(&book_dir, "book directory")?;
-    ensure_exists(&text_dir, "book text directory")?;
+    let book_dir = cli.book_id.as_ref().map(|id| cli.books_root.join(id));
+    if cli.input.is_none()
+        && let Some(book_dir) = &book_dir
+    {
+        ensure_exists(book_dir, "book directory")?;
+        ensure_exists(&book_dir.join("text"), "book text directory")?;
+    }
 
-    let book_cfg_path = book_dir.join("book.cfg");
-    ensure_exists(&book_cfg_path, "book configuration")?;
-    let book_cfg = BookConfig::load(&book_cfg_path)?;
+    // 指定 --input 时 book.cfg 可缺省，全部配置项由 --set 提供
+    let book_cfg_path = book_dir.as_ref().map(|dir| dir.join("book.cfg"));
+    let mut book_raw = match &book_cfg_path {
+        Some(path) if path.exists() || cli.input.is_none() => {
+            ensure_exists(path, "book configuration")?;
+            RawConfig::load(path)?
+        }
+        _ => RawConfig::parse("book.cfg", ""),
+    };
+    book_raw.apply_overrides(&cli.book_overrides)?;
+    let book_cfg = BookConfig::from_raw(&book_raw)?;
     book_cfg.validate()?;
     let canvas_cfg_path = cli.canvas_root.join(format!("{}.cfg", book_cfg.canvas_id));
     ensure_exists(&canvas_cfg_path, "canvas configuration")?;
-    let canvas_cfg = CanvasConfig::load(&canvas_cfg_path)?;
+    let mut canvas_raw = RawConfig::load(&canvas_cfg_path)?;
+    canvas_raw.apply_overrides(&cli.canvas_overrides)?;
+    let canvas_cfg = CanvasConfig::from_raw(&canvas_raw)?;
     canvas_cfg.validate()?;
     println!("Loaded '{}' by {}", book_cfg.title, book_cfg.author);
 
@@ -66,46 +63,71 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    let multirows_mode = MultiRowsMode::from_flags(
-        canvas_cfg.multirows_enabled,
-        canvas_cfg.multirows_count,
-        book_cfg.multirows_horizontal_layout,
-    );
-
-    let layout = Layout::build(&book_cfg, &canvas_cfg, multirows_mode)?;
-    let fonts = FontManager::new(&book_cfg, &cli.fonts_root)?;
+    let logo_image = canvas_cfg.logo.image.as_ref().and_then(|name| {
+        let path = cli.canvas_root.join(name);
+        match SourceImage::open(&path) {
+            Ok(img) => Some(img),
+            Err(err) => {
+                eprintln!("Logo image unavailable ({}): {}", path.display(), err);
+                None
+            }
+        }
+    });
+    let cover = book_dir.as_ref().and_then(|dir| {
+        load_first_available_image(&[dir.join("cover.jpg"), dir.join("cover.png")])
+    });
+
+    let glyph_images = match &book_dir {
+        Some(dir) => GlyphImages::load(&dir.join("glyphs"))?,
+        None => GlyphImages::default(),
+    };
+    let corpus = match &cli.input {
+        Some(input) => {
+            if to != cli.from {
+                bail!("--input holds a single text; --to must equal --from");
+            }
+            let text = read_input(input)?;
+            TextCorpus::from_texts_with_glyphs(&book_cfg, &glyph_images, [(cli.from, text)])?
+        }
+        None => {
+            let book_dir = book_dir
+                .as_ref()
+                .ok_or_else(|| anyhow!("--book or --input is required"))?;
+            load_corpus(book_dir, &book_cfg, &glyph_images)?
+        }
+    };
     let numerals = NumeralMap::load(cli.db_root.join("num2zh_jid.txt"))?;
+    let mut builder = Project::builder()
+        .book(book_cfg)
+        .canvas(canvas_cfg)
+        .fonts_dir(&cli.fonts_root)
+        .numerals(numerals)
+        .corpus(corpus)
+        .glyph_images(glyph_images);
+    if let Some(dir) = &book_dir {
+        builder = builder.font_map(FontMap::load(&dir.join(FONT_MAP_FILE))?);
+    }
+    if let Some((path, image)) = cover {
+        builder = builder.cover_image(path, image);
+    }
+    if let Some(image) = logo_image {
+        builder = builder.logo_image(image);
+    }
+    let project = builder.build()?;
     println!(
         "Layout: {} columns x {} rows ({} glyphs/page)",
-        canvas_cfg.leaf_col, book_cfg.row_num, layout.per_page
+        project.canvas().leaf_col,
+        project.book().row_num,
+        project.layout().per_page
     );
 
-    let corpus = load_corpus(&book_dir, &book_cfg)?;
-
-    let background_candidates = vec![
-        cli.canvas_root.join(format!("{}.jpg", book_cfg.canvas_id)),
-        cli.canvas_root.join(format!("{}.png", book_cfg.canvas_id)),
-    ];
-    let (_bg_path, mut background_image) = load_first_available_image(&background_candidates);
-    if background_image.is_none() {
-        background_image = Some(background::generate_bamboo_background(&canvas_cfg));
-    }
-
-    let cover_candidates = vec![book_dir.join("cover.jpg"), book_dir.join("cover.png")];
-    let (cover_plan_path, cover_image) = load_first_available_image(&cover_candidates);
-
-    let typeset_opts = TypesetOptions {
+    let plan = project.typeset(TypesetOptions {
         from: cli.from,
         to,
         test_pages: cli.test_pages,
         verbose: cli.verbose,
-        cover_image: cover_plan_path.clone(),
-    };
-
-    let mut typesetter =
-        Typesetter::new(&book_cfg, &layout, &fonts, &numerals, &corpus, typeset_opts)?;
-    let plan = typesetter.build_plan()?;
-    plan.validate()?;
+        cover_image: None,
+    })?;
     if let Some(path) = &cli.debug_plan {
         if let Err(err) = plan.write_debug_json(path) {
             eprintln!(
@@ -118,23 +140,75 @@ fn main() -> Result<()> {
         }
     }
 
-    let render_ctx = RenderContext {
-        book: &book_cfg,
-        canvas: &canvas_cfg,
-        fonts: &fonts,
-        numerals: &numerals,
-        background: background_image,
-        cover_image,
-    };
-    let output_name = format!("《{}》文本{}至{}.pdf", book_cfg.title, cli.from, to);
-    let output_path = book_dir.join(&output_name);
+    if let Some(path) = &cli.missing_report {
+        std::fs::write(path, plan.missing_report())
+            .with_context(|| format!("write missing report {}", path.display()))?;
+        println!("Missing glyph report written to {}", path.display());
+    }
+    if !plan.missing.is_empty() {
+        let occurrences: usize = plan.missing.iter().map(|glyph| glyph.count).sum();
+        println!(
+            "Missing glyphs: {} distinct, {} occurrences replaced with □",
+            plan.missing.len(),
+            occurrences
+        );
+        if cli.missing_report.is_none() {
+            print!("{}", plan.missing_report());
+        }
+        if cli.fail_on_missing {
+            bail!(
+                "{} characters have no glyph in any font",
+                plan.missing.len()
+            );
+        }
+    }
+
+    let output_path = cli.output.clone().unwrap_or_else(|| {
+        let output_name = format!("《{}》文本{}至{}.pdf", project.book().title, cli.from, to);
+        book_dir.clone().unwrap_or_default().join(output_name)
+    });
     println!("Rendering PDF to {}", output_path.display());
-    render_document(&plan, &render_ctx, &output_path)?;
+    let pdf_options = PdfOptions {
+        compress: cli.compress,
+    };
+    project.render_pdf(&plan, &output_path, &pdf_options)?;
+
+    let output_stem = output_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output")
+        .to_string();
+    if let Some(format) = cli.raster {
+        let output_dir = cli
+            .raster_dir
+            .clone()
+            .unwrap_or_else(|| output_path.with_file_name(format!("{output_stem}_pages")));
+        let options = RasterOptions {
+            format,
+            dpi: cli.dpi,
+            output_dir,
+        };
+        let written = project.render_raster(&plan, &output_path, &options)?;
+        println!(
+            "Rendered {} page images to {}",
+            written.len(),
+            options.output_dir.display()
+        );
+    }
 
-    if cli.compress {
-        if let Err(err) = compress_pdf(&output_path) {
-            eprintln!("PDF compression failed: {err}");
-        }
+    if cli.svg {
+        let options = SvgOptions {
+            output_dir: cli
+                .svg_dir
+                .clone()
+                .unwrap_or_else(|| output_path.with_file_name(format!("{output_stem}_svg"))),
+        };
+        let written = project.render_svg(&plan, &output_path, &options)?;
+        println!(
+            "Rendered {} SVG pages to {}",
+            written.len(),
+            options.output_dir.display()
+        );
     }
 
     println!("Done.");
@@ -148,54 +222,25 @@ fn ensure_exists(path: &Path, label: &str) -> Result<()> {
     Ok(())
 }
 
-fn compress_pdf(output: &Path) -> Result<()> {
-    // require gs to be present
-    if std::process::Command::new("which")
-        .arg("gs")
-        .status()
-        .map(|s| !s.success())
-        .unwrap_or(true)
-    {
-        println!("Ghostscript not found, skip compression. Install gs to enable -c.");
-        return Ok(());
-    }
-    let compressed = output.with_file_name(format!(
-        "{}_compressed.pdf",
-        output
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("output")
-    ));
-    let status = std::process::Command::new("gs")
-        .args([
-            "-sDEVICE=pdfwrite",
-            "-dCompatibilityLevel=1.4",
-            "-dPDFSETTINGS=/screen",
-            "-dNOPAUSE",
-            "-dQUIET",
-            "-dBATCH",
-        ])
-        .arg(format!("-sOutputFile={}", compressed.display()))
-        .arg(output)
-        .status();
-    match status {
-        Ok(code) if code.success() => {
-            println!("Compressed PDF saved to {}", compressed.display());
-            Ok(())
-        }
-        Ok(code) => Err(anyhow!("ghostscript exited with {}", code)),
-        Err(err) => Err(anyhow!("ghostscript invocation failed: {err}")),
+fn read_input(path: &Path) -> Result<String> {
+    if path == Path::new("-") {
+        let mut text = String::new();
+        std::io::stdin()
+            .read_to_string(&mut text)
+            .context("read text from stdin")?;
+        return Ok(text);
     }
+    std::fs::read_to_string(path).with_context(|| format!("read text {}", path.display()))
 }
 
-fn load_first_available_image(paths: &[PathBuf]) -> (Option<PathBuf>, Option<DynamicImage>) {
+fn load_first_available_image(paths: &[PathBuf]) -> Option<(PathBuf, SourceImage)> {
     for path in paths {
         if path.exists() {
-            match pdf_image::open(path) {
-                Ok(img) => return (Some(path.clone()), Some(img)),
+            match SourceImage::open(path) {
+                Ok(img) => return Some((path.clone(), img)),
                 Err(_) => continue,
             }
         }
     }
-    (None, None)
+    None
 }
@@ -3,7 +3,8 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
-#[derive(Debug)]
+/// 数字到汉字的映射（db/num2zh_jid.txt）；未收录的数字逐位转写
+#[derive(Debug, Default)]
 pub struct NumeralMap {
     map: HashMap<usize, String>,
 }
@@ -13,6 +14,11 @@ impl NumeralMap {
         let path = path.as_ref();
         let content =
             fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Ok(Self::parse(&content))
+    }
+
+    /// 解析 `数字|汉字` 格式的映射文本
+    pub fn parse(content: &str) -> Self {
         let mut map = HashMap::new();
         for line in content.lines() {
             let trimmed = line.trim();
@@ -20,13 +26,13 @@ impl NumeralMap {
                 continue;
             }
             let mut parts = trimmed.split('|');
-            if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
-                if let Ok(num) = key.parse::<usize>() {
-                    map.insert(num, value.to_string());
-                }
+            if let (Some(key), Some(value)) = (parts.next(), parts.next())
+                && let Ok(num) = key.parse::<usize>()
+            {
+                map.insert(num, value.to_string());
             }
         }
-        Ok(Self { map })
+        Self { map }
     }
 
     pub fn get(&self, num: usize) -> Option<&str> {
@@ -21,6 +21,81 @@ pub struct GlyphSpec {
     pub y: f32,
     pub rotate_deg: f32,
     pub color: RgbColor,
+    /// 字体的竖排替换字形号；为 None 时按 ch 取字形
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub glyph_id: Option<u16>,
+    /// 横向缩放比例，1 为原宽；IDS 拼合的部件按所占子框压扁或拉长
+    pub scale_x: f32,
+    /// 组成此字的各部件字形；非空时代替主字形绘制
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<GlyphPart>,
+    /// 代替字形绘制于字框中的图片
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<PathBuf>,
+    /// 各字体均缺而以 □ 代替的原文（单字、字形簇、外字记号或 IDS 部件）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub missing: Option<String>,
+}
+
+impl GlyphSpec {
+    /// 按字取形、不旋转不缩放的字形；其余字段按需以结构体更新语法另设
+    pub fn new(ch: char, font_idx: usize, font_size: f32, x: f32, y: f32, color: RgbColor) -> Self {
+        Self {
+            ch,
+            font_idx,
+            font_size,
+            x,
+            y,
+            rotate_deg: 0.0,
+            color,
+            glyph_id: None,
+            scale_x: 1.0,
+            parts: Vec::new(),
+            image: None,
+            missing: None,
+        }
+    }
+
+    /// 外字图片或 IDS 拼合字（部件按字取形，不带字形号），不按字形度量，占一字见方
+    pub fn is_synthetic(&self) -> bool {
+        self.image.is_some() || self.parts.iter().any(|part| part.glyph_id.is_none())
+    }
+
+    /// parts 中各部件按字号换算偏移、随主字形旋转后的独立字形
+    pub fn part_glyphs(&self) -> impl Iterator<Item = GlyphSpec> + '_ {
+        let (sin, cos) = self.rotate_deg.to_radians().sin_cos();
+        self.parts.iter().map(move |part| {
+            let dx = part.dx * self.font_size;
+            let dy = part.dy * self.font_size;
+            GlyphSpec {
+                rotate_deg: self.rotate_deg,
+                glyph_id: part.glyph_id,
+                scale_x: self.scale_x * part.scale_x,
+                ..GlyphSpec::new(
+                    part.ch,
+                    part.font_idx,
+                    self.font_size * part.scale,
+                    self.x + dx * cos - dy * sin,
+                    self.y + dx * sin + dy * cos,
+                    self.color,
+                )
+            }
+        })
+    }
+}
+
+/// 字的一个部件：字形簇整形所得的各字形，或 IDS 拼合的各部件。
+/// 偏移相对主字形原点、以主字形字号为单位，scale 为相对主字形字号的缩放
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct GlyphPart {
+    pub ch: char,
+    pub font_idx: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub glyph_id: Option<u16>,
+    pub dx: f32,
+    pub dy: f32,
+    pub scale: f32,
+    pub scale_x: f32,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -34,6 +109,12 @@ pub struct LineSpec {
     pub wavy: bool,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct PolygonSpec {
+    pub points: Vec<(f32, f32)>,
+    pub color: RgbColor,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct PagePlan {
     pub number: usize,
@@ -55,17 +136,56 @@ pub struct DocumentPlan {
     pub cover_path: Option<PathBuf>,
     pub pages: Vec<PagePlan>,
     pub outlines: Vec<OutlineEntry>,
+    /// 缺字报告，按码位排序
+    pub missing: Vec<MissingGlyph>,
+}
+
+/// 一个缺字在全书中的出现情况
+#[derive(Debug, Clone, Serialize)]
+pub struct MissingGlyph {
+    pub text: String,
+    pub count: usize,
+    /// 出现的文本文件，按排版顺序
+    pub chapters: Vec<String>,
+    pub pages: Vec<usize>,
+}
+
+impl MissingGlyph {
+    /// 各字的码位，如 `U+2A6D6`
+    pub fn code_points(&self) -> String {
+        self.text
+            .chars()
+            .map(|ch| format!("U+{:04X}", ch as u32))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
 }
 
-#[derive(Clone)]
+/// 排版范围与调试选项
+#[derive(Debug, Clone)]
 pub struct TypesetOptions {
+    /// 起止文本序号（含两端）
     pub from: usize,
     pub to: usize,
+    /// 仅生成前若干页，用于试排
     pub test_pages: Option<usize>,
     pub verbose: bool,
+    /// 封面图路径；为 None 时生成文字封面
     pub cover_image: Option<PathBuf>,
 }
 
+impl Default for TypesetOptions {
+    fn default() -> Self {
+        Self {
+            from: 1,
+            to: 1,
+            test_pages: None,
+            verbose: false,
+            cover_image: None,
+        }
+    }
+}
+
 impl DocumentPlan {
     pub fn write_debug_json<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         let file = File::create(path)?;
@@ -74,6 +194,23 @@ impl DocumentPlan {
         Ok(())
     }
 
+    /// 逐行列出缺字：字、码位、次数、所在文件与页码，以制表符分隔
+    pub fn missing_report(&self) -> String {
+        let mut report = String::from("字\t码位\t次数\t文件\t页码\n");
+        for missing in &self.missing {
+            let pages: Vec<String> = missing.pages.iter().map(usize::to_string).collect();
+            report.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                missing.text,
+                missing.code_points(),
+                missing.count,
+                missing.chapters.join(","),
+                pages.join(",")
+            ));
+        }
+        report
+    }
+
     pub fn validate(&self) -> Result<()> {
         if matches!(self.cover, CoverPlan::Image) && self.cover_path.is_none() {
             return Err(anyhow!(
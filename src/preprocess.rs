@@ -1,8 +1,14 @@
 #![allow(dead_code)]
 
-use crate::config::{BookConfig, ReplacementRules, TextModes};
+use crate::clusters::ClusterTable;
+use crate::config::{
+    ANNOTATION_LABEL_CLOSE, ANNOTATION_LABEL_OPEN, AnnotationConfig, BookConfig, LayoutMode,
+    ReplacementRules, TextModes,
+};
+use crate::glyph_images::GlyphImages;
 use anyhow::{Context, Result, anyhow};
 use std::fs;
+use std::ops::Range;
 use std::path::Path;
 
 #[derive(Debug, Clone)]
@@ -17,9 +23,70 @@ pub struct TextCorpus {
     pub entries: Vec<Option<TextEntry>>,
     pub has_text000: bool,
     pub has_text999: bool,
+    /// 正文中多字字形簇的占位字表
+    pub clusters: ClusterTable,
 }
 
 impl TextCorpus {
+    /// 由内存中的文本构建，每项为 (序号, 原始文本)；序号含义同 text/ 下的文件名。
+    /// 没有外字图片，`{…}` 均作普通文字
+    pub fn from_texts<S: AsRef<str>>(
+        book: &BookConfig,
+        texts: impl IntoIterator<Item = (usize, S)>,
+    ) -> Result<Self> {
+        Self::from_texts_with_glyphs(book, &GlyphImages::default(), texts)
+    }
+
+    /// 同 [`TextCorpus::from_texts`]，glyph_images 中登记的 `{名称}` 记号作一字处理
+    pub fn from_texts_with_glyphs<S: AsRef<str>>(
+        book: &BookConfig,
+        glyph_images: &GlyphImages,
+        texts: impl IntoIterator<Item = (usize, S)>,
+    ) -> Result<Self> {
+        let mut corpus = Self::empty(glyph_images);
+        for (ordinal, content) in texts {
+            corpus.insert(book, format!("{ordinal:03}.txt"), ordinal, content.as_ref())?;
+        }
+        if corpus.entries.iter().all(|e| e.is_none()) {
+            return Err(anyhow!("no text given"));
+        }
+        Ok(corpus)
+    }
+
+    /// 单章文本，作为第 1 篇
+    pub fn from_text(book: &BookConfig, text: &str) -> Result<Self> {
+        Self::from_texts(book, [(1, text)])
+    }
+
+    fn empty(glyph_images: &GlyphImages) -> Self {
+        Self {
+            entries: vec![None; 1000],
+            has_text000: false,
+            has_text999: false,
+            clusters: ClusterTable::with_tokens(glyph_images.tokens()),
+        }
+    }
+
+    fn insert(
+        &mut self,
+        book: &BookConfig,
+        name: String,
+        ordinal: usize,
+        content: &str,
+    ) -> Result<()> {
+        if ordinal >= self.entries.len() {
+            self.entries.resize(ordinal + 1, None);
+        }
+        self.has_text000 |= ordinal == 0;
+        self.has_text999 |= ordinal == 999;
+        self.entries[ordinal] = Some(TextEntry {
+            name,
+            ordinal,
+            data: process_text(content, book, &mut self.clusters)?,
+        });
+        Ok(())
+    }
+
     pub fn entry(&self, idx: usize) -> Result<&TextEntry> {
         self.entries
             .get(idx)
@@ -38,11 +105,14 @@ impl TextCorpus {
     }
 }
 
-pub fn load_corpus(book_dir: &Path, book: &BookConfig) -> Result<TextCorpus> {
+/// 读取 book_dir/text 下的各篇；glyph_images 中登记的 `{名称}` 记号作一字处理
+pub fn load_corpus(
+    book_dir: &Path,
+    book: &BookConfig,
+    glyph_images: &GlyphImages,
+) -> Result<TextCorpus> {
     let text_dir = book_dir.join("text");
-    let mut entries: Vec<Option<TextEntry>> = vec![None; 1000];
-    let mut has_text000 = false;
-    let mut has_text999 = false;
+    let mut corpus = TextCorpus::empty(glyph_images);
 
     let mut files = fs::read_dir(&text_dir)
         .with_context(|| format!("reading {}", text_dir.display()))?
@@ -61,40 +131,22 @@ pub fn load_corpus(book_dir: &Path, book: &BookConfig) -> Result<TextCorpus> {
         let file_name = entry.file_name().to_string_lossy().to_string();
         let lower = file_name.to_ascii_lowercase();
         let stem = lower.trim_end_matches(".txt");
-        if stem.chars().all(|c| c == '0') {
-            has_text000 = true;
-        }
-        if lower == "999.txt" {
-            has_text999 = true;
-        }
         let Some(ordinal) = stem.parse::<usize>().ok() else {
             continue;
         };
-        if ordinal >= entries.len() {
-            entries.resize(ordinal + 1, None);
-        }
         let content =
             fs::read_to_string(entry.path()).with_context(|| entry.path().display().to_string())?;
-        let processed = process_text(&content, book)?;
-        entries[ordinal] = Some(TextEntry {
-            name: file_name,
-            ordinal,
-            data: processed,
-        });
+        corpus.insert(book, file_name, ordinal, &content)?;
     }
 
-    if entries.iter().all(|e| e.is_none()) {
+    if corpus.entries.iter().all(|e| e.is_none()) {
         return Err(anyhow!("no .txt files found under {}", text_dir.display()));
     }
 
-    Ok(TextCorpus {
-        entries,
-        has_text000,
-        has_text999,
-    })
+    Ok(corpus)
 }
 
-fn process_text(content: &str, book: &BookConfig) -> Result<String> {
+fn process_text(content: &str, book: &BookConfig, clusters: &mut ClusterTable) -> Result<String> {
     let mut result = String::new();
     for raw_line in content.lines() {
         let trimmed = raw_line.trim();
@@ -105,6 +157,8 @@ fn process_text(content: &str, book: &BookConfig) -> Result<String> {
         if current.is_empty() {
             continue;
         }
+        // 先收起字形簇与外字记号，免得 `{}` 等被标点替换改写
+        current = clusters.encode(&current);
         apply_replacements(&mut current, &book.replacements);
         apply_text_modes(&mut current, &book.text_modes);
         current = current.replace('@', " ");
@@ -120,17 +174,23 @@ fn process_text(content: &str, book: &BookConfig) -> Result<String> {
         if book.book_line_flag {
             working.retain(|ch| ch != '《' && ch != '》');
         }
-        let annotation_extra = count_annotation_slots(&working);
-        strip_annotations(&mut working);
+        if book.name_frame.is_some() {
+            working.retain(|ch| ch != '<' && ch != '>');
+        }
+        let annotation_extra = count_annotation_slots(&working, &book.annotations);
+        strip_annotations(&mut working, &book.annotations);
         let total_chars = working.chars().count() + annotation_extra;
         let spaces = missing_spaces(total_chars, book.row_num);
 
         result.push_str(&tmp_original);
-        if spaces > 0 && spaces < book.row_num {
+        // 比例竖排与横排按换行另起一列（行），不以空格补齐；字格排版补齐后同样以换行
+        // 标记段末，排版时据此校正禁则与悬挂造成的移位
+        if book.layout_mode == LayoutMode::Grid && spaces > 0 && spaces < book.row_num {
             for _ in 0..spaces {
                 result.push(' ');
             }
         }
+        result.push('\n');
     }
     Ok(result)
 }
@@ -185,45 +245,51 @@ fn remove_chars(text: &mut String, text_nop: &[char], comment_strip: &[char]) {
     }
 }
 
-fn count_annotation_slots(working: &str) -> usize {
-    let mut total = 0usize;
-    let mut temp = working.to_string();
-    loop {
-        let start = match temp.find('【') {
-            Some(idx) => idx,
-            None => break,
-        };
-        let content_start = start + '【'.len_utf8();
-        let rel_end = match temp[content_start..].find('】') {
-            Some(idx) => idx,
-            None => break,
-        };
-        let content_end = content_start + rel_end;
-        let len = temp[content_start..content_end].chars().count();
-        if len % 2 == 0 {
-            total += len / 2;
-        } else {
-            total += len / 2 + 1;
+/// 顶层批注的字节范围与字数；嵌套批注计入外层，批注与出处标签的标记不计
+fn annotation_spans(text: &str, annotations: &AnnotationConfig) -> Vec<(Range<usize>, usize)> {
+    let mut spans = Vec::new();
+    let mut open_styles: Vec<usize> = Vec::new();
+    let mut start = 0usize;
+    let mut count = 0usize;
+    for (idx, ch) in text.char_indices() {
+        if let Some(style) = annotations.opening(ch) {
+            if open_styles.is_empty() {
+                start = idx;
+                count = 0;
+            }
+            open_styles.push(style);
+            continue;
+        }
+        if open_styles.is_empty() {
+            continue;
+        }
+        if let Some(depth) = open_styles
+            .iter()
+            .rposition(|&style| annotations.styles[style].close == ch)
+        {
+            open_styles.truncate(depth);
+            if open_styles.is_empty() {
+                spans.push((start..idx + ch.len_utf8(), count));
+            }
+            continue;
+        }
+        if ch != ANNOTATION_LABEL_OPEN && ch != ANNOTATION_LABEL_CLOSE {
+            count += 1;
         }
-        let remove_end = content_end + '】'.len_utf8();
-        temp.replace_range(start..remove_end, "");
     }
-    total
+    spans
 }
 
-fn strip_annotations(text: &mut String) {
-    loop {
-        let start = match text.find('【') {
-            Some(idx) => idx,
-            None => break,
-        };
-        let content_start = start + '【'.len_utf8();
-        let rel_end = match text[content_start..].find('】') {
-            Some(idx) => idx,
-            None => break,
-        };
-        let remove_end = content_start + rel_end + '】'.len_utf8();
-        text.replace_range(start..remove_end, "");
+fn count_annotation_slots(working: &str, annotations: &AnnotationConfig) -> usize {
+    annotation_spans(working, annotations)
+        .iter()
+        .map(|(_, count)| count.div_ceil(annotations.columns))
+        .sum()
+}
+
+fn strip_annotations(text: &mut String, annotations: &AnnotationConfig) {
+    for (range, _) in annotation_spans(text, annotations).into_iter().rev() {
+        text.replace_range(range, "");
     }
 }
 
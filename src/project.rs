@@ -0,0 +1,299 @@
+use crate::backend::{RenderContext, SourceImage};
+use crate::background::{CanvasLayers, load_canvas_layers};
+use crate::config::{BookConfig, CanvasConfig};
+use crate::fonts::{FontManager, FontMap};
+use crate::glyph_images::GlyphImages;
+use crate::layout::Layout;
+use crate::multirows::MultiRowsMode;
+use crate::numerals::NumeralMap;
+use crate::plan::{DocumentPlan, TypesetOptions};
+use crate::preprocess::TextCorpus;
+use crate::raster::{RasterFormat, RasterOptions, render_raster, render_raster_pages};
+use crate::renderer::{PdfOptions, render_document, render_document_bytes};
+use crate::svg::{SvgOptions, render_svg};
+use crate::typesetter::Typesetter;
+use anyhow::{Result, anyhow};
+use std::path::{Path, PathBuf};
+
+/// 一部书排版所需的全部输入：配置、版面、字体与文本。
+///
+/// 由 [`ProjectBuilder`] 组装，之后可多次排版并输出为 PDF、图片或 SVG。
+pub struct Project {
+    book: BookConfig,
+    canvas: CanvasConfig,
+    layout: Layout,
+    fonts: FontManager,
+    numerals: NumeralMap,
+    corpus: TextCorpus,
+    glyph_images: GlyphImages,
+    layers: CanvasLayers,
+    cover: Option<(PathBuf, SourceImage)>,
+    logo_image: Option<SourceImage>,
+}
+
+impl Project {
+    pub fn builder() -> ProjectBuilder {
+        ProjectBuilder::default()
+    }
+
+    pub fn book(&self) -> &BookConfig {
+        &self.book
+    }
+
+    pub fn canvas(&self) -> &CanvasConfig {
+        &self.canvas
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    pub fn fonts(&self) -> &FontManager {
+        &self.fonts
+    }
+
+    pub fn corpus(&self) -> &TextCorpus {
+        &self.corpus
+    }
+
+    /// 按 options 排版；设置了封面图时 `options.cover_image` 以其为准
+    pub fn typeset(&self, mut options: TypesetOptions) -> Result<DocumentPlan> {
+        if let Some((path, _)) = &self.cover {
+            options.cover_image = Some(path.clone());
+        }
+        let mut typesetter = Typesetter::new(
+            &self.book,
+            &self.layout,
+            &self.fonts,
+            &self.numerals,
+            &self.corpus,
+            &self.glyph_images,
+            options,
+        )?;
+        let plan = typesetter.build_plan()?;
+        plan.validate()?;
+        Ok(plan)
+    }
+
+    /// 供自定义 [`Renderer`](crate::backend::Renderer) 使用的绘制上下文
+    pub fn render_context(&self) -> RenderContext<'_> {
+        RenderContext {
+            book: &self.book,
+            canvas: &self.canvas,
+            fonts: &self.fonts,
+            numerals: &self.numerals,
+            background: self.layers.image.as_ref(),
+            background_color: self.layers.color,
+            cover_image: self.cover.as_ref().map(|(_, image)| image),
+            logo_image: self.logo_image.as_ref(),
+            draw_frame: self.layers.draw_frame,
+        }
+    }
+
+    pub fn render_pdf(
+        &self,
+        plan: &DocumentPlan,
+        output_path: &Path,
+        options: &PdfOptions,
+    ) -> Result<()> {
+        render_document(plan, &self.render_context(), output_path, options)
+    }
+
+    /// 输出到内存中的 PDF，不叠加印章
+    pub fn render_pdf_bytes(&self, plan: &DocumentPlan, options: &PdfOptions) -> Result<Vec<u8>> {
+        render_document_bytes(plan, &self.render_context(), options)
+    }
+
+    /// 逐页输出图片，返回写出的文件；document_path 决定文件名前缀与印章配置
+    pub fn render_raster(
+        &self,
+        plan: &DocumentPlan,
+        document_path: &Path,
+        options: &RasterOptions,
+    ) -> Result<Vec<PathBuf>> {
+        render_raster(plan, &self.render_context(), document_path, options)
+    }
+
+    /// 逐页编码为内存中的图片，封面在首位，不叠加印章
+    pub fn render_raster_pages(
+        &self,
+        plan: &DocumentPlan,
+        format: RasterFormat,
+        dpi: f32,
+    ) -> Result<Vec<Vec<u8>>> {
+        render_raster_pages(plan, &self.render_context(), format, dpi)
+    }
+
+    /// 逐页输出 SVG，返回写出的文件；document_path 的含义同 [`Project::render_raster`]
+    pub fn render_svg(
+        &self,
+        plan: &DocumentPlan,
+        document_path: &Path,
+        options: &SvgOptions,
+    ) -> Result<Vec<PathBuf>> {
+        render_svg(plan, &self.render_context(), document_path, options)
+    }
+}
+
+/// [`Project`] 的构建器。书籍配置、画布配置与文本必填；字体默认从 `fonts/` 目录加载，
+/// 数字映射缺省时逐位转写。
+#[derive(Default)]
+pub struct ProjectBuilder {
+    book: Option<BookConfig>,
+    canvas: Option<CanvasConfig>,
+    fonts: Option<FontManager>,
+    fonts_dir: Option<PathBuf>,
+    numerals: Option<NumeralMap>,
+    corpus: Option<TextCorpus>,
+    glyph_images: GlyphImages,
+    font_map: Option<FontMap>,
+    cover: Option<(PathBuf, SourceImage)>,
+    logo_image: Option<SourceImage>,
+}
+
+impl ProjectBuilder {
+    pub fn book(mut self, book: BookConfig) -> Self {
+        self.book = Some(book);
+        self
+    }
+
+    pub fn canvas(mut self, canvas: CanvasConfig) -> Self {
+        self.canvas = Some(canvas);
+        self
+    }
+
+    /// 字体与书房名字体所在目录，未调用 [`ProjectBuilder::fonts`] 时使用
+    pub fn fonts_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.fonts_dir = Some(dir.into());
+        self
+    }
+
+    /// 直接使用已加载的字体，如 [`FontManager::from_loader`] 从内存构建的字体
+    pub fn fonts(mut self, fonts: FontManager) -> Self {
+        self.fonts = Some(fonts);
+        self
+    }
+
+    pub fn numerals(mut self, numerals: NumeralMap) -> Self {
+        self.numerals = Some(numerals);
+        self
+    }
+
+    pub fn corpus(mut self, corpus: TextCorpus) -> Self {
+        self.corpus = Some(corpus);
+        self
+    }
+
+    /// 外字图片，如 [`GlyphImages::load`] 读取的书籍 glyphs/ 目录；其中的 `{名称}` 记号
+    /// 须在构建文本时一并给出，见 [`TextCorpus::from_texts_with_glyphs`]
+    pub fn glyph_images(mut self, images: GlyphImages) -> Self {
+        self.glyph_images = images;
+        self
+    }
+
+    /// 各字首选的字体槽位，如 [`FontMap::load`] 读取的书籍 font_map.txt
+    pub fn font_map(mut self, map: FontMap) -> Self {
+        self.font_map = Some(map);
+        self
+    }
+
+    /// 封面图；path 记入 DocumentPlan，不会再次读取
+    pub fn cover_image(mut self, path: impl Into<PathBuf>, image: SourceImage) -> Self {
+        self.cover = Some((path.into(), image));
+        self
+    }
+
+    pub fn logo_image(mut self, image: SourceImage) -> Self {
+        self.logo_image = Some(image);
+        self
+    }
+
+    /// 校验配置并计算版面；按 fonts_dir 加载字体时书房名字体缺失只提示不报错
+    pub fn build(self) -> Result<Project> {
+        let book = self
+            .book
+            .ok_or_else(|| anyhow!("book config is required"))?;
+        let canvas = self
+            .canvas
+            .ok_or_else(|| anyhow!("canvas config is required"))?;
+        let corpus = self
+            .corpus
+            .ok_or_else(|| anyhow!("text corpus is required"))?;
+        book.validate()?;
+        canvas.validate()?;
+
+        let multirows = MultiRowsMode::from_flags(
+            canvas.multirows_enabled,
+            canvas.multirows_count,
+            book.multirows_horizontal_layout,
+        );
+        let layout = Layout::build(&book, &canvas, multirows)?;
+        let mut fonts = match self.fonts {
+            Some(fonts) => fonts,
+            None => {
+                let dir = self.fonts_dir.unwrap_or_else(|| PathBuf::from("fonts"));
+                let mut fonts = FontManager::new(&book, &dir)?;
+                if let Err(err) = fonts.load_logo_font(&canvas, &dir) {
+                    eprintln!("Logo font unavailable, skip logo text: {err}");
+                }
+                fonts
+            }
+        };
+        if let Some(map) = self.font_map {
+            fonts.font_map = map;
+        }
+        let layers = load_canvas_layers(&canvas);
+
+        Ok(Project {
+            book,
+            canvas,
+            layout,
+            fonts,
+            numerals: self.numerals.unwrap_or_default(),
+            corpus,
+            glyph_images: self.glyph_images,
+            layers,
+            cover: self.cover,
+            logo_image: self.logo_image,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_typesets_in_memory_config_and_text() {
+        let book_cfg =
+            std::fs::read_to_string("books/01/book.cfg").expect("read sample book configuration");
+        let book = BookConfig::parse(&book_cfg).expect("parse book configuration from memory");
+        let canvas_path = format!("canvas/{}.cfg", book.canvas_id);
+        let canvas = CanvasConfig::parse(
+            &canvas_path,
+            &std::fs::read_to_string(&canvas_path).expect("read canvas configuration"),
+        )
+        .expect("parse canvas configuration from memory");
+        let corpus = TextCorpus::from_text(&book, "太史公曰：余讀【注文】書。")
+            .expect("build corpus from memory");
+
+        let project = Project::builder()
+            .book(book)
+            .canvas(canvas)
+            .corpus(corpus)
+            .fonts_dir("fonts")
+            .build()
+            .expect("build project");
+        let plan = project
+            .typeset(TypesetOptions::default())
+            .expect("typeset in-memory text");
+        assert_eq!(plan.pages.len(), 1);
+        assert!(!plan.pages[0].glyphs.is_empty());
+        assert_eq!(plan.outlines[0].page_number, 1);
+
+        let pdf = project
+            .render_pdf_bytes(&plan, &PdfOptions::default())
+            .expect("render pdf into memory");
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+}
@@ -0,0 +1,539 @@
+use crate::backend::{
+    FontFace, ImageKey, ImagePlacement, RenderContext, Renderer, SourceImage, render_plan,
+    wavy_points,
+};
+use crate::color::RgbColor;
+use crate::fonts::FontManager;
+use crate::plan::{DocumentPlan, GlyphSpec, LineSpec, PolygonSpec};
+use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
+use fontdue::{Font, Metrics};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+/// 画布单位即 72dpi 下的点
+const CANVAS_DPI: f32 = 72.0;
+/// 多边形填充时每个像素行的纵向采样数，用于边缘抗锯齿
+const SUBSAMPLES: usize = 4;
+const JPEG_QUALITY: u8 = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RasterFormat {
+    Png,
+    Jpeg,
+}
+
+impl RasterFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            RasterFormat::Png => "png",
+            RasterFormat::Jpeg => "jpg",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RasterOptions {
+    pub format: RasterFormat,
+    pub dpi: f32,
+    pub output_dir: PathBuf,
+}
+
+/// 将 DocumentPlan 逐页渲染为图片，封面为第 000 页。
+///
+/// `document_path` 为对应的 PDF 路径，用于确定文件名前缀与同目录下的 yins.cfg。
+pub fn render_raster(
+    plan: &DocumentPlan,
+    ctx: &RenderContext,
+    document_path: &Path,
+    options: &RasterOptions,
+) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(&options.output_dir).with_context(|| {
+        format!(
+            "failed to create raster output dir {}",
+            options.output_dir.display()
+        )
+    })?;
+    let stem = document_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("page")
+        .to_string();
+    let output = PageOutput::Files {
+        dir: &options.output_dir,
+        stem,
+        written: Vec::with_capacity(plan.pages.len() + 1),
+    };
+    let mut renderer = RasterRenderer::new(ctx, options.format, options.dpi, output);
+    render_plan(plan, ctx, Some(document_path), &mut renderer)?;
+    match renderer.output {
+        PageOutput::Files { written, .. } => Ok(written),
+        PageOutput::Memory(_) => unreachable!("raster output switched to memory"),
+    }
+}
+
+/// 逐页渲染为内存中的图片数据，封面在首位；不叠加印章
+pub fn render_raster_pages(
+    plan: &DocumentPlan,
+    ctx: &RenderContext,
+    format: RasterFormat,
+    dpi: f32,
+) -> Result<Vec<Vec<u8>>> {
+    let output = PageOutput::Memory(Vec::with_capacity(plan.pages.len() + 1));
+    let mut renderer = RasterRenderer::new(ctx, format, dpi, output);
+    render_plan(plan, ctx, None, &mut renderer)?;
+    match renderer.output {
+        PageOutput::Memory(pages) => Ok(pages),
+        PageOutput::Files { .. } => unreachable!("raster output switched to files"),
+    }
+}
+
+/// 编码后的页面写入目录（文件名为 `{stem}_{页码:03}.{扩展名}`）或留在内存
+enum PageOutput<'a> {
+    Files {
+        dir: &'a Path,
+        stem: String,
+        written: Vec<PathBuf>,
+    },
+    Memory(Vec<Vec<u8>>),
+}
+
+/// 位图后端：图片按来源与目标尺寸缓存缩放结果，字形按字体与字号缓存
+struct RasterRenderer<'a> {
+    fonts: &'a FontManager,
+    format: RasterFormat,
+    output: PageOutput<'a>,
+    blank: Raster,
+    page: Option<(usize, Raster)>,
+    glyphs: GlyphCache,
+    images: HashMap<(ImageKey, u32, u32), RgbaImage>,
+}
+
+impl<'a> RasterRenderer<'a> {
+    fn new(
+        ctx: &RenderContext<'a>,
+        format: RasterFormat,
+        dpi: f32,
+        output: PageOutput<'a>,
+    ) -> Self {
+        Self {
+            fonts: ctx.fonts,
+            format,
+            output,
+            blank: Raster::new(
+                ctx.canvas.canvas_width,
+                ctx.canvas.canvas_height,
+                dpi / CANVAS_DPI,
+            ),
+            page: None,
+            glyphs: GlyphCache::default(),
+            images: HashMap::new(),
+        }
+    }
+}
+
+impl Renderer for RasterRenderer<'_> {
+    fn begin_page(&mut self, number: usize) -> Result<()> {
+        self.page = Some((number, self.blank.clone()));
+        Ok(())
+    }
+
+    fn draw_image(
+        &mut self,
+        key: &ImageKey,
+        image: &SourceImage,
+        target: ImagePlacement,
+    ) -> Result<()> {
+        let image = &image.image;
+        let Some((_, raster)) = self.page.as_mut() else {
+            return Err(anyhow!("drawing outside of a page"));
+        };
+        let width = (target.width * raster.scale).round() as u32;
+        let height = (target.height * raster.scale).round() as u32;
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+        let resized = self
+            .images
+            .entry((key.clone(), width, height))
+            .or_insert_with(|| {
+                if (image.width(), image.height()) == (width, height) {
+                    image.to_rgba8()
+                } else {
+                    image
+                        .resize_exact(width, height, FilterType::Triangle)
+                        .to_rgba8()
+                }
+            });
+        raster.draw_rgba(resized, target);
+        Ok(())
+    }
+
+    fn draw_polygon(&mut self, polygon: &PolygonSpec) {
+        if let Some((_, raster)) = self.page.as_mut() {
+            raster.draw_polygon(polygon);
+        }
+    }
+
+    fn draw_line(&mut self, line: &LineSpec) {
+        if let Some((_, raster)) = self.page.as_mut() {
+            raster.draw_line(line);
+        }
+    }
+
+    fn draw_glyph(&mut self, face: FontFace, glyph: &GlyphSpec) -> Result<()> {
+        let font = match face {
+            FontFace::Slot(idx) => self.fonts.font(idx),
+            FontFace::Logo => self.fonts.logo.as_ref(),
+        };
+        let Some(font) = font else {
+            return Ok(());
+        };
+        let Some((_, raster)) = self.page.as_mut() else {
+            return Err(anyhow!("drawing outside of a page"));
+        };
+        raster.draw_glyph(&mut self.glyphs, face, &font.font, glyph);
+        Ok(())
+    }
+
+    fn end_page(&mut self) -> Result<()> {
+        let (number, raster) = self
+            .page
+            .take()
+            .ok_or_else(|| anyhow!("end_page without begin_page"))?;
+        let bytes = encode_page(&raster, self.format)?;
+        match &mut self.output {
+            PageOutput::Files { dir, stem, written } => {
+                let path = dir.join(format!("{stem}_{number:03}.{}", self.format.extension()));
+                std::fs::write(&path, bytes)
+                    .with_context(|| format!("failed to write {}", path.display()))?;
+                written.push(path);
+            }
+            PageOutput::Memory(pages) => pages.push(bytes),
+        }
+        Ok(())
+    }
+}
+
+fn encode_page(raster: &Raster, format: RasterFormat) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    match format {
+        RasterFormat::Png => raster
+            .image
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .context("failed to encode png page")?,
+        RasterFormat::Jpeg => {
+            let rgb = DynamicImage::ImageRgba8(raster.image.clone()).to_rgb8();
+            JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY)
+                .encode_image(&rgb)
+                .context("failed to encode jpeg page")?;
+        }
+    }
+    Ok(bytes)
+}
+
+/// 按 (字体, 字符, 像素字号) 缓存的 fontdue 位图
+#[derive(Default)]
+struct GlyphCache {
+    bitmaps: HashMap<(FontFace, u16, u32), (Metrics, Vec<u8>)>,
+}
+
+impl GlyphCache {
+    /// 按字形号缓存，竖排替换字形与按字取得的字形共用一处
+    fn get(
+        &mut self,
+        face: FontFace,
+        font: &Font,
+        glyph: &GlyphSpec,
+        px: f32,
+    ) -> &(Metrics, Vec<u8>) {
+        let gid = glyph
+            .glyph_id
+            .unwrap_or_else(|| font.lookup_glyph_index(glyph.ch));
+        self.bitmaps
+            .entry((face, gid, px.to_bits()))
+            .or_insert_with(|| font.rasterize_indexed(gid, px))
+    }
+}
+
+#[derive(Clone)]
+struct Raster {
+    image: RgbaImage,
+    scale: f32,
+    canvas_height: f32,
+}
+
+impl Raster {
+    fn new(canvas_width: f32, canvas_height: f32, scale: f32) -> Self {
+        let width = (canvas_width * scale).round().max(1.0) as u32;
+        let height = (canvas_height * scale).round().max(1.0) as u32;
+        Self {
+            image: RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255])),
+            scale,
+            canvas_height,
+        }
+    }
+
+    /// 画布坐标（左下原点）转为像素坐标（左上原点）
+    fn to_device(&self, x: f32, y: f32) -> (f32, f32) {
+        (x * self.scale, (self.canvas_height - y) * self.scale)
+    }
+
+    fn blend(&mut self, x: i64, y: i64, color: RgbColor, alpha: f32) {
+        blend_pixel(&mut self.image, x, y, color, alpha);
+    }
+
+    fn draw_polygon(&mut self, polygon: &PolygonSpec) {
+        let points = polygon
+            .points
+            .iter()
+            .map(|&(x, y)| self.to_device(x, y))
+            .collect::<Vec<_>>();
+        self.fill_device_polygon(&points, polygon.color);
+    }
+
+    fn draw_line(&mut self, line: &LineSpec) {
+        let points = if line.wavy {
+            wavy_points(line)
+        } else {
+            vec![(line.x1, line.y1), (line.x2, line.y2)]
+        };
+        let width = line.width * self.scale;
+        for pair in points.windows(2) {
+            let (x1, y1) = self.to_device(pair[0].0, pair[0].1);
+            let (x2, y2) = self.to_device(pair[1].0, pair[1].1);
+            let (dx, dy) = (x2 - x1, y2 - y1);
+            let len = (dx * dx + dy * dy).sqrt();
+            if len <= f32::EPSILON {
+                continue;
+            }
+            // 平头线端，与 PDF 默认描边一致
+            let (nx, ny) = (-dy / len * width / 2.0, dx / len * width / 2.0);
+            let quad = [
+                (x1 + nx, y1 + ny),
+                (x2 + nx, y2 + ny),
+                (x2 - nx, y2 - ny),
+                (x1 - nx, y1 - ny),
+            ];
+            self.fill_device_polygon(&quad, line.color);
+        }
+    }
+
+    /// 扫描线填充（奇偶规则），每行纵向多次采样、横向按覆盖比例累加
+    fn fill_device_polygon(&mut self, points: &[(f32, f32)], color: RgbColor) {
+        if points.len() < 3 {
+            return;
+        }
+        let width = self.image.width() as usize;
+        let (min_y, max_y) = points.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| {
+            (lo.min(p.1), hi.max(p.1))
+        });
+        let row_start = min_y.floor().max(0.0) as i64;
+        let row_end = max_y.ceil().min(self.image.height() as f32) as i64;
+        let weight = 1.0 / SUBSAMPLES as f32;
+        let mut coverage = vec![0.0f32; width];
+        let mut crossings = Vec::new();
+        for row in row_start..row_end {
+            let (mut lo, mut hi) = (width, 0);
+            for k in 0..SUBSAMPLES {
+                let sy = row as f32 + (k as f32 + 0.5) * weight;
+                crossings.clear();
+                for (i, &(x0, y0)) in points.iter().enumerate() {
+                    let (x1, y1) = points[(i + 1) % points.len()];
+                    if (y0 <= sy && sy < y1) || (y1 <= sy && sy < y0) {
+                        crossings.push(x0 + (sy - y0) / (y1 - y0) * (x1 - x0));
+                    }
+                }
+                crossings.sort_by(f32::total_cmp);
+                for span in crossings.chunks_exact(2) {
+                    let xa = span[0].clamp(0.0, width as f32);
+                    let xb = span[1].clamp(0.0, width as f32);
+                    if xb <= xa {
+                        continue;
+                    }
+                    let (ia, ib) = (xa.floor() as usize, xb.floor() as usize);
+                    lo = lo.min(ia);
+                    hi = hi.max((ib + 1).min(width));
+                    if ia == ib {
+                        coverage[ia] += (xb - xa) * weight;
+                        continue;
+                    }
+                    coverage[ia] += (ia as f32 + 1.0 - xa) * weight;
+                    for cell in &mut coverage[ia + 1..ib] {
+                        *cell += weight;
+                    }
+                    if ib < width {
+                        coverage[ib] += (xb - ib as f32) * weight;
+                    }
+                }
+            }
+            if lo >= hi {
+                continue;
+            }
+            for (x, cell) in coverage[lo..hi].iter_mut().enumerate() {
+                if *cell > 0.0 {
+                    blend_pixel(&mut self.image, (lo + x) as i64, row, color, *cell);
+                    *cell = 0.0;
+                }
+            }
+        }
+    }
+
+    /// 绘制已缩放到目标像素尺寸的图片，target 的左下角为落点
+    fn draw_rgba(&mut self, image: &RgbaImage, target: ImagePlacement) {
+        let (left, top) = self.to_device(target.x, target.y + target.height);
+        let (left, top) = (left.round() as i64, top.round() as i64);
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let [r, g, b, a] = pixel.0;
+            if a == 0 {
+                continue;
+            }
+            self.blend(
+                left + x as i64,
+                top + y as i64,
+                RgbColor::new_u8(r, g, b),
+                a as f32 / 255.0,
+            );
+        }
+    }
+
+    /// 以基线原点 (x, y) 绘制单字，rotate_deg 为逆时针角度，与 PDF 文本矩阵一致
+    fn draw_glyph(
+        &mut self,
+        cache: &mut GlyphCache,
+        face: FontFace,
+        font: &Font,
+        glyph: &GlyphSpec,
+    ) {
+        let px = glyph.font_size * self.scale;
+        let (origin_x, origin_y) = self.to_device(glyph.x, glyph.y);
+        let (metrics, bitmap) = cache.get(face, font, glyph, px);
+        if metrics.width == 0 || metrics.height == 0 {
+            return;
+        }
+        let (gw, gh) = (metrics.width as i64, metrics.height as i64);
+        // 位图左上角相对基线原点的像素偏移（向下为正）
+        let left = metrics.xmin as i64;
+        let top = -(metrics.ymin as i64 + gh);
+
+        let stretched = (glyph.scale_x - 1.0).abs() > f32::EPSILON;
+        if glyph.rotate_deg.abs() <= f32::EPSILON && !stretched {
+            let (ox, oy) = (origin_x.round() as i64, origin_y.round() as i64);
+            for row in 0..gh {
+                for col in 0..gw {
+                    let value = bitmap[(row * gw + col) as usize];
+                    if value > 0 {
+                        self.blend(
+                            ox + left + col,
+                            oy + top + row,
+                            glyph.color,
+                            value as f32 / 255.0,
+                        );
+                    }
+                }
+            }
+            return;
+        }
+
+        let (sin, cos) = glyph.rotate_deg.to_radians().sin_cos();
+        // 以半径覆盖旋转、横向缩放后的位图范围
+        let corners = [
+            (left, top),
+            (left + gw, top),
+            (left, top + gh),
+            (left + gw, top + gh),
+        ];
+        let radius = corners
+            .iter()
+            .map(|&(x, y)| (x as f32 * glyph.scale_x).hypot(y as f32))
+            .fold(0.0f32, f32::max)
+            .ceil() as i64
+            + 1;
+        let (ox, oy) = (origin_x.round() as i64, origin_y.round() as i64);
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                // 设备坐标向下为正，旋转按向上为正的字形坐标计算
+                let (fx, fy) = (dx as f32 + 0.5, -(dy as f32 + 0.5));
+                let u = (fx * cos + fy * sin) / glyph.scale_x;
+                let v = -fx * sin + fy * cos;
+                let col = (u - left as f32).floor() as i64;
+                let row = (-v - top as f32).floor() as i64;
+                if col < 0 || row < 0 || col >= gw || row >= gh {
+                    continue;
+                }
+                let value = bitmap[(row * gw + col) as usize];
+                if value > 0 {
+                    self.blend(ox + dx, oy + dy, glyph.color, value as f32 / 255.0);
+                }
+            }
+        }
+    }
+}
+
+fn blend_pixel(image: &mut RgbaImage, x: i64, y: i64, color: RgbColor, alpha: f32) {
+    if x < 0 || y < 0 || x >= image.width() as i64 || y >= image.height() as i64 {
+        return;
+    }
+    let alpha = alpha.clamp(0.0, 1.0);
+    let pixel = image.get_pixel_mut(x as u32, y as u32);
+    for (channel, value) in pixel.0.iter_mut().zip([color.r, color.g, color.b]) {
+        let mixed = *channel as f32 * (1.0 - alpha) + value * 255.0 * alpha;
+        *channel = mixed.round().clamp(0.0, 255.0) as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{Sample, single_page};
+
+    #[test]
+    fn render_raster_writes_one_image_per_page() {
+        let sample = Sample::load(None);
+        let canvas = &sample.canvas;
+        let black = RgbColor::new_u8(0, 0, 0);
+        let glyph = GlyphSpec {
+            rotate_deg: -90.0,
+            ..GlyphSpec::new('W', sample.text_font(), 48.0, 100.0, 100.0, black)
+        };
+        let line = LineSpec {
+            x1: 300.0,
+            y1: 100.0,
+            x2: 300.0,
+            y2: 400.0,
+            width: 2.0,
+            color: black,
+            wavy: true,
+        };
+        let plan = single_page(1, vec![glyph], vec![line]);
+        let ctx = RenderContext {
+            background_color: Some(RgbColor::new_u8(250, 245, 230)),
+            ..sample.context()
+        };
+
+        let output_dir = std::env::temp_dir().join("vrain_raster_smoke");
+        let options = RasterOptions {
+            format: RasterFormat::Png,
+            dpi: 24.0,
+            output_dir: output_dir.clone(),
+        };
+        let written = render_raster(&plan, &ctx, &output_dir.join("smoke.pdf"), &options)
+            .expect("render raster pages");
+        assert_eq!(written.len(), 2, "expected cover plus one page");
+
+        let page = image::open(&written[1])
+            .expect("read rendered page")
+            .to_rgba8();
+        let expected_w = (canvas.canvas_width * options.dpi / CANVAS_DPI).round() as u32;
+        assert_eq!(page.width(), expected_w);
+        assert!(
+            page.pixels().any(|p| p.0[0] < 128),
+            "frame and glyphs should leave dark pixels on the page"
+        );
+        let _ = std::fs::remove_dir_all(output_dir);
+    }
+}
@@ -1,286 +1,353 @@
+use crate::backend::{
+    FontFace, ImageKey, ImagePlacement, RenderContext, Renderer, SourceImage, render_plan,
+    wavy_points,
+};
 use crate::color::RgbColor;
-use crate::config::{BookConfig, CanvasConfig};
 use crate::fonts::FontManager;
-use crate::numerals::NumeralMap;
-use crate::plan::{CoverPlan, DocumentPlan, GlyphSpec, LineSpec, PagePlan};
-use anyhow::{Result, anyhow, Context};
+use crate::plan::{DocumentPlan, GlyphSpec, LineSpec, PolygonSpec};
+use anyhow::{Context, Result, anyhow};
 use image::DynamicImage;
+use image::codecs::jpeg::JpegEncoder;
 use printpdf::{
-    Color, FontId, Line, LinePoint, Mm, Op, ParsedFont, PdfDocument, PdfPage, PdfSaveOptions,
-    Point, Pt, Px, RawImage, RawImageData, RawImageFormat, Rgb, TextItem, TextMatrix, XObjectId,
+    Color, DictItem, ExternalStream, ExternalXObject, FontId, ImageCompression,
+    ImageOptimizationOptions, Line, LinePoint, Mm, Op, PaintMode, ParsedFont, PdfDocument, PdfPage,
+    PdfParseErrorSeverity, PdfSaveOptions, PdfWarnMsg, Point, Polygon, PolygonRing, Pt, Px,
+    RawImage, RawImageData, RawImageFormat, Rgb, TextItem, TextMatrix, WindingOrder, XObjectId,
     XObjectTransform,
 };
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
-use std::io::BufWriter;
+use std::hash::{Hash, Hasher};
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
 
 const PX_TO_MM: f32 = 25.4f32 / 72.0f32;
 const IMAGE_DPI: f32 = 72.0f32;
-
-pub struct RenderContext<'a> {
-    pub book: &'a BookConfig,
-    pub canvas: &'a CanvasConfig,
-    pub fonts: &'a FontManager,
-    pub numerals: &'a NumeralMap,
-    pub background: Option<DynamicImage>,
-    pub cover_image: Option<DynamicImage>,
+/// 无法原样嵌入的 JPEG 重新编码时的质量
+const JPEG_QUALITY: u8 = 90;
+/// `-c` 压缩时的 JPEG 质量
+const JPEG_QUALITY_COMPRESSED: u8 = 60;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PdfOptions {
+    /// 以较低 JPEG 质量重新压缩图片，替代原先的 Ghostscript 压缩
+    pub compress: bool,
 }
 
-pub fn render_document(plan: &DocumentPlan, ctx: &RenderContext, output_path: &Path) -> Result<()> {
-    let width_mm = Mm(ctx.canvas.canvas_width * PX_TO_MM);
-    let height_mm = Mm(ctx.canvas.canvas_height * PX_TO_MM);
+/// 渲染 PDF：字体仅在首次使用时嵌入并按用到的字形子集化，图片按内容去重后压缩存储。
+///
+/// JPEG 来源原样作为 DCT 流嵌入；其余图片以 Flate 压缩，透明通道单独存为 SMask。
+pub fn render_document(
+    plan: &DocumentPlan,
+    ctx: &RenderContext,
+    output_path: &Path,
+    options: &PdfOptions,
+) -> Result<()> {
+    let doc = build_document(plan, ctx, Some(output_path), options)?;
+    let file = File::create(output_path)
+        .with_context(|| format!("failed to create {}", output_path.display()))?;
+    let mut writer = BufWriter::new(file);
+    let mut warnings = Vec::new();
+    doc.save_writer(&mut writer, &pdf_save_options(), &mut warnings);
+    writer
+        .flush()
+        .with_context(|| format!("failed to write {}", output_path.display()))?;
+    report_save_warnings(&warnings);
+    Ok(())
+}
 
-    let mut doc = PdfDocument::new("vRain");
-    let font_ids = prepare_font_ids(&mut doc, ctx.fonts)?;
-    let outline_map = build_outline_map(plan, ctx);
-    let stamps = load_stamps(ctx, output_path)?;
+/// 渲染 PDF 到内存；没有输出路径，因此不叠加 yins.cfg 中的印章
+pub fn render_document_bytes(
+    plan: &DocumentPlan,
+    ctx: &RenderContext,
+    options: &PdfOptions,
+) -> Result<Vec<u8>> {
+    let doc = build_document(plan, ctx, None, options)?;
+    let mut warnings = Vec::new();
+    let bytes = doc.save(&pdf_save_options(), &mut warnings);
+    report_save_warnings(&warnings);
+    Ok(bytes)
+}
 
-    let background_image_id = if let Some(image) = ctx.background.as_ref() {
-        Some(register_image(&mut doc, image))
-    } else {
-        None
+/// 输出 printpdf 保存时的警告与错误，提示信息不输出
+fn report_save_warnings(warnings: &[PdfWarnMsg]) {
+    for warning in warnings {
+        if warning.severity != PdfParseErrorSeverity::Info {
+            eprintln!("PDF 保存警告（第 {} 页）: {}", warning.page, warning.msg);
+        }
     }
-    .transpose()?;
+}
 
-    let cover_image_id = if matches!(plan.cover, CoverPlan::Image) {
-        ctx.cover_image
-            .as_ref()
-            .map(|image| register_image(&mut doc, image))
-            .transpose()?
-    } else {
-        None
+fn build_document(
+    plan: &DocumentPlan,
+    ctx: &RenderContext,
+    document_path: Option<&Path>,
+    options: &PdfOptions,
+) -> Result<PdfDocument> {
+    let mut renderer = PdfRenderer {
+        doc: PdfDocument::new("vRain"),
+        fonts: ctx.fonts,
+        font_ids: HashMap::new(),
+        embedded_fonts: HashMap::new(),
+        images: HashMap::new(),
+        image_digests: HashMap::new(),
+        compress: options.compress,
+        outline_map: build_outline_map(plan, ctx),
+        width: Mm(ctx.canvas.canvas_width * PX_TO_MM),
+        height: Mm(ctx.canvas.canvas_height * PX_TO_MM),
+        pages: Vec::with_capacity(plan.pages.len() + 1),
+        page_number: 0,
+        ops: Vec::new(),
     };
+    render_plan(plan, ctx, document_path, &mut renderer)?;
 
-    let mut pages = Vec::with_capacity(plan.pages.len() + 1);
-    let cover_ops = build_cover_ops(
-        plan,
-        ctx,
-        &font_ids,
-        background_image_id.as_ref(),
-        cover_image_id.as_ref(),
-    )?;
-    pages.push(PdfPage::new(width_mm, height_mm, cover_ops));
-
-    for page in &plan.pages {
-        let ops = build_page_ops(
-            page,
-            ctx,
-            &font_ids,
-            background_image_id.as_ref(),
-            stamps.get(&page.number),
-            &mut doc,
-        )?;
-        pages.push(PdfPage::new(width_mm, height_mm, ops));
-        if let Some(outlines) = outline_map.as_ref().and_then(|map| map.get(&page.number)) {
-            let pdf_page_number = pages.len();
-            for title in outlines {
-                doc.add_bookmark(title, pdf_page_number);
-            }
-        }
+    let PdfRenderer { mut doc, pages, .. } = renderer;
+    doc.with_pages(pages);
+    Ok(doc)
+}
+
+/// 经 printpdf 编码的只有非 JPEG 图片，一律无损 Flate；透明通道由 printpdf 拆为 SMask
+fn pdf_save_options() -> PdfSaveOptions {
+    PdfSaveOptions {
+        optimize: true,
+        subset_fonts: true,
+        secure: true,
+        image_optimization: Some(ImageOptimizationOptions {
+            quality: None,
+            // 画布与背景图均为 72dpi，保持原分辨率
+            max_image_size: None,
+            dither_greyscale: Some(false),
+            convert_to_greyscale: Some(false),
+            auto_optimize: Some(false),
+            format: Some(ImageCompression::Flate),
+        }),
     }
+}
 
-    doc.with_pages(pages);
-    let file = File::create(output_path)?;
-    let mut writer = BufWriter::new(file);
-    let mut warnings = Vec::new();
-    doc.save_writer(&mut writer, &PdfSaveOptions::default(), &mut warnings);
-    Ok(())
+/// printpdf 后端：每页累积一组 Op；字体按文件只嵌入一次，图片按来源与内容只注册一次
+struct PdfRenderer<'a> {
+    doc: PdfDocument,
+    fonts: &'a FontManager,
+    font_ids: HashMap<FontFace, Option<FontId>>,
+    embedded_fonts: HashMap<PathBuf, FontId>,
+    images: HashMap<ImageKey, XObjectId>,
+    /// 按内容摘要分桶；摘要相同仍须逐字节比对，确认内容一致才复用
+    image_digests: HashMap<u64, Vec<(SourceImage, XObjectId)>>,
+    compress: bool,
+    outline_map: Option<HashMap<usize, Vec<String>>>,
+    width: Mm,
+    height: Mm,
+    pages: Vec<PdfPage>,
+    page_number: usize,
+    ops: Vec<Op>,
 }
 
-fn prepare_font_ids(doc: &mut PdfDocument, fonts: &FontManager) -> Result<Vec<Option<FontId>>> {
-    let mut font_ids = Vec::with_capacity(fonts.slots.len());
-    for slot in &fonts.slots {
-        if let Some(font) = slot {
-            let mut warnings = Vec::new();
-            let parsed = ParsedFont::from_bytes(&font.data, 0, &mut warnings)
-                .ok_or_else(|| anyhow!("failed to load font '{}'", font.slot.name))?;
-            let font_id = doc.add_font(&parsed);
-            font_ids.push(Some(font_id));
-        } else {
-            font_ids.push(None);
+impl PdfRenderer<'_> {
+    fn font_id(&mut self, face: FontFace) -> Result<Option<FontId>> {
+        if let Some(id) = self.font_ids.get(&face) {
+            return Ok(id.clone());
         }
+        let font = match face {
+            FontFace::Slot(idx) => self.fonts.font(idx),
+            FontFace::Logo => self.fonts.logo.as_ref(),
+        };
+        let id = match font {
+            Some(font) => Some(match self.embedded_fonts.get(&font.path) {
+                Some(id) => id.clone(),
+                None => {
+                    let mut warnings = Vec::new();
+                    let parsed = ParsedFont::from_bytes(&font.data, 0, &mut warnings)
+                        .ok_or_else(|| anyhow!("failed to load font '{}'", font.slot.name))?;
+                    let id = self.doc.add_font(&parsed);
+                    self.embedded_fonts.insert(font.path.clone(), id.clone());
+                    id
+                }
+            }),
+            None => None,
+        };
+        self.font_ids.insert(face, id.clone());
+        Ok(id)
     }
-    Ok(font_ids)
-}
 
-fn build_cover_ops(
-    plan: &DocumentPlan,
-    ctx: &RenderContext,
-    font_ids: &[Option<FontId>],
-    background: Option<&XObjectId>,
-    cover_image: Option<&XObjectId>,
-) -> Result<Vec<Op>> {
-    let mut ops = Vec::new();
-    match (&plan.cover, cover_image) {
-        (CoverPlan::Image, Some(image_id)) => {
-            push_full_page_image(&mut ops, image_id);
+    fn image_id(&mut self, key: &ImageKey, source: &SourceImage) -> Result<XObjectId> {
+        if let Some(id) = self.images.get(key) {
+            return Ok(id.clone());
         }
-        (CoverPlan::Image, None) => {
-            if let Some(path) = &plan.cover_path {
-                eprintln!(
-                    "Cover image requested ({}) but no image loaded; fallback to generated cover",
-                    path.display()
-                );
-            } else {
-                eprintln!("Cover image requested but no image loaded; fallback to generated cover");
+        let digest = image_digest(source);
+        let known = self.image_digests.get(&digest).and_then(|bucket| {
+            bucket
+                .iter()
+                .find(|(seen, _)| same_content(seen, source))
+                .map(|(_, id)| id.clone())
+        });
+        let id = match known {
+            Some(id) => id,
+            None => {
+                let id = self.embed_image(source)?;
+                self.image_digests
+                    .entry(digest)
+                    .or_default()
+                    .push((source.clone(), id.clone()));
+                id
             }
-            add_background_ops(&mut ops, background);
-            draw_simple_cover(ctx, &mut ops, font_ids)?;
+        };
+        self.images.insert(key.clone(), id.clone());
+        Ok(id)
+    }
+
+    fn embed_image(&mut self, source: &SourceImage) -> Result<XObjectId> {
+        let image = &source.image;
+        if !self.compress
+            && let Some(jpeg) = &source.jpeg
+            && let Some((color_space, inverted)) = jpeg_color_space(jpeg)
+        {
+            let xobject = dct_xobject(
+                jpeg.clone(),
+                image.width(),
+                image.height(),
+                color_space,
+                inverted,
+            );
+            return Ok(self.doc.add_xobject(&xobject));
         }
-        _ => {
-            add_background_ops(&mut ops, background);
-            draw_simple_cover(ctx, &mut ops, font_ids)?;
+        // 压缩模式下不透明图片统一转为 JPEG；帧头无法识别的 JPEG 也重新编码
+        if is_opaque(image) && (self.compress || source.jpeg.is_some()) {
+            let quality = if self.compress {
+                JPEG_QUALITY_COMPRESSED
+            } else {
+                JPEG_QUALITY
+            };
+            let mut jpeg = Vec::new();
+            JpegEncoder::new_with_quality(&mut jpeg, quality)
+                .encode_image(&image.to_rgb8())
+                .context("failed to encode jpeg for pdf")?;
+            let xobject = dct_xobject(jpeg, image.width(), image.height(), "DeviceRGB", false);
+            return Ok(self.doc.add_xobject(&xobject));
         }
+        Ok(self.doc.add_image(&raw_image_from_dynamic(image)))
     }
-    Ok(ops)
 }
 
-fn build_page_ops(
-    page: &PagePlan,
-    ctx: &RenderContext,
-    font_ids: &[Option<FontId>],
-    background: Option<&XObjectId>,
-    stamps: Option<&Vec<StampSpec>>,
-    doc: &mut PdfDocument,
-) -> Result<Vec<Op>> {
-    let mut ops = Vec::new();
-    add_background_ops(&mut ops, background);
-    if let Some(stamps) = stamps {
-        for stamp in stamps {
-            add_stamp(&mut ops, stamp, ctx, doc)?;
-        }
-    }
-    draw_page_title(ctx, &mut ops, font_ids, &page.title);
-    draw_page_number(ctx, &mut ops, font_ids, page.number);
-    for line in &page.lines {
-        draw_line(&mut ops, line);
+impl Renderer for PdfRenderer<'_> {
+    fn begin_page(&mut self, number: usize) -> Result<()> {
+        self.page_number = number;
+        self.ops.clear();
+        Ok(())
     }
-    for glyph in &page.glyphs {
-        draw_glyph(&mut ops, font_ids, glyph)?;
+
+    fn draw_image(
+        &mut self,
+        key: &ImageKey,
+        image: &SourceImage,
+        target: ImagePlacement,
+    ) -> Result<()> {
+        let id = self.image_id(key, image)?;
+        push_placed_image(&mut self.ops, id, (image.width(), image.height()), target);
+        Ok(())
     }
-    Ok(ops)
-}
 
-fn add_background_ops(ops: &mut Vec<Op>, image_id: Option<&XObjectId>) {
-    if let Some(id) = image_id {
-        push_full_page_image(ops, id);
+    fn draw_polygon(&mut self, polygon: &PolygonSpec) {
+        draw_polygon(&mut self.ops, polygon);
     }
-}
 
-fn push_full_page_image(ops: &mut Vec<Op>, image_id: &XObjectId) {
-    ops.push(Op::UseXobject {
-        id: image_id.clone(),
-        transform: XObjectTransform {
-            translate_x: Some(Mm(0.0).into()),
-            translate_y: Some(Mm(0.0).into()),
-            dpi: Some(IMAGE_DPI),
-            ..Default::default()
-        },
-    });
-}
+    fn draw_line(&mut self, line: &LineSpec) {
+        draw_line(&mut self.ops, line);
+    }
 
-fn draw_simple_cover(
-    ctx: &RenderContext,
-    ops: &mut Vec<Op>,
-    font_ids: &[Option<FontId>],
-) -> Result<()> {
-    if let Some(font_idx) = ctx.fonts.text_stack.first().copied() {
-        if let Some(font_id) = font_id(font_ids, font_idx) {
-            for (idx, ch) in ctx.book.title.chars().enumerate() {
-                let x = ctx.book.cover.title_font_size;
-                let y = ctx.canvas.canvas_height
-                    - ctx.book.cover.title_y
-                    - idx as f32 * ctx.book.cover.title_font_size * 1.2;
-                push_text_ops(
-                    ops,
-                    &font_id,
-                    &ctx.book.cover.color,
-                    ctx.book.cover.title_font_size,
-                    x,
-                    y,
-                    0.0,
-                    &ch.to_string(),
-                );
-            }
-            for (idx, ch) in ctx.book.author.chars().enumerate() {
-                let x = ctx.book.cover.author_font_size / 2.0;
-                let y = ctx.canvas.canvas_height
-                    - ctx.book.cover.author_y
-                    - idx as f32 * ctx.book.cover.author_font_size * 1.2;
-                push_text_ops(
-                    ops,
-                    &font_id,
-                    &ctx.book.cover.color,
-                    ctx.book.cover.author_font_size,
-                    x,
-                    y,
-                    0.0,
-                    &ch.to_string(),
-                );
-            }
+    fn draw_glyph(&mut self, face: FontFace, glyph: &GlyphSpec) -> Result<()> {
+        if let Some(font_id) = self.font_id(face)? {
+            // 竖排替换字形没有码位，按字形号写入并映射回原字以便复制文本
+            let write = match glyph.glyph_id {
+                Some(gid) => Op::WriteCodepoints {
+                    font: font_id.clone(),
+                    cp: vec![(gid, glyph.ch)],
+                },
+                None => Op::WriteText {
+                    font: font_id.clone(),
+                    items: vec![TextItem::Text(glyph.ch.to_string())],
+                },
+            };
+            push_text_ops(
+                &mut self.ops,
+                &font_id,
+                &glyph.color,
+                glyph.font_size,
+                glyph.x,
+                glyph.y,
+                glyph.rotate_deg,
+                glyph.scale_x,
+                write,
+            );
         }
+        Ok(())
     }
-    Ok(())
-}
 
-fn draw_page_title(
-    ctx: &RenderContext,
-    ops: &mut Vec<Op>,
-    font_ids: &[Option<FontId>],
-    title: &str,
-) {
-    if let Some(font_idx) = ctx.fonts.text_stack.first().copied() {
-        if let Some(font_id) = font_id(font_ids, font_idx) {
-            for (idx, ch) in title.chars().enumerate() {
-                let x = if ctx.book.title_style.center {
-                    ctx.canvas.canvas_width / 2.0 - ctx.book.title_style.font_size / 2.0
-                } else {
-                    0.0
-                };
-                let y = ctx.book.title_style.y
-                    - ctx.book.title_style.font_size * idx as f32 * ctx.book.title_style.y_dis;
-                push_text_ops(
-                    ops,
-                    &font_id,
-                    &ctx.book.title_style.color,
-                    ctx.book.title_style.font_size,
-                    x,
-                    y,
-                    0.0,
-                    &ch.to_string(),
-                );
+    fn end_page(&mut self) -> Result<()> {
+        let ops = std::mem::take(&mut self.ops);
+        self.pages.push(PdfPage::new(self.width, self.height, ops));
+        // 封面不加书签；正文页的目录条目指向刚加入的 PDF 页
+        if self.page_number > 0
+            && let Some(outlines) = self
+                .outline_map
+                .as_ref()
+                .and_then(|map| map.get(&self.page_number))
+        {
+            let pdf_page_number = self.pages.len();
+            for title in outlines {
+                self.doc.add_bookmark(title, pdf_page_number);
             }
         }
+        Ok(())
     }
 }
 
-fn draw_page_number(
-    ctx: &RenderContext,
+fn push_placed_image(
     ops: &mut Vec<Op>,
-    font_ids: &[Option<FontId>],
-    number: usize,
+    id: XObjectId,
+    (source_width, source_height): (u32, u32),
+    target: ImagePlacement,
 ) {
-    if let Some(font_idx) = ctx.fonts.text_stack.first().copied() {
-        if let Some(font_id) = font_id(font_ids, font_idx) {
-            let text = ctx.numerals.render(number);
-            for (idx, ch) in text.chars().enumerate() {
-                let x = ctx.canvas.canvas_width / 2.0 - ctx.book.pager_style.font_size / 2.0;
-                let y = ctx.book.pager_style.y
-                    - ctx.book.pager_style.font_size * idx as f32 * ctx.book.title_style.y_dis;
-                push_text_ops(
-                    ops,
-                    &font_id,
-                    &ctx.book.pager_style.color,
-                    ctx.book.pager_style.font_size,
-                    x,
-                    y,
-                    0.0,
-                    &ch.to_string(),
-                );
-            }
+    // 以 72dpi 嵌入时图片像素与画布单位一一对应；两向分别缩放，纹理可拉伸铺满画布
+    let scale = |target: f32, source: u32| {
+        if source > 0 {
+            target / source as f32
+        } else {
+            1.0
         }
-    }
+    };
+    ops.push(Op::UseXobject {
+        id,
+        transform: XObjectTransform {
+            translate_x: Some(px_to_mm(target.x).into()),
+            translate_y: Some(px_to_mm(target.y).into()),
+            scale_x: Some(scale(target.width, source_width)),
+            scale_y: Some(scale(target.height, source_height)),
+            dpi: Some(IMAGE_DPI),
+            ..Default::default()
+        },
+    });
+}
+
+fn draw_polygon(ops: &mut Vec<Op>, polygon: &PolygonSpec) {
+    let points = polygon
+        .points
+        .iter()
+        .map(|&(x, y)| LinePoint {
+            p: Point::new(px_to_mm(x), px_to_mm(y)),
+            bezier: false,
+        })
+        .collect();
+    ops.push(Op::SetFillColor {
+        col: pdf_color(&polygon.color),
+    });
+    ops.push(Op::DrawPolygon {
+        polygon: Polygon {
+            rings: vec![PolygonRing { points }],
+            mode: PaintMode::Fill,
+            winding_order: WindingOrder::NonZero,
+        },
+    });
 }
 
 fn draw_line(ops: &mut Vec<Op>, line: &LineSpec) {
@@ -288,15 +355,16 @@ fn draw_line(ops: &mut Vec<Op>, line: &LineSpec) {
         draw_wavy_line(ops, line);
         return;
     }
-    let mut points = Vec::with_capacity(2);
-    points.push(LinePoint {
-        p: Point::new(px_to_mm(line.x1), px_to_mm(line.y1)),
-        bezier: false,
-    });
-    points.push(LinePoint {
-        p: Point::new(px_to_mm(line.x2), px_to_mm(line.y2)),
-        bezier: false,
-    });
+    let points = vec![
+        LinePoint {
+            p: Point::new(px_to_mm(line.x1), px_to_mm(line.y1)),
+            bezier: false,
+        },
+        LinePoint {
+            p: Point::new(px_to_mm(line.x2), px_to_mm(line.y2)),
+            bezier: false,
+        },
+    ];
     ops.push(Op::SetOutlineColor {
         col: pdf_color(&line.color),
     });
@@ -312,20 +380,13 @@ fn draw_line(ops: &mut Vec<Op>, line: &LineSpec) {
 }
 
 fn draw_wavy_line(ops: &mut Vec<Op>, line: &LineSpec) {
-    let segments = ((line.y2 - line.y1).abs().max(20.0) / 12.0).ceil() as usize;
-    let amplitude = (line.y2 - line.y1).abs().max(1.0) * 0.05;
-    let wavelength = (line.y2 - line.y1).abs().max(1.0) / (segments as f32);
-    let mut points = Vec::with_capacity(segments + 1);
-    for i in 0..=segments {
-        let t = i as f32 / segments as f32;
-        let y = line.y1 + (line.y2 - line.y1) * t;
-        let wave = amplitude * (2.0 * std::f32::consts::PI * (y - line.y1) / wavelength).sin();
-        let x = line.x1 + wave;
-        points.push(LinePoint {
+    let points = wavy_points(line)
+        .into_iter()
+        .map(|(x, y)| LinePoint {
             p: Point::new(px_to_mm(x), px_to_mm(y)),
             bezier: false,
-        });
-    }
+        })
+        .collect();
     ops.push(Op::SetOutlineColor {
         col: pdf_color(&line.color),
     });
@@ -340,22 +401,7 @@ fn draw_wavy_line(ops: &mut Vec<Op>, line: &LineSpec) {
     });
 }
 
-fn draw_glyph(ops: &mut Vec<Op>, font_ids: &[Option<FontId>], glyph: &GlyphSpec) -> Result<()> {
-    if let Some(font_id) = font_id(font_ids, glyph.font_idx) {
-        push_text_ops(
-            ops,
-            &font_id,
-            &glyph.color,
-            glyph.font_size,
-            glyph.x,
-            glyph.y,
-            glyph.rotate_deg,
-            &glyph.ch.to_string(),
-        );
-    }
-    Ok(())
-}
-
+#[allow(clippy::too_many_arguments)]
 fn push_text_ops(
     ops: &mut Vec<Op>,
     font_id: &FontId,
@@ -364,7 +410,8 @@ fn push_text_ops(
     x: f32,
     y: f32,
     rotate_deg: f32,
-    text: &str,
+    scale_x: f32,
+    write: Op,
 ) {
     ops.push(Op::StartTextSection);
     ops.push(Op::SetFillColor {
@@ -383,18 +430,18 @@ fn push_text_ops(
             pos: Point::new(px_to_mm(x), px_to_mm(y)),
         });
     }
-    ops.push(Op::WriteText {
-        font: font_id.clone(),
-        items: vec![TextItem::Text(text.to_string())],
-    });
-    ops.push(Op::EndTextSection);
-}
-
-fn font_id(fonts: &[Option<FontId>], idx: usize) -> Option<FontId> {
-    if idx == 0 {
-        return None;
+    // 横向缩放属于文字状态，不随文字段结束而复原，用后须恢复
+    let stretched = (scale_x - 1.0).abs() > f32::EPSILON;
+    if stretched {
+        ops.push(Op::SetHorizontalScaling {
+            percent: scale_x * 100.0,
+        });
+    }
+    ops.push(write);
+    if stretched {
+        ops.push(Op::SetHorizontalScaling { percent: 100.0 });
     }
-    fonts.get(idx - 1).and_then(|id| id.clone())
+    ops.push(Op::EndTextSection);
 }
 
 fn px_to_mm(value: f32) -> Mm {
@@ -410,141 +457,117 @@ fn pdf_color(color: &RgbColor) -> Color {
     Color::Rgb(Rgb::new(color.r, color.g, color.b, None))
 }
 
-fn register_image(doc: &mut PdfDocument, image: &DynamicImage) -> Result<XObjectId> {
-    let raw = raw_image_from_dynamic(image);
-    Ok(doc.add_image(&raw))
-}
-
+/// 不透明图片按 RGB8 交给 printpdf；带透明度的保留 RGBA8，保存时拆出 SMask
 fn raw_image_from_dynamic(image: &DynamicImage) -> RawImage {
-    let rgba = image.to_rgba8();
-    let width = rgba.width() as usize;
-    let height = rgba.height() as usize;
-    let pixels = rgba.into_raw();
+    let (pixels, data_format) = if is_opaque(image) {
+        (image.to_rgb8().into_raw(), RawImageFormat::RGB8)
+    } else {
+        (image.to_rgba8().into_raw(), RawImageFormat::RGBA8)
+    };
     RawImage {
         pixels: RawImageData::U8(pixels),
-        width,
-        height,
-        data_format: RawImageFormat::RGBA8,
+        width: image.width() as usize,
+        height: image.height() as usize,
+        data_format,
         tag: Vec::new(),
     }
 }
 
-#[derive(Debug, Clone)]
-struct StampSpec {
-    page: usize,
-    col_begin: usize,
-    row_begin: usize,
-    cols: usize,
-    path: PathBuf,
+fn is_opaque(image: &DynamicImage) -> bool {
+    !image.color().has_alpha() || image.to_rgba8().pixels().all(|p| p.0[3] == 255)
 }
 
-fn load_stamps(_ctx: &RenderContext, output_path: &Path) -> Result<HashMap<usize, Vec<StampSpec>>> {
-    let stamps_cfg = output_path
-        .parent()
-        .map(|p| p.join("yins.cfg"))
-        .unwrap_or_else(|| PathBuf::from("yins.cfg"));
-    if !stamps_cfg.exists() {
-        return Ok(HashMap::new());
-    }
-    let content = std::fs::read_to_string(&stamps_cfg)
-        .with_context(|| format!("读取印章配置失败: {}", stamps_cfg.display()))?;
-    let stem = output_path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or_default()
-        .to_string();
-    let mut map: HashMap<usize, Vec<StampSpec>> = HashMap::new();
-    for (lineno, line) in content.lines().enumerate() {
-        let trimmed = line.trim();
-        if trimmed.is_empty() || trimmed.starts_with('#') {
-            continue;
-        }
-        let mut parts = trimmed.split('|');
-        let pdf_name = parts.next().unwrap_or("").trim();
-        let pos = parts.next().unwrap_or("").trim();
-        let file = parts.next().unwrap_or("").trim();
-        if pdf_name != stem && pdf_name != "*" {
-            continue;
+fn image_digest(source: &SourceImage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    match &source.jpeg {
+        Some(jpeg) => jpeg.hash(&mut hasher),
+        None => {
+            let image = &source.image;
+            (image.width(), image.height(), image.color()).hash(&mut hasher);
+            image.as_bytes().hash(&mut hasher);
         }
-        let parsed = pos
-            .split(',')
-            .filter_map(|x| x.trim().parse::<usize>().ok())
-            .collect::<Vec<_>>();
-        if parsed.len() != 4 || file.is_empty() {
-            eprintln!(
-                "忽略 yins.cfg 第 {} 行：格式错误 (需要 pdf|page,col,row,cols|file)",
-                lineno + 1
-            );
-            continue;
+    }
+    hasher.finish()
+}
+
+fn same_content(a: &SourceImage, b: &SourceImage) -> bool {
+    match (&a.jpeg, &b.jpeg) {
+        (Some(a), Some(b)) => a == b,
+        (None, None) => {
+            let (a, b) = (&a.image, &b.image);
+            (a.width(), a.height(), a.color()) == (b.width(), b.height(), b.color())
+                && a.as_bytes() == b.as_bytes()
         }
-        let spec = StampSpec {
-            page: parsed[0],
-            col_begin: parsed[1],
-            row_begin: parsed[2],
-            cols: parsed[3],
-            path: stamps_cfg
-                .parent()
-                .unwrap_or_else(|| Path::new("."))
-                .join("yins")
-                .join(file),
-        };
-        map.entry(spec.page).or_default().push(spec);
+        _ => false,
     }
-    Ok(map)
 }
 
-fn add_stamp(
-    ops: &mut Vec<Op>,
-    stamp: &StampSpec,
-    ctx: &RenderContext,
-    doc: &mut PdfDocument,
-) -> Result<()> {
-    if !stamp.path.exists() {
-        eprintln!("印章文件不存在，跳过: {}", stamp.path.display());
-        return Ok(());
+/// 以 DCTDecode 滤镜直接嵌入 JPEG 数据的图片 XObject；`inverted` 为 Adobe 反相存储的 CMYK
+fn dct_xobject(
+    jpeg: Vec<u8>,
+    width: u32,
+    height: u32,
+    color_space: &str,
+    inverted: bool,
+) -> ExternalXObject {
+    let name = |value: &str| DictItem::Name(value.as_bytes().to_vec());
+    let mut dict = BTreeMap::from([
+        ("Type".to_string(), name("XObject")),
+        ("Subtype".to_string(), name("Image")),
+        ("Width".to_string(), DictItem::Int(width as i64)),
+        ("Height".to_string(), DictItem::Int(height as i64)),
+        ("ColorSpace".to_string(), name(color_space)),
+        ("BitsPerComponent".to_string(), DictItem::Int(8)),
+        ("Filter".to_string(), name("DCTDecode")),
+    ]);
+    if inverted {
+        let decode = [1, 0, 1, 0, 1, 0, 1, 0].map(DictItem::Int).to_vec();
+        dict.insert("Decode".to_string(), DictItem::Array(decode));
     }
-    let image = image::open(&stamp.path)
-        .with_context(|| format!("读取印章图片失败: {}", stamp.path.display()))?;
-    let raw = raw_image_from_dynamic(&image);
-    let id = doc.add_image(&raw);
-
-    let cw = (ctx.canvas.canvas_width
-        - ctx.canvas.margins_left
-        - ctx.canvas.margins_right
-        - ctx.canvas.leaf_center_width)
-        / ctx.canvas.leaf_col as f32;
-    let rh = (ctx.canvas.canvas_height - ctx.canvas.margins_top - ctx.canvas.margins_bottom)
-        / ctx.book.row_num as f32;
-
-    let mut x = ctx.canvas.canvas_width - ctx.canvas.margins_right - cw * stamp.col_begin as f32;
-    if stamp.col_begin > ctx.canvas.leaf_col / 2 {
-        x -= ctx.canvas.leaf_center_width;
+    ExternalXObject {
+        stream: ExternalStream {
+            dict,
+            content: jpeg,
+            compress: false,
+        },
+        width: Some(Px(width as usize)),
+        height: Some(Px(height as usize)),
+        dpi: Some(IMAGE_DPI),
     }
-    let y = ctx.canvas.margins_bottom + rh * (stamp.row_begin.saturating_sub(1)) as f32;
-    let target_w = cw * stamp.cols as f32;
-
-    let source_w_pt = Px(raw.width).into_pt(IMAGE_DPI).0;
-    let source_h_pt = Px(raw.height).into_pt(IMAGE_DPI).0;
-    let target_w_pt = px_to_pt(target_w).0;
-    let scale = if source_w_pt > 0.0 {
-        target_w_pt / source_w_pt
-    } else {
-        1.0
-    };
-    let scale_y = if source_h_pt > 0.0 { scale } else { 1.0 };
+}
 
-    ops.push(Op::UseXobject {
-        id,
-        transform: XObjectTransform {
-            translate_x: Some(px_to_mm(x).into()),
-            translate_y: Some(px_to_mm(y).into()),
-            scale_x: Some(scale),
-            scale_y: Some(scale_y),
-            dpi: Some(IMAGE_DPI),
-            ..Default::default()
-        },
-    });
-    Ok(())
+/// 按帧头（SOFn）分量数确定 JPEG 的色彩空间；带 Adobe APP14 标记的 CMYK 以反相存储，
+/// 第二项为真时需以 Decode 数组翻转
+fn jpeg_color_space(data: &[u8]) -> Option<(&'static str, bool)> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut adobe = false;
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        if marker == 0xEE && data.get(pos + 4..pos + 9) == Some(b"Adobe".as_slice()) {
+            adobe = true;
+        }
+        if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            return match data.get(pos + 9)? {
+                1 => Some(("DeviceGray", false)),
+                3 => Some(("DeviceRGB", false)),
+                4 => Some(("DeviceCMYK", adobe)),
+                _ => None,
+            };
+        }
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        pos += 2 + len;
+    }
+    None
 }
 
 fn build_outline_map(
@@ -566,73 +589,60 @@ fn build_outline_map(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::{BookConfig, CanvasConfig};
-    use crate::fonts::FontManager;
-    use crate::numerals::NumeralMap;
-    use crate::plan::{CoverPlan, DocumentPlan, GlyphSpec, OutlineEntry, PagePlan};
-    use std::path::Path;
+    use crate::fixtures::{Sample, single_page};
+    use crate::plan::OutlineEntry;
 
     #[test]
     fn render_document_produces_pdf_with_bookmark() {
-        let book = BookConfig::load("books/01/book.cfg").expect("load sample book configuration");
-        let canvas_path = format!("canvas/{}.cfg", book.canvas_id);
-        let canvas =
-            CanvasConfig::load(&canvas_path).expect("load canvas configuration referenced by book");
-        let fonts =
-            FontManager::new(&book, Path::new("fonts")).expect("load fonts for renderer tests");
-        let numerals =
-            NumeralMap::load("db/num2zh_jid.txt").expect("load numeral mapping for tests");
-
-        let sample_font_idx = book
-            .fonts
-            .text_stack
-            .first()
-            .copied()
-            .expect("book config must define a text font stack");
-
-        let glyph = GlyphSpec {
-            ch: '測',
-            font_idx: sample_font_idx,
-            font_size: 48.0,
-            x: 100.0,
-            y: 100.0,
-            rotate_deg: 0.0,
-            color: book.text_font_color,
-        };
+        let sample = Sample::load(None);
+        let font_idx = sample.text_font();
+        let color = sample.book.text_font_color;
+        let glyph = GlyphSpec::new('測', font_idx, 48.0, 100.0, 100.0, color);
+        let mut plan = single_page(1, vec![glyph], Vec::new());
+        plan.outlines.push(OutlineEntry {
+            title: "卷一".into(),
+            page_number: 1,
+        });
 
-        let page = PagePlan {
-            number: 1,
-            title: "测试页面".into(),
-            glyphs: vec![glyph],
-            lines: Vec::new(),
-        };
-        let plan = DocumentPlan {
-            cover: CoverPlan::Generated,
-            cover_path: None,
-            pages: vec![page],
-            outlines: vec![OutlineEntry {
-                title: "卷一".into(),
-                page_number: 1,
-            }],
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, 80)
+            .encode_image(&DynamicImage::new_rgb8(16, 16).to_rgb8())
+            .expect("encode sample background");
+        let background = SourceImage {
+            image: DynamicImage::new_rgb8(16, 16),
+            jpeg: Some(jpeg.clone()),
         };
 
         let ctx = RenderContext {
-            book: &book,
-            canvas: &canvas,
-            fonts: &fonts,
-            numerals: &numerals,
-            background: Some(DynamicImage::new_rgba8(16, 16)),
-            cover_image: None,
+            background: Some(&background),
+            ..sample.context()
         };
 
         let output_path = std::env::temp_dir().join("vrain_renderer_smoke.pdf");
-        render_document(&plan, &ctx, &output_path).expect("render minimal pdf");
+        render_document(&plan, &ctx, &output_path, &PdfOptions::default())
+            .expect("render minimal pdf");
 
         let bytes = std::fs::read(&output_path).expect("read rendered pdf");
         assert!(
-            bytes.len() > 0,
+            !bytes.is_empty(),
             "rendered pdf must contain at least some bytes"
         );
+        let font = sample
+            .fonts
+            .font(font_idx)
+            .expect("sample font slot loaded");
+        assert!(
+            bytes.len() < font.data.len(),
+            "embedded font should be subset to the glyphs used",
+        );
+        let embedded = bytes
+            .windows(jpeg.len())
+            .filter(|window| *window == jpeg.as_slice())
+            .count();
+        assert_eq!(
+            embedded, 1,
+            "background jpeg should be embedded verbatim once for cover and page",
+        );
 
         let mut warnings = Vec::new();
         let parsed = printpdf::PdfDocument::parse(
@@ -647,4 +657,143 @@ mod tests {
         );
         let _ = std::fs::remove_file(output_path);
     }
+
+    #[test]
+    fn images_are_shared_only_when_their_bytes_match() {
+        let sample = Sample::load(None);
+        let mut renderer = PdfRenderer {
+            doc: PdfDocument::new("vRain"),
+            fonts: &sample.fonts,
+            font_ids: HashMap::new(),
+            embedded_fonts: HashMap::new(),
+            images: HashMap::new(),
+            image_digests: HashMap::new(),
+            compress: false,
+            outline_map: None,
+            width: Mm(100.0),
+            height: Mm(100.0),
+            pages: Vec::new(),
+            page_number: 0,
+            ops: Vec::new(),
+        };
+        let source = |value: u8| SourceImage {
+            image: DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+                4,
+                4,
+                image::Rgb([value; 3]),
+            )),
+            jpeg: None,
+        };
+        let stamp = |name: &str| ImageKey::Stamp(name.into());
+
+        let first = renderer
+            .image_id(&stamp("a.png"), &source(10))
+            .expect("embed first image");
+        let same = renderer
+            .image_id(&stamp("b.png"), &source(10))
+            .expect("embed identical image");
+        assert_eq!(first, same, "identical content is embedded once");
+
+        // 伪造摘要碰撞：桶中已有内容不同的图片时不得复用其 XObject
+        let other = source(20);
+        renderer
+            .image_digests
+            .insert(image_digest(&other), vec![(source(30), first.clone())]);
+        let collided = renderer
+            .image_id(&stamp("c.png"), &other)
+            .expect("embed colliding image");
+        assert_ne!(
+            collided, first,
+            "a digest match alone must not share an image"
+        );
+    }
+
+    /// 以给定背景渲染一页空白 PDF，返回文件字节
+    fn render_with_background(background: &SourceImage) -> Vec<u8> {
+        let sample = Sample::load(None);
+        let plan = single_page(1, Vec::new(), Vec::new());
+        let ctx = RenderContext {
+            background: Some(background),
+            draw_frame: false,
+            ..sample.context()
+        };
+        render_document_bytes(&plan, &ctx, &PdfOptions::default()).expect("render pdf bytes")
+    }
+
+    /// 只含 SOI、可选 Adobe APP14 与帧头的 JPEG 片段，足以检验原样嵌入
+    fn jpeg_stub(sof: u8, components: u8, adobe: bool) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8];
+        if adobe {
+            data.extend_from_slice(&[0xFF, 0xEE, 0x00, 0x0E]);
+            data.extend_from_slice(b"Adobe");
+            data.extend_from_slice(&[0x00, 0x64, 0x00, 0x00, 0x00, 0x00, 0x02]);
+        }
+        let len = 8 + 3 * components as u16;
+        data.extend_from_slice(&[0xFF, sof]);
+        data.extend_from_slice(&len.to_be_bytes());
+        data.extend_from_slice(&[8, 0, 4, 0, 4, components]);
+        for id in 1..=components {
+            data.extend_from_slice(&[id, 0x11, 0]);
+        }
+        data.extend_from_slice(&[0xFF, 0xD9]);
+        data
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    #[test]
+    fn jpeg_backgrounds_pass_through_as_dct_streams() {
+        let mut gray = Vec::new();
+        JpegEncoder::new_with_quality(&mut gray, 80)
+            .encode_image(&image::GrayImage::from_pixel(4, 4, image::Luma([90])))
+            .expect("encode grayscale jpeg");
+        let cases = [
+            (gray, "/DeviceGray", false),
+            (jpeg_stub(0xC0, 4, true), "/DeviceCMYK", true),
+            (jpeg_stub(0xC0, 4, false), "/DeviceCMYK", false),
+            (jpeg_stub(0xC2, 3, false), "/DeviceRGB", false),
+        ];
+        for (jpeg, color_space, inverted) in cases {
+            let background = SourceImage {
+                image: DynamicImage::new_rgb8(4, 4),
+                jpeg: Some(jpeg.clone()),
+            };
+            let bytes = render_with_background(&background);
+            assert!(
+                contains(&bytes, &jpeg),
+                "{color_space} jpeg embedded verbatim"
+            );
+            assert!(contains(&bytes, b"/DCTDecode"));
+            assert!(contains(&bytes, color_space.as_bytes()));
+            assert_eq!(
+                contains(&bytes, b"/Decode"),
+                inverted,
+                "only Adobe CMYK is inverted",
+            );
+        }
+    }
+
+    #[test]
+    fn png_backgrounds_get_an_smask_only_when_translucent() {
+        let png = |alpha: u8| SourceImage {
+            image: DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                4,
+                4,
+                image::Rgba([200, 100, 50, alpha]),
+            )),
+            jpeg: None,
+        };
+        let opaque = render_with_background(&png(255));
+        assert!(!contains(&opaque, b"/SMask"), "opaque png has no soft mask");
+        assert!(!contains(&opaque, b"/DCTDecode"), "png stays lossless");
+        let translucent = render_with_background(&png(128));
+        assert!(
+            contains(&translucent, b"/SMask"),
+            "alpha is split into an SMask"
+        );
+    }
 }
@@ -0,0 +1,434 @@
+use crate::backend::{
+    FontFace, ImageKey, ImagePlacement, RenderContext, Renderer, SourceImage, render_plan,
+    wavy_points,
+};
+use crate::color::RgbColor;
+use crate::fonts::{FontManager, LoadedFont, subset_font};
+use crate::plan::{DocumentPlan, GlyphSpec, LineSpec, PolygonSpec};
+use anyhow::{Context, Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use image::ImageFormat;
+use image::codecs::jpeg::JpegEncoder;
+use printpdf::ParsedFont;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use ttf_parser::{Face, GlyphId, OutlineBuilder};
+
+const JPEG_QUALITY: u8 = 90;
+const LOGO_FAMILY: &str = "vrain-logo";
+
+#[derive(Debug, Clone)]
+pub struct SvgOptions {
+    pub output_dir: PathBuf,
+}
+
+/// 将 DocumentPlan 逐页写成独立的 SVG，封面为第 000 页。
+///
+/// 每页内嵌该页所用字形的字体子集与 base64 图片，可直接在浏览器打开。
+/// `document_path` 为对应的 PDF 路径，用于确定文件名前缀与同目录下的 yins.cfg。
+pub fn render_svg(
+    plan: &DocumentPlan,
+    ctx: &RenderContext,
+    document_path: &Path,
+    options: &SvgOptions,
+) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(&options.output_dir).with_context(|| {
+        format!(
+            "failed to create svg output dir {}",
+            options.output_dir.display()
+        )
+    })?;
+    let mut renderer = SvgRenderer {
+        fonts: ctx.fonts,
+        options,
+        stem: document_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("page")
+            .to_string(),
+        width: ctx.canvas.canvas_width,
+        height: ctx.canvas.canvas_height,
+        page: None,
+        parsed_fonts: FontCache::default(),
+        images: HashMap::new(),
+        written: Vec::with_capacity(plan.pages.len() + 1),
+    };
+    render_plan(plan, ctx, Some(document_path), &mut renderer)?;
+    Ok(renderer.written)
+}
+
+/// SVG 后端：图片按来源编码一次后在各页复用
+struct SvgRenderer<'a> {
+    fonts: &'a FontManager,
+    options: &'a SvgOptions,
+    stem: String,
+    width: f32,
+    height: f32,
+    page: Option<(usize, SvgPage<'a>)>,
+    parsed_fonts: FontCache,
+    images: HashMap<ImageKey, String>,
+    written: Vec<PathBuf>,
+}
+
+impl<'a> Renderer for SvgRenderer<'a> {
+    fn begin_page(&mut self, number: usize) -> Result<()> {
+        self.page = Some((number, SvgPage::new(self.width, self.height)));
+        Ok(())
+    }
+
+    fn draw_image(
+        &mut self,
+        key: &ImageKey,
+        image: &SourceImage,
+        target: ImagePlacement,
+    ) -> Result<()> {
+        if !self.images.contains_key(key) {
+            self.images.insert(key.clone(), encode_image(image)?);
+        }
+        if let Some((_, page)) = self.page.as_mut() {
+            page.image(&self.images[key], target);
+        }
+        Ok(())
+    }
+
+    fn draw_polygon(&mut self, polygon: &PolygonSpec) {
+        if let Some((_, page)) = self.page.as_mut() {
+            page.polygon(polygon);
+        }
+    }
+
+    fn draw_line(&mut self, line: &LineSpec) {
+        if let Some((_, page)) = self.page.as_mut() {
+            page.line(line);
+        }
+    }
+
+    fn draw_glyph(&mut self, face: FontFace, glyph: &GlyphSpec) -> Result<()> {
+        let fonts: &'a FontManager = self.fonts;
+        let (family, font) = match face {
+            FontFace::Slot(idx) => (format!("vrain-{idx}"), fonts.font(idx)),
+            FontFace::Logo => (LOGO_FAMILY.to_string(), fonts.logo.as_ref()),
+        };
+        if let (Some(font), Some((_, page))) = (font, self.page.as_mut()) {
+            page.glyph(&family, font, glyph);
+        }
+        Ok(())
+    }
+
+    fn end_page(&mut self) -> Result<()> {
+        let (number, page) = self
+            .page
+            .take()
+            .ok_or_else(|| anyhow!("end_page without begin_page"))?;
+        let path = self
+            .options
+            .output_dir
+            .join(format!("{}_{number:03}.svg", self.stem));
+        let content = page.finish(&mut self.parsed_fonts)?;
+        std::fs::write(&path, content)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        self.written.push(path);
+        Ok(())
+    }
+}
+
+/// 单页 SVG：正文按绘制顺序累积，同时记录每个字体用到的字符以便子集化
+struct SvgPage<'a> {
+    width: f32,
+    height: f32,
+    body: String,
+    faces: BTreeMap<String, (&'a LoadedFont, BTreeSet<char>)>,
+}
+
+impl<'a> SvgPage<'a> {
+    fn new(width: f32, height: f32) -> Self {
+        Self {
+            width,
+            height,
+            body: String::new(),
+            faces: BTreeMap::new(),
+        }
+    }
+
+    /// 以基线原点绘制单字；PDF 的逆时针角度在 SVG（y 轴向下）中取反
+    fn glyph(&mut self, family: &str, font: &'a LoadedFont, glyph: &GlyphSpec) {
+        if glyph.glyph_id.is_some() || (glyph.scale_x - 1.0).abs() > f32::EPSILON {
+            self.glyph_outline(font, font.glyph_index(glyph), glyph);
+            return;
+        }
+        self.faces
+            .entry(family.to_string())
+            .or_insert_with(|| (font, BTreeSet::new()))
+            .1
+            .insert(glyph.ch);
+        let (x, y) = (glyph.x, self.height - glyph.y);
+        let _ = write!(
+            self.body,
+            r#"<text x="{}" y="{}" font-family="{family}, serif" font-size="{}" fill="{}""#,
+            num(x),
+            num(y),
+            num(glyph.font_size),
+            hex(&glyph.color),
+        );
+        if glyph.rotate_deg.abs() > f32::EPSILON {
+            let _ = write!(
+                self.body,
+                r#" transform="rotate({} {} {})""#,
+                num(-glyph.rotate_deg),
+                num(x),
+                num(y)
+            );
+        }
+        let _ = writeln!(self.body, ">{}</text>", escape(glyph.ch));
+    }
+
+    /// 竖排替换字形没有码位，无法以 <text> 引用，改为按字形轮廓绘制路径；
+    /// 横向缩放的部件也以路径绘制
+    fn glyph_outline(&mut self, font: &LoadedFont, gid: u16, glyph: &GlyphSpec) {
+        let Ok(face) = Face::parse(&font.data, 0) else {
+            return;
+        };
+        let mut path = SvgPath::default();
+        if face.outline_glyph(GlyphId(gid), &mut path).is_none() {
+            return;
+        }
+        let scale = glyph.font_size / face.units_per_em() as f32;
+        let (x, y) = (glyph.x, self.height - glyph.y);
+        let _ = write!(
+            self.body,
+            r#"<path d="{}" fill="{}" transform="translate({} {})"#,
+            path.0.trim_end(),
+            hex(&glyph.color),
+            num(x),
+            num(y),
+        );
+        if glyph.rotate_deg.abs() > f32::EPSILON {
+            let _ = write!(self.body, " rotate({})", num(-glyph.rotate_deg));
+        }
+        let _ = writeln!(
+            self.body,
+            r#" scale({} {})"/>"#,
+            scale * glyph.scale_x,
+            -scale
+        );
+    }
+
+    fn line(&mut self, line: &LineSpec) {
+        let points = if line.wavy {
+            wavy_points(line)
+        } else {
+            vec![(line.x1, line.y1), (line.x2, line.y2)]
+        };
+        let _ = writeln!(
+            self.body,
+            r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="{}"/>"#,
+            self.points(&points),
+            hex(&line.color),
+            num(line.width),
+        );
+    }
+
+    fn polygon(&mut self, polygon: &PolygonSpec) {
+        let _ = writeln!(
+            self.body,
+            r#"<polygon points="{}" fill="{}"/>"#,
+            self.points(&polygon.points),
+            hex(&polygon.color),
+        );
+    }
+
+    fn image(&mut self, href: &str, target: ImagePlacement) {
+        let _ = writeln!(
+            self.body,
+            r#"<image x="{}" y="{}" width="{}" height="{}" preserveAspectRatio="none" href="{href}"/>"#,
+            num(target.x),
+            num(self.height - target.y - target.height),
+            num(target.width),
+            num(target.height),
+        );
+    }
+
+    fn points(&self, points: &[(f32, f32)]) -> String {
+        points
+            .iter()
+            .map(|&(x, y)| format!("{},{}", num(x), num(self.height - y)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn finish(self, fonts: &mut FontCache) -> Result<String> {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+            w = num(self.width),
+            h = num(self.height),
+        );
+        if !self.faces.is_empty() {
+            out.push_str("<style>\n");
+            for (family, (font, chars)) in &self.faces {
+                let Some(subset) = subset_font(fonts.parsed(family, font)?, chars)? else {
+                    continue;
+                };
+                let _ = writeln!(
+                    out,
+                    r#"@font-face {{ font-family: "{family}"; src: url(data:font/ttf;base64,{}) format("truetype"); }}"#,
+                    BASE64.encode(subset),
+                );
+            }
+            out.push_str("</style>\n");
+        }
+        out.push_str(&self.body);
+        out.push_str("</svg>\n");
+        Ok(out)
+    }
+}
+
+/// 字形轮廓的 SVG 路径数据，坐标为字体单位（y 轴向上）
+#[derive(Default)]
+struct SvgPath(String);
+
+impl OutlineBuilder for SvgPath {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let _ = write!(self.0, "M{x} {y} ");
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let _ = write!(self.0, "L{x} {y} ");
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let _ = write!(self.0, "Q{x1} {y1} {x} {y} ");
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let _ = write!(self.0, "C{x1} {y1} {x2} {y2} {x} {y} ");
+    }
+
+    fn close(&mut self) {
+        self.0.push_str("Z ");
+    }
+}
+
+/// 每个字体只解析一次，各页按需子集化
+#[derive(Default)]
+struct FontCache {
+    parsed: HashMap<String, ParsedFont>,
+}
+
+impl FontCache {
+    fn parsed(&mut self, family: &str, font: &LoadedFont) -> Result<&ParsedFont> {
+        if !self.parsed.contains_key(family) {
+            self.parsed.insert(family.to_string(), font.parse()?);
+        }
+        Ok(&self.parsed[family])
+    }
+}
+
+/// 含透明通道的图片存为 PNG，其余存为 JPEG 以控制体积
+fn encode_image(source: &SourceImage) -> Result<String> {
+    if let Some(jpeg) = &source.jpeg {
+        return Ok(format!("data:image/jpeg;base64,{}", BASE64.encode(jpeg)));
+    }
+    let image = &source.image;
+    let mut bytes = Vec::new();
+    if image.color().has_alpha() {
+        image
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .context("failed to encode png for svg")?;
+        Ok(format!("data:image/png;base64,{}", BASE64.encode(bytes)))
+    } else {
+        JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY)
+            .encode_image(&image.to_rgb8())
+            .context("failed to encode jpeg for svg")?;
+        Ok(format!("data:image/jpeg;base64,{}", BASE64.encode(bytes)))
+    }
+}
+
+fn num(value: f32) -> String {
+    let rounded = (value * 100.0).round() / 100.0;
+    if rounded == 0.0 {
+        "0".to_string()
+    } else {
+        rounded.to_string()
+    }
+}
+
+fn hex(color: &RgbColor) -> String {
+    let channel = |value: f32| (value * 255.0).round().clamp(0.0, 255.0) as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(color.r),
+        channel(color.g),
+        channel(color.b)
+    )
+}
+
+fn escape(ch: char) -> String {
+    match ch {
+        '&' => "&amp;".to_string(),
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{Sample, single_page};
+
+    #[test]
+    fn render_svg_embeds_subset_font_rotated_glyphs_and_glyph_outlines() {
+        let sample = Sample::load(None);
+        let canvas = &sample.canvas;
+        let font_idx = sample.text_font();
+        // 以 A 的字形号模拟竖排替换字形
+        let vertical_gid = sample
+            .fonts
+            .font(font_idx)
+            .expect("text font loaded")
+            .font
+            .lookup_glyph_index('A');
+        let black = RgbColor::new_u8(0, 0, 0);
+        let glyphs = vec![
+            GlyphSpec {
+                rotate_deg: -90.0,
+                ..GlyphSpec::new('<', font_idx, 48.0, 100.0, 100.0, black)
+            },
+            GlyphSpec {
+                glyph_id: Some(vertical_gid),
+                ..GlyphSpec::new('A', font_idx, 48.0, 200.0, 100.0, black)
+            },
+        ];
+        let plan = single_page(1, glyphs, Vec::new());
+        let ctx = sample.context();
+
+        let output_dir = std::env::temp_dir().join("vrain_svg_smoke");
+        let options = SvgOptions {
+            output_dir: output_dir.clone(),
+        };
+        let written = render_svg(&plan, &ctx, &output_dir.join("smoke.pdf"), &options)
+            .expect("render svg pages");
+        assert_eq!(written.len(), 2, "expected cover plus one page");
+
+        let svg = std::fs::read_to_string(&written[1]).expect("read rendered svg");
+        assert!(svg.contains("@font-face"), "fonts must be embedded");
+        assert!(svg.contains(&format!(
+            r#"transform="rotate(90 100 {})">&lt;</text>"#,
+            num(canvas.canvas_height - 100.0)
+        )));
+        assert!(
+            svg.contains(r#"<path d="M"#),
+            "glyph ids are drawn as outlines"
+        );
+        assert!(svg.contains(&format!(
+            "translate(200 {})",
+            num(canvas.canvas_height - 100.0)
+        )));
+        let _ = std::fs::remove_dir_all(output_dir);
+    }
+}
@@ -1,13 +1,17 @@
 #![allow(dead_code)]
 
-use crate::config::BookConfig;
+use crate::config::{BookConfig, LayoutMode};
+use crate::flow_engine::{FlowCursor, FlowEngine, FlowPage};
 use crate::fonts::FontManager;
+use crate::glyph_images::GlyphImages;
+use crate::horizontal_engine::HorizontalEngine;
 use crate::layout::Layout;
-use crate::layout_engine::LayoutEngine;
+use crate::layout_engine::{LayoutEngine, NameFrameState};
 use crate::numerals::NumeralMap;
-use crate::plan::{CoverPlan, DocumentPlan, OutlineEntry, PagePlan, TypesetOptions};
+use crate::plan::{CoverPlan, DocumentPlan, MissingGlyph, OutlineEntry, PagePlan, TypesetOptions};
 use crate::preprocess::TextCorpus;
 use anyhow::Result;
+use std::collections::BTreeMap;
 use std::mem;
 
 pub struct Typesetter<'a> {
@@ -16,6 +20,7 @@ pub struct Typesetter<'a> {
     fonts: &'a FontManager,
     numerals: &'a NumeralMap,
     corpus: &'a TextCorpus,
+    glyph_images: &'a GlyphImages,
     options: TypesetOptions,
 }
 
@@ -26,6 +31,7 @@ impl<'a> Typesetter<'a> {
         fonts: &'a FontManager,
         numerals: &'a NumeralMap,
         corpus: &'a TextCorpus,
+        glyph_images: &'a GlyphImages,
         options: TypesetOptions,
     ) -> Result<Self> {
         Ok(Self {
@@ -34,6 +40,7 @@ impl<'a> Typesetter<'a> {
             fonts,
             numerals,
             corpus,
+            glyph_images,
             options,
         })
     }
@@ -46,6 +53,7 @@ impl<'a> Typesetter<'a> {
 
         let mut pages: Vec<PagePlan> = Vec::new();
         let mut outlines: Vec<OutlineEntry> = Vec::new();
+        let mut missing: BTreeMap<String, MissingGlyph> = BTreeMap::new();
         let mut current_page = PagePlan {
             number: 1,
             title: String::new(),
@@ -56,12 +64,24 @@ impl<'a> Typesetter<'a> {
         let mut next_page_number = 1usize;
         let mut generated_pages = 0usize;
         let mut bookline_active = false;
+        let mut name_frame = NameFrameState::default();
+        let mut flow_cursor = FlowCursor::default();
 
         let engine = LayoutEngine {
             book: self.book,
             layout: self.layout,
             fonts: self.fonts,
             options: &self.options,
+            clusters: &self.corpus.clusters,
+            glyph_images: self.glyph_images,
+        };
+        let flow = match self.book.layout_mode {
+            LayoutMode::Proportional => Some(FlowEngine::new(&engine)?),
+            LayoutMode::Grid | LayoutMode::Horizontal => None,
+        };
+        let horizontal = match self.book.layout_mode {
+            LayoutMode::Horizontal => Some(HorizontalEngine::new(&engine)?),
+            LayoutMode::Grid | LayoutMode::Proportional => None,
         };
 
         for idx in self.options.from..=self.options.to {
@@ -85,25 +105,57 @@ impl<'a> Typesetter<'a> {
                 }
                 next_page_number += 1;
                 pcnt = 0;
+                flow_cursor = FlowCursor::default();
             } else {
                 current_page.title = title_text.clone();
             }
 
+            let first_page = pages.len();
             outlines.push(OutlineEntry {
                 title: title_text.clone(),
                 page_number: next_page_number,
             });
 
-            engine.process_entry(
-                &entry.data,
-                &title_text,
-                &mut current_page,
-                &mut pages,
-                &mut pcnt,
-                &mut generated_pages,
-                &mut next_page_number,
-                &mut bookline_active,
-            )?;
+            if flow.is_some() || horizontal.is_some() {
+                let mut page = FlowPage {
+                    current_page: &mut current_page,
+                    pages: &mut pages,
+                    generated_pages: &mut generated_pages,
+                    next_page_number: &mut next_page_number,
+                    title_text: &title_text,
+                };
+                if let Some(flow) = &flow {
+                    flow.process_entry(
+                        &entry.data,
+                        &mut page,
+                        &mut flow_cursor,
+                        &mut bookline_active,
+                    )?;
+                } else if let Some(horizontal) = &horizontal {
+                    horizontal.process_entry(
+                        &entry.data,
+                        &mut page,
+                        &mut flow_cursor,
+                        &mut bookline_active,
+                    )?;
+                }
+            } else {
+                engine.process_entry(
+                    &entry.data,
+                    &title_text,
+                    &mut current_page,
+                    &mut pages,
+                    &mut pcnt,
+                    &mut generated_pages,
+                    &mut next_page_number,
+                    &mut bookline_active,
+                    &mut name_frame,
+                )?;
+            }
+
+            for page in pages[first_page..].iter().chain([&current_page]) {
+                collect_missing(&mut missing, page, &entry.name);
+            }
 
             if self.reached_limit(generated_pages) {
                 break;
@@ -119,6 +171,7 @@ impl<'a> Typesetter<'a> {
             cover_path,
             pages,
             outlines,
+            missing: missing.into_values().collect(),
         })
     }
 
@@ -149,3 +202,26 @@ impl<'a> Typesetter<'a> {
             .unwrap_or(false)
     }
 }
+
+/// 把页面中以 □ 代替的字计入缺字报告
+fn collect_missing(missing: &mut BTreeMap<String, MissingGlyph>, page: &PagePlan, chapter: &str) {
+    for text in page
+        .glyphs
+        .iter()
+        .filter_map(|glyph| glyph.missing.as_ref())
+    {
+        let report = missing.entry(text.clone()).or_insert_with(|| MissingGlyph {
+            text: text.clone(),
+            count: 0,
+            chapters: Vec::new(),
+            pages: Vec::new(),
+        });
+        report.count += 1;
+        if report.chapters.last().map(String::as_str) != Some(chapter) {
+            report.chapters.push(chapter.to_string());
+        }
+        if report.pages.last() != Some(&page.number) {
+            report.pages.push(page.number);
+        }
+    }
+}